@@ -6,11 +6,16 @@ namespace unpacksharp
 {
 	class MainClass
 	{
+		private const int V8_OK = 0;
+
 		[DllImport("libv8unpack4rs.so", CharSet = CharSet.Ansi)]
-		private static extern bool parse_cf(
+		private static extern int v8_parse(
 			string fileName,
 			string dirName);
 
+		[DllImport("libv8unpack4rs.so")]
+		private static extern IntPtr v8_last_error_message();
+
 		public static void Main(string[] args)
 		{
 			if (args.Length < 3)
@@ -25,8 +30,14 @@ namespace unpacksharp
 				string fileName = args[1];
 				string dirName = args[2];
 				
-				var resultParse = parse_cf(fileName, dirName);
-				Console.WriteLine("parse_cf: {0}", resultParse);
+				var status = v8_parse(fileName, dirName);
+				if (status != V8_OK)
+				{
+					var message = Marshal.PtrToStringAnsi(v8_last_error_message());
+					Console.WriteLine("v8_parse: error {0}: {1}", status, message);
+					Environment.Exit(status);
+				}
+				Console.WriteLine("v8_parse: ok");
 				
 			}
 			else
@@ -0,0 +1,339 @@
+//! Python bindings of `v8unpack4rs`, the module is imported as `v8unpack`.
+//!
+//! Errors of the library are raised as subclasses of `v8unpack.V8Error`
+//! by their category, the same as the exit codes of the command line
+//! utility and the status codes of the C interface.
+
+use std::fs;
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+use v8unpack4rs::builder::{self, BuildOptions};
+use v8unpack4rs::error::V8Error as Error;
+use v8unpack4rs::parser::{self, UnpackOptions};
+use v8unpack4rs::sink::UnpackReport;
+use v8unpack4rs::view::ContainerView;
+
+create_exception!(
+    v8unpack,
+    V8Error,
+    PyException,
+    "Errors of reading and writing containers."
+);
+create_exception!(
+    v8unpack,
+    IoError,
+    V8Error,
+    "Reading or writing a file failed."
+);
+create_exception!(
+    v8unpack,
+    NotV8FileError,
+    V8Error,
+    "The input is not a 1C v8 container."
+);
+create_exception!(
+    v8unpack,
+    BadFormatError,
+    V8Error,
+    "The container is damaged."
+);
+create_exception!(
+    v8unpack,
+    NotFoundError,
+    V8Error,
+    "The container has no such element."
+);
+create_exception!(
+    v8unpack,
+    LimitExceededError,
+    V8Error,
+    "The container exceeds the limits of the 32-bit format."
+);
+create_exception!(
+    v8unpack,
+    CancelledError,
+    V8Error,
+    "The operation was cancelled."
+);
+
+fn to_py_err(e: Error) -> PyErr {
+    let message = e.to_string();
+    match e {
+        Error::IoError(_) => IoError::new_err(message),
+        Error::NotV8File { .. } => NotV8FileError::new_err(message),
+        Error::LimitExceeded { .. } => LimitExceededError::new_err(message),
+        Error::Cancelled => CancelledError::new_err(message),
+        _ => BadFormatError::new_err(message),
+    }
+}
+
+fn not_found(elem_path: &str) -> PyErr {
+    NotFoundError::new_err(format!("The container has no element {}", elem_path))
+}
+
+fn report_to_dict<'py>(
+    py: Python<'py>,
+    report: &UnpackReport,
+) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("added", report.added)?;
+    dict.set_item("changed", report.changed)?;
+    dict.set_item("unchanged", report.unchanged)?;
+    dict.set_item("removed", report.removed)?;
+
+    Ok(dict)
+}
+
+/// Description of an element of a container, see `list`.
+#[pyclass(frozen, get_all, module = "v8unpack")]
+#[derive(Debug, Clone)]
+struct ElemInfo {
+    /// Path of the element, like `Form/form`.
+    path: String,
+    /// Nesting level, `0` for the elements of the root container.
+    depth: usize,
+    /// The offset of the header block.
+    header_addr: u32,
+    /// The offset of the data block, `None` for an element without data.
+    data_addr: Option<u32>,
+    /// Size of the data as stored in the container.
+    raw_size: u64,
+    /// Size of the data after inflating.
+    inflated_size: u64,
+    /// The data is deflated.
+    compressed: bool,
+    /// The data is a nested container.
+    v8file: bool,
+    /// Creation time in 1C ticks.
+    date_creation: u64,
+    /// Modification time in 1C ticks.
+    date_modification: u64,
+}
+
+#[pymethods]
+impl ElemInfo {
+    fn __repr__(&self) -> String {
+        format!(
+            "ElemInfo(path={:?}, inflated_size={}, v8file={})",
+            self.path,
+            self.inflated_size,
+            if self.v8file { "True" } else { "False" }
+        )
+    }
+}
+
+impl From<parser::ElemInfo> for ElemInfo {
+    fn from(other: parser::ElemInfo) -> ElemInfo {
+        ElemInfo {
+            path: other.path,
+            depth: other.depth,
+            header_addr: other.header_addr,
+            data_addr: other.data_addr,
+            raw_size: other.raw_size,
+            inflated_size: other.inflated_size,
+            compressed: other.compressed,
+            v8file: other.v8file,
+            date_creation: other.date_creation,
+            date_modification: other.date_modification,
+        }
+    }
+}
+
+fn list_data(data: &[u8], max_depth: Option<usize>) -> PyResult<Vec<ElemInfo>> {
+    let elems = parser::list_elems(data, max_depth).map_err(to_py_err)?;
+
+    Ok(elems.into_iter().map(ElemInfo::from).collect())
+}
+
+/// A container read into memory by `open` or `Container.from_bytes`.
+#[pyclass(frozen, module = "v8unpack")]
+#[derive(Debug)]
+struct Container {
+    data: Vec<u8>,
+    len: usize,
+}
+
+impl Container {
+    fn new(data: Vec<u8>) -> PyResult<Container> {
+        let len = ContainerView::new(&data).map_err(to_py_err)?.len();
+
+        Ok(Container { data, len })
+    }
+}
+
+#[pymethods]
+impl Container {
+    /// Opens the container held in `data`.
+    #[staticmethod]
+    fn from_bytes(data: &[u8]) -> PyResult<Container> {
+        Container::new(data.to_vec())
+    }
+
+    /// Returns the bytes of the container.
+    fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.data)
+    }
+
+    /// Lists the elements up to `max_depth` levels of nested containers,
+    /// all of them by default.
+    #[pyo3(signature = (max_depth=None))]
+    fn list(&self, max_depth: Option<usize>) -> PyResult<Vec<ElemInfo>> {
+        list_data(&self.data, max_depth)
+    }
+
+    /// Returns the inflated data of the element `elem_path`, like
+    /// `Form/form`.
+    fn extract<'py>(
+        &self,
+        py: Python<'py>,
+        elem_path: &str,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        match parser::find_elem(&self.data, elem_path).map_err(to_py_err)? {
+            Some(data) => Ok(PyBytes::new(py, &data)),
+            None => Err(not_found(elem_path)),
+        }
+    }
+
+    /// Unpacks the container into the directory `dir_name`, inflating the
+    /// elements, and returns the counts of the touched files.
+    fn unpack<'py>(
+        &self,
+        py: Python<'py>,
+        dir_name: &str,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let report = py
+            .allow_threads(|| {
+                parser::unpack_bytes_to_directory(
+                    &self.data,
+                    dir_name,
+                    &UnpackOptions::new(),
+                )
+            })
+            .map_err(to_py_err)?;
+
+        report_to_dict(py, &report)
+    }
+
+    /// Number of the elements of the root container.
+    fn __len__(&self) -> usize {
+        self.len
+    }
+
+    fn __contains__(&self, elem_path: &str) -> PyResult<bool> {
+        let found = parser::find_elem(&self.data, elem_path).map_err(to_py_err)?;
+
+        Ok(found.is_some())
+    }
+}
+
+/// Reads the container `file_name` into memory.
+#[pyfunction]
+fn open(file_name: &str) -> PyResult<Container> {
+    Container::new(fs::read(file_name).map_err(|e| to_py_err(e.into()))?)
+}
+
+/// Lists the elements of the container `file_name` up to `max_depth` levels
+/// of nested containers, all of them by default.
+#[pyfunction]
+#[pyo3(signature = (file_name, max_depth=None))]
+fn list(file_name: &str, max_depth: Option<usize>) -> PyResult<Vec<ElemInfo>> {
+    let elems = parser::list_file(file_name, max_depth).map_err(to_py_err)?;
+
+    Ok(elems.into_iter().map(ElemInfo::from).collect())
+}
+
+/// Returns the inflated data of the element `elem_path` of the container
+/// `file_name`, or writes it to `out_path` and returns `None`. A nested
+/// container is unpacked into the directory `out_path`.
+#[pyfunction]
+#[pyo3(signature = (file_name, elem_path, out_path=None))]
+fn extract<'py>(
+    py: Python<'py>,
+    file_name: &str,
+    elem_path: &str,
+    out_path: Option<&str>,
+) -> PyResult<Option<Bound<'py, PyBytes>>> {
+    match out_path {
+        Some(out_path) => {
+            let found = py
+                .allow_threads(|| {
+                    parser::extract_to_path(file_name, elem_path, out_path)
+                })
+                .map_err(to_py_err)?;
+            if !found {
+                return Err(not_found(elem_path));
+            }
+
+            Ok(None)
+        }
+        None => open(file_name)?.extract(py, elem_path).map(Some),
+    }
+}
+
+/// Unpacks the container `file_name` into the directory `dir_name`,
+/// inflating the elements, like the `parse` command, and returns the counts
+/// of the touched files.
+#[pyfunction]
+fn unpack<'py>(
+    py: Python<'py>,
+    file_name: &str,
+    dir_name: &str,
+) -> PyResult<Bound<'py, PyDict>> {
+    let report = py
+        .allow_threads(|| {
+            parser::parse_to_folder_with_options(
+                file_name,
+                dir_name,
+                &UnpackOptions::new(),
+            )
+        })
+        .map_err(to_py_err)?;
+
+    report_to_dict(py, &report)
+}
+
+/// Builds the container `file_name` from the directory `dir_name` made by
+/// `unpack`, like the `build` command. The elements are stored without
+/// compression when `no_deflate` is set.
+#[pyfunction]
+#[pyo3(signature = (dir_name, file_name, no_deflate=false))]
+fn build(
+    py: Python<'_>,
+    dir_name: &str,
+    file_name: &str,
+    no_deflate: bool,
+) -> PyResult<()> {
+    let options = BuildOptions::new().with_no_deflate(no_deflate);
+    py.allow_threads(|| {
+        builder::build_cf_file_with_options(dir_name, file_name, &options)
+    })
+    .map_err(to_py_err)?;
+
+    Ok(())
+}
+
+#[pymodule]
+fn v8unpack(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("V8Error", py.get_type::<V8Error>())?;
+    m.add("IoError", py.get_type::<IoError>())?;
+    m.add("NotV8FileError", py.get_type::<NotV8FileError>())?;
+    m.add("BadFormatError", py.get_type::<BadFormatError>())?;
+    m.add("NotFoundError", py.get_type::<NotFoundError>())?;
+    m.add("LimitExceededError", py.get_type::<LimitExceededError>())?;
+    m.add("CancelledError", py.get_type::<CancelledError>())?;
+
+    m.add_class::<Container>()?;
+    m.add_class::<ElemInfo>()?;
+    m.add_function(wrap_pyfunction!(open, m)?)?;
+    m.add_function(wrap_pyfunction!(list, m)?)?;
+    m.add_function(wrap_pyfunction!(extract, m)?)?;
+    m.add_function(wrap_pyfunction!(unpack, m)?)?;
+    m.add_function(wrap_pyfunction!(build, m)?)?;
+
+    Ok(())
+}
@@ -0,0 +1,137 @@
+"""Tests of the Python bindings against the container of the library tests.
+
+Run them after building the module, see README.md:
+
+    python -m unittest discover -s tests
+"""
+
+import os
+import shutil
+import tempfile
+import unittest
+
+import v8unpack
+
+TEST_FILE1 = os.path.join(
+    os.path.dirname(os.path.abspath(__file__)),
+    "..",
+    "..",
+    "v8unpack4rs",
+    "tests",
+    "test1.cf",
+)
+
+
+class ContainerTest(unittest.TestCase):
+    def setUp(self):
+        self.container = v8unpack.open(TEST_FILE1)
+
+    def test_list(self):
+        elems = self.container.list()
+        self.assertEqual(len(elems), 24)
+        self.assertEqual(len(self.container.list(0)), len(self.container))
+        self.assertEqual(len(self.container), 14)
+        self.assertEqual(
+            [elem.path for elem in elems],
+            [elem.path for elem in v8unpack.list(TEST_FILE1)],
+        )
+        nested = [elem for elem in elems if elem.v8file]
+        self.assertTrue(nested)
+        self.assertTrue(all(elem.depth == 0 for elem in nested))
+
+    def test_extract(self):
+        version = self.container.extract("version")
+        self.assertIsInstance(version, bytes)
+        self.assertEqual(len(version), self.elem("version").inflated_size)
+        self.assertEqual(v8unpack.extract(TEST_FILE1, "version"), version)
+        self.assertIn("version", self.container)
+        self.assertNotIn("missing", self.container)
+
+        nested = next(elem for elem in self.container.list() if elem.depth == 1)
+        self.assertEqual(
+            len(self.container.extract(nested.path)), nested.inflated_size
+        )
+
+    def test_from_bytes(self):
+        with open(TEST_FILE1, "rb") as f:
+            data = f.read()
+        container = v8unpack.Container.from_bytes(data)
+        self.assertEqual(container.to_bytes(), data)
+        self.assertEqual(len(container.list()), 24)
+
+    def elem(self, path):
+        return next(elem for elem in self.container.list() if elem.path == path)
+
+
+class FilesTest(unittest.TestCase):
+    def setUp(self):
+        self.dir = tempfile.mkdtemp()
+
+    def tearDown(self):
+        shutil.rmtree(self.dir)
+
+    def path(self, name):
+        return os.path.join(self.dir, name)
+
+    def test_unpack_and_build(self):
+        files = sum(not elem.v8file for elem in v8unpack.list(TEST_FILE1))
+        report = v8unpack.unpack(TEST_FILE1, self.path("unpack"))
+        self.assertEqual(report["added"], files)
+        self.assertEqual(report["removed"], 0)
+        self.assertTrue(os.path.isfile(self.path("unpack/version")))
+
+        v8unpack.build(self.path("unpack"), self.path("build.cf"))
+        built = v8unpack.open(self.path("build.cf"))
+        self.assertEqual(len(built.list()), 24)
+        self.assertEqual(
+            built.extract("version"), v8unpack.extract(TEST_FILE1, "version")
+        )
+
+        report = v8unpack.open(TEST_FILE1).unpack(self.path("memory"))
+        self.assertEqual(report["added"], files)
+
+    def test_extract_to_path(self):
+        out_path = self.path("version")
+        self.assertIsNone(v8unpack.extract(TEST_FILE1, "version", out_path))
+        with open(out_path, "rb") as f:
+            self.assertEqual(f.read(), v8unpack.extract(TEST_FILE1, "version"))
+
+
+class ErrorsTest(unittest.TestCase):
+    def test_categories(self):
+        for error in (
+            v8unpack.IoError,
+            v8unpack.NotV8FileError,
+            v8unpack.BadFormatError,
+            v8unpack.NotFoundError,
+            v8unpack.LimitExceededError,
+            v8unpack.CancelledError,
+        ):
+            self.assertTrue(issubclass(error, v8unpack.V8Error))
+        self.assertTrue(issubclass(v8unpack.V8Error, Exception))
+
+    def test_not_found(self):
+        with self.assertRaises(v8unpack.NotFoundError):
+            v8unpack.open(TEST_FILE1).extract("missing")
+        with self.assertRaises(v8unpack.NotFoundError):
+            v8unpack.extract(TEST_FILE1, "missing", os.devnull)
+
+    def test_io_error(self):
+        with self.assertRaises(v8unpack.IoError):
+            v8unpack.open(TEST_FILE1 + ".missing")
+
+    def test_not_v8file(self):
+        with self.assertRaises(v8unpack.NotV8FileError):
+            v8unpack.Container.from_bytes(b"not a container")
+
+    def test_bad_format(self):
+        with open(TEST_FILE1, "rb") as f:
+            data = bytearray(f.read())
+        # The table of contents points past the end of the container.
+        data[16 + 31 : 16 + 35] = b"\xff\xff\xff\x00"
+        with self.assertRaises(v8unpack.BadFormatError):
+            v8unpack.Container.from_bytes(bytes(data))
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,392 @@
+//! Definition of the command line interface.
+
+use clap::{crate_authors, crate_version, App, AppSettings, Arg, SubCommand};
+use std::ffi::OsString;
+
+/// Flags of the versions before subcommands, mapped to the subcommands.
+const LEGACY_COMMANDS: &[(&str, &str)] = &[
+    ("-p", "parse"),
+    ("--parse", "parse"),
+    ("-u", "unpack"),
+    ("--unpack", "unpack"),
+    ("--pack", "pack"),
+    ("-b", "build"),
+    ("--build", "build"),
+];
+
+/// Rewrites the old form of the command line, like `v8unpack -p FILE DIR`,
+/// into the subcommand form `v8unpack parse FILE DIR`.
+pub fn legacy_args(mut args: Vec<OsString>) -> Vec<OsString> {
+    let position = args.iter().skip(1).position(|arg| {
+        let arg = arg.to_str().unwrap_or_default();
+        LEGACY_COMMANDS.iter().any(|&(flag, _)| flag == arg)
+    });
+
+    if let Some(position) = position {
+        let flag = args.remove(position + 1);
+        let command = LEGACY_COMMANDS
+            .iter()
+            .find(|&&(legacy, _)| legacy == flag.to_str().unwrap_or_default())
+            .map_or("", |&(_, command)| command);
+        args.insert(1, OsString::from(command));
+    }
+
+    args
+}
+
+fn single_threaded_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("single-threaded")
+        .short("s")
+        .long("single-threaded")
+        .help("Do all the work on a single thread")
+}
+
+fn input_file_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("INPUTFILE")
+        .required(true)
+        .help("The container, `-` reads it from the standard input")
+}
+
+fn include_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("include")
+        .long("include")
+        .help("Process only the elements matching the pattern, like '*/text'")
+        .takes_value(true)
+        .value_name("PATTERN")
+        .multiple(true)
+        .number_of_values(1)
+}
+
+fn exclude_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("exclude")
+        .long("exclude")
+        .help("Skip the elements matching the pattern, like '*.0/*'")
+        .takes_value(true)
+        .value_name("PATTERN")
+        .multiple(true)
+        .number_of_values(1)
+}
+
+fn progress_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("progress")
+        .long("progress")
+        .help("Show a progress bar on stderr")
+}
+
+fn size_validator(value: String) -> Result<(), String> {
+    parse_size(&value).map(|_| ())
+}
+
+fn threads_validator(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(threads) if threads > 0 => Ok(()),
+        _ => Err(format!("Bad number of threads {:?}", value)),
+    }
+}
+
+fn depth_validator(value: String) -> Result<(), String> {
+    value
+        .parse::<usize>()
+        .map(|_| ())
+        .map_err(|_| format!("Bad depth {:?}", value))
+}
+
+/// Parses a size like `512M`, with an optional `K`, `M` or `G` suffix.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (digits, multiplier) = match value.chars().last().map(|c| c.to_ascii_uppercase())
+    {
+        Some('K') => (&value[..value.len() - 1], 1 << 10),
+        Some('M') => (&value[..value.len() - 1], 1 << 20),
+        Some('G') => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("Bad size value {:?}, expected e.g. 512M", value))
+}
+
+pub fn build_app() -> App<'static, 'static> {
+    App::new("v8unpack")
+        .version(crate_version!())
+        .author(crate_authors!())
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .setting(AppSettings::VersionlessSubcommands)
+        .about(
+            "\n\t2008 Denis Demidov 2008-03-30\n\t2017 Alexander Andreev\n\
+             Unpack, pack, deflate and inflate 1C v8 file (*.cf)",
+        )
+        .arg(
+            Arg::with_name("verbosity")
+                .short("v")
+                .long("verbosity")
+                .help("Logging verbosity level: error, warn, info, debug or trace")
+                .takes_value(true)
+                .value_name("LOG_LEVEL")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .help("Print the result as text or as a JSON report")
+                .takes_value(true)
+                .value_name("FORMAT")
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .global(true),
+        )
+        .subcommand(
+            SubCommand::with_name("parse")
+                .about("Unpack the container into a directory, inflating the elements")
+                .arg(input_file_arg())
+                .arg(Arg::with_name("OUTDIR").required(true).help(
+                    "The directory, or an archive with the .tar or .zip extension",
+                ))
+                .arg(single_threaded_arg())
+                .arg(
+                    Arg::with_name("incremental")
+                        .long("incremental")
+                        .help("Rewrite only the files which content has changed"),
+                )
+                .arg(
+                    Arg::with_name("remove-stale")
+                        .long("remove-stale")
+                        .requires("incremental")
+                        .help("Delete files written by an earlier parse which are no longer in the container"),
+                )
+                .arg(
+                    Arg::with_name("max-memory")
+                        .long("max-memory")
+                        .conflicts_with("single-threaded")
+                        .help("Limit the memory used for element data, e.g. 512M")
+                        .takes_value(true)
+                        .value_name("SIZE")
+                        .validator(size_validator),
+                )
+                .arg(include_arg())
+                .arg(exclude_arg())
+                .arg(progress_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("unpack")
+                .about("Unpack the binary headers and data of the elements")
+                .arg(input_file_arg())
+                .arg(Arg::with_name("OUTDIR").required(true))
+                .arg(single_threaded_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("pack")
+                .about("Package the binaries made by `unpack` into a container")
+                .arg(Arg::with_name("INPUTDIR").required(true))
+                .arg(Arg::with_name("OUTFILE").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("build")
+                .about("Build a container from the source files made by `parse`")
+                .arg(Arg::with_name("INPUTDIR").required(true).help(
+                    "The directory, or an archive with the .tar or .zip extension",
+                ))
+                .arg(
+                    Arg::with_name("OUTFILE")
+                        .required(true)
+                        .help("The container, `-` writes it to the standard output"),
+                )
+                .arg(
+                    Arg::with_name("no-deflate")
+                        .long("no-deflate")
+                        .help("Store the elements without compression"),
+                )
+                // `nopack` after the paths of the old `--build` flag.
+                .arg(Arg::with_name("nopack").hidden(true).index(3))
+                .arg(
+                    Arg::with_name("threads")
+                        .short("j")
+                        .long("threads")
+                        .help("Number of threads compressing the elements")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(threads_validator),
+                )
+                .arg(
+                    Arg::with_name("cache-dir")
+                        .long("cache-dir")
+                        .help(
+                            "Reuse compressed blocks of unchanged sources from the \
+                             directory",
+                        )
+                        .takes_value(true)
+                        .value_name("CACHEDIR"),
+                )
+                .arg(include_arg())
+                .arg(exclude_arg())
+                .arg(progress_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("batch")
+                .about("Parse or build many containers into a mirrored directory tree")
+                .arg(
+                    Arg::with_name("COMMAND")
+                        .required(true)
+                        .possible_values(&["parse", "build"]),
+                )
+                .arg(Arg::with_name("INPUT").required(true).help(
+                    "A directory, a quoted pattern like 'in/**/*.epf' or @FILE with \
+                     one path per line",
+                ))
+                .arg(Arg::with_name("OUTDIR").required(true))
+                .arg(
+                    Arg::with_name("jobs")
+                        .short("j")
+                        .long("jobs")
+                        .help("Number of containers processed at once")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(threads_validator),
+                )
+                .arg(
+                    Arg::with_name("no-deflate")
+                        .long("no-deflate")
+                        .help("Build the containers without compression"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("List the elements of the container")
+                .arg(input_file_arg())
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Print the elements as a JSON array"),
+                )
+                .arg(
+                    Arg::with_name("recursive")
+                        .short("r")
+                        .long("recursive")
+                        .help("List the elements of all nested containers"),
+                )
+                .arg(
+                    Arg::with_name("depth")
+                        .long("depth")
+                        .conflicts_with("recursive")
+                        .help("List the elements of nested containers up to N levels")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(depth_validator),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Check that all the elements of the container can be read")
+                .arg(input_file_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("info")
+                .about("Print the headers of the container and the platform version")
+                .arg(input_file_arg())
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Print the information as a JSON object"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("extract")
+                .about("Extract a single element, like `Form/form`, of the container")
+                .arg(input_file_arg())
+                .arg(Arg::with_name("ELEMENT").required(true))
+                .arg(Arg::with_name("OUTPATH").required(true).help(
+                    "The file, or the directory for a nested container, `-` \
+                             prints the element to the standard output",
+                )),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_legacy_args() {
+        let cases: &[(&[&str], &[&str])] = &[
+            (
+                &["v8unpack", "-p", "a.cf", "dir"],
+                &["v8unpack", "parse", "a.cf", "dir"],
+            ),
+            (
+                &["v8unpack", "--unpack", "a.cf", "dir"],
+                &["v8unpack", "unpack", "a.cf", "dir"],
+            ),
+            (
+                &["v8unpack", "-u", "a.cf", "dir"],
+                &["v8unpack", "unpack", "a.cf", "dir"],
+            ),
+            (
+                &["v8unpack", "--pack", "dir", "a.cf"],
+                &["v8unpack", "pack", "dir", "a.cf"],
+            ),
+            (
+                &["v8unpack", "-b", "dir", "a.cf"],
+                &["v8unpack", "build", "dir", "a.cf"],
+            ),
+            (
+                &["v8unpack", "-v", "debug", "--parse", "a.cf", "dir"],
+                &["v8unpack", "parse", "-v", "debug", "a.cf", "dir"],
+            ),
+            // The subcommand form is left as is.
+            (
+                &["v8unpack", "parse", "a.cf", "dir"],
+                &["v8unpack", "parse", "a.cf", "dir"],
+            ),
+        ];
+
+        for &(legacy, expected) in cases {
+            assert_eq!(legacy_args(args(legacy)), args(expected), "{:?}", legacy);
+        }
+    }
+
+    #[test]
+    fn test_legacy_args_match_subcommands() {
+        let matches = build_app()
+            .get_matches_from_safe(legacy_args(args(&[
+                "v8unpack", "--build", "dir", "a.cf", "nopack",
+            ])))
+            .unwrap();
+        let (command, build) = matches.subcommand();
+        assert_eq!(command, "build");
+        let build = build.unwrap();
+        assert_eq!(build.value_of("INPUTDIR"), Some("dir"));
+        assert_eq!(build.value_of("OUTFILE"), Some("a.cf"));
+        assert!(build.is_present("nopack"));
+
+        let matches = build_app()
+            .get_matches_from_safe(legacy_args(args(&[
+                "v8unpack", "--pack", "dir", "a.cf",
+            ])))
+            .unwrap();
+        let (command, pack) = matches.subcommand();
+        assert_eq!(command, "pack");
+        assert_eq!(pack.unwrap().value_of("INPUTDIR"), Some("dir"));
+        assert_eq!(pack.unwrap().value_of("OUTFILE"), Some("a.cf"));
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("1k"), Ok(1 << 10));
+        assert_eq!(parse_size("1K"), Ok(1 << 10));
+        assert_eq!(parse_size("512M"), Ok(512 << 20));
+        assert_eq!(parse_size("2g"), Ok(2 << 30));
+        assert_eq!(parse_size(" 3K "), Ok(3 << 10));
+
+        for bad in &["", "M", "abc", "-1", "1.5M", "1T", "18446744073709551615G"] {
+            assert!(parse_size(bad).is_err(), "{:?}", bad);
+        }
+    }
+}
@@ -1,12 +1,176 @@
 extern crate v8unpack4rs;
 
-use clap::{crate_authors, crate_version, App, Arg};
-use std::io;
-use v8unpack4rs::{builder, parser};
+mod app;
+mod progress;
+mod report;
 
-fn setup_logging(log_level: Option<&str>) -> Result<(), fern::InitError> {
-    let mut basic_config = fern::Dispatch::new();
+use report::Report;
+use std::io::prelude::*;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+use std::{env, fmt, fs, io, process};
+use v8unpack4rs::archive::{ArchiveFormat, ArchiveSource, TarSink, ZipSink};
+use v8unpack4rs::batch::{self, BatchCommand, BatchOptions};
+use v8unpack4rs::builder::cache::BlockCache;
+use v8unpack4rs::error::V8Error;
+use v8unpack4rs::filter::ElemFilter;
+use v8unpack4rs::progress::Progress;
+use v8unpack4rs::sink::{OutputSink, UnpackReport};
+use v8unpack4rs::source::{DirSource, InputSource};
+use v8unpack4rs::{builder, container, parser};
 
+use chrono::TimeZone;
+use serde_json::json;
+
+/// Exit code for the wrong command line arguments.
+const EXIT_USAGE: i32 = 2;
+/// Exit code for the errors of reading and writing files.
+const EXIT_IO: i32 = 3;
+/// Exit code for the input which is not a correct container.
+const EXIT_BAD_FORMAT: i32 = 4;
+/// Exit code for the element which is not in the container.
+const EXIT_NOT_FOUND: i32 = 5;
+/// Exit code for the other errors.
+const EXIT_FAILURE: i32 = 1;
+
+/// The name of the standard input or output in place of a file name.
+const STDIO: &str = "-";
+
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    NotV8File(String),
+    NotFound(String),
+    BatchFailed { failed: usize, total: usize },
+    Logging(fern::InitError),
+    V8(V8Error),
+}
+
+impl CliError {
+    fn exit_code(&self) -> i32 {
+        match *self {
+            CliError::Usage(_) => EXIT_USAGE,
+            CliError::NotV8File(_) => EXIT_BAD_FORMAT,
+            CliError::NotFound(_) => EXIT_NOT_FOUND,
+            CliError::BatchFailed { .. } => EXIT_FAILURE,
+            CliError::Logging(_) => EXIT_FAILURE,
+            CliError::V8(V8Error::IoError(_)) => EXIT_IO,
+            CliError::V8(V8Error::LimitExceeded { .. }) => EXIT_FAILURE,
+            CliError::V8(_) => EXIT_BAD_FORMAT,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CliError::Usage(ref message) => write!(f, "{}", message),
+            CliError::NotV8File(ref file_name) => {
+                write!(f, "{} is not a 1C v8 container", file_name)
+            }
+            CliError::NotFound(ref elem_path) => {
+                write!(f, "The container has no element {}", elem_path)
+            }
+            CliError::BatchFailed { failed, total } => {
+                write!(f, "{} of {} containers failed", failed, total)
+            }
+            CliError::Logging(ref e) => write!(f, "Failed to initialize logging: {}", e),
+            CliError::V8(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<V8Error> for CliError {
+    fn from(other: V8Error) -> CliError {
+        CliError::V8(other)
+    }
+}
+
+impl From<fern::InitError> for CliError {
+    fn from(other: fern::InitError) -> CliError {
+        CliError::Logging(other)
+    }
+}
+
+/// The input container of a command. The standard input given as `-` is
+/// spooled to a temporary file, which is removed when dropped.
+struct Input {
+    name: String,
+    spooled: Option<parser::SpooledInput>,
+}
+
+impl Input {
+    fn open(args: &clap::ArgMatches) -> Result<Input, CliError> {
+        let name = args.value_of("INPUTFILE").unwrap_or_default().to_string();
+        let spooled = if name == STDIO {
+            Some(parser::SpooledInput::from_reader(&mut io::stdin().lock())?)
+        } else {
+            None
+        };
+
+        Ok(Input { name, spooled })
+    }
+
+    /// The name of the file to read.
+    fn path(&self) -> String {
+        match self.spooled {
+            Some(ref spooled) => spooled.file_name(),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// Checks whether the command writes its result to the standard output.
+fn writes_stdout(args: &clap::ArgMatches) -> bool {
+    args.value_of("OUTFILE") == Some(STDIO) || args.value_of("OUTPATH") == Some(STDIO)
+}
+
+/// Writes to the standard output with `write`. A reader which closed the
+/// pipe early, like `head`, is not an error.
+fn print_stdout<F>(write: F) -> Result<(), CliError>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match write(&mut out).and_then(|_| out.flush()) {
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result.map_err(V8Error::from)?),
+    }
+}
+
+/// Turns `false` returned by the library for a wrong input into an error.
+fn ensure_v8file(parsed: bool, file_name: &str) -> Result<(), CliError> {
+    if parsed {
+        Ok(())
+    } else {
+        Err(CliError::NotV8File(file_name.to_string()))
+    }
+}
+
+fn format_record(
+    out: fern::FormatCallback,
+    message: &fmt::Arguments,
+    record: &log::Record,
+) {
+    out.finish(format_args!(
+        "[{}][{}][{}] {}",
+        chrono::Local::now().format("%H:%M:%S"),
+        record.target(),
+        record.level(),
+        message
+    ))
+}
+
+/// Sets up printing of the log. With the JSON report the log goes to stderr
+/// and the warnings are collected into the report. With `to_stderr` the log
+/// goes to stderr, leaving stdout for the result of the command.
+fn setup_logging(
+    log_level: Option<&str>,
+    json_report: bool,
+    to_stderr: bool,
+) -> Result<(), CliError> {
     let level = match log_level {
         None => log::LevelFilter::Info,
         Some(v) => match v {
@@ -15,143 +179,633 @@ fn setup_logging(log_level: Option<&str>) -> Result<(), fern::InitError> {
             "warn" => log::LevelFilter::Warn,
             "trace" => log::LevelFilter::Trace,
             "error" => log::LevelFilter::Error,
-            _ => panic!(
-                "Bad value of the logging level. True variants: debug, info, \
-                 trace, warn, error."
-            ),
+            _ => {
+                return Err(CliError::Usage(
+                    "Bad value of the logging level. True variants: debug, info, \
+                     trace, warn, error."
+                        .to_string(),
+                ))
+            }
         },
     };
 
-    basic_config = basic_config.level(level);
+    let basic_config = if json_report {
+        let mut basic_config = fern::Dispatch::new().chain(
+            fern::Dispatch::new()
+                .level(log::LevelFilter::Warn)
+                .chain(fern::Output::call(report::collect_warning)),
+        );
+        if log_level.is_some() {
+            basic_config = basic_config.chain(
+                fern::Dispatch::new()
+                    .level(level)
+                    .format(format_record)
+                    .chain(io::stderr()),
+            );
+        }
+        basic_config
+    } else if to_stderr {
+        fern::Dispatch::new().level(level).chain(
+            fern::Dispatch::new()
+                .format(format_record)
+                .chain(io::stderr()),
+        )
+    } else {
+        fern::Dispatch::new().level(level).chain(
+            fern::Dispatch::new()
+                .format(format_record)
+                .chain(io::stdout()),
+        )
+    };
+
+    basic_config.apply().map_err(fern::InitError::from)?;
 
-    let stdout_config = fern::Dispatch::new()
-        .format(|out, message, record| {
-            out.finish(format_args!(
-                "[{}][{}][{}] {}",
-                chrono::Local::now().format("%H:%M:%S"),
-                record.target(),
-                record.level(),
-                message
-            ))
-        })
-        .chain(io::stdout());
+    Ok(())
+}
+
+fn is_json_report(args: &clap::ArgMatches) -> bool {
+    args.value_of("format") == Some("json")
+}
+
+fn file_size(file_name: &str) -> Option<u64> {
+    fs::metadata(file_name).ok().map(|metadata| metadata.len())
+}
 
-    basic_config.chain(stdout_config).apply()?;
+/// Adds the number of the elements of the root container and the size of
+/// their stored data to the report.
+fn add_elems_summary(report: &mut Report, file_name: &str) -> Result<(), CliError> {
+    let elems = parser::list_file(file_name, Some(0))?;
+    report.elem_count = Some(elems.len());
+    report.data_size = Some(elems.iter().map(|elem| elem.raw_size).sum());
 
     Ok(())
 }
 
-fn parse(app_m: &clap::ArgMatches, single_threaded: bool) {
-    if let Some(v) = app_m.values_of("parse") {
-        let args: Vec<&str> = v.collect();
-        if single_threaded {
-            parser::unpack_to_directory_no_load(&args[0], &args[1], true, true).unwrap();
+fn elem_filter(args: &clap::ArgMatches) -> ElemFilter {
+    let mut filter = ElemFilter::new();
+    for pattern in args.values_of("include").into_iter().flatten() {
+        filter = filter.with_include(pattern);
+    }
+    for pattern in args.values_of("exclude").into_iter().flatten() {
+        filter = filter.with_exclude(pattern);
+    }
+
+    filter
+}
+
+fn parse(args: &clap::ArgMatches, report: &mut Report) -> Result<(), CliError> {
+    let input = Input::open(args)?;
+    let file_name = &input.path();
+    let dir_name = args.value_of("OUTDIR").unwrap_or_default();
+    let single_threaded = args.is_present("single-threaded");
+    report.input = Some(input.name.clone());
+    report.input_size = file_size(file_name);
+    report.output = Some(dir_name.to_string());
+
+    let filter = elem_filter(args);
+    let archive = ArchiveFormat::from_path(dir_name);
+
+    if archive.is_some()
+        || is_json_report(args)
+        || args.is_present("incremental")
+        || args.is_present("max-memory")
+        || args.is_present("progress")
+        || !filter.is_empty()
+    {
+        let max_memory = match args.value_of("max-memory") {
+            Some(v) => Some(app::parse_size(v).map_err(CliError::Usage)?),
+            None => None,
+        };
+        let progress_bar: Option<Arc<dyn Progress>> = if args.is_present("progress") {
+            Some(Arc::new(progress::ProgressBar::default()))
+        } else {
+            None
+        };
+        let counter = Arc::new(progress::WrittenCounter::new(progress_bar));
+        let options = parser::UnpackOptions::new()
+            .with_incremental(args.is_present("incremental"))
+            .with_remove_stale(args.is_present("remove-stale"))
+            .with_max_memory(max_memory)
+            .with_filter(filter)
+            .with_progress(counter.clone());
+        let unpack_report = match archive {
+            Some(_) if args.is_present("incremental") => {
+                return Err(CliError::Usage(
+                    "--incremental needs a directory, not an archive".to_string(),
+                ))
+            }
+            Some(format) => {
+                unpack_to_archive(file_name, dir_name, format, single_threaded, &options)
+            }
+            None if single_threaded => {
+                parser::unpack_to_directory_with_options(file_name, dir_name, &options)
+            }
+            None => parser::parse_to_folder_with_options(file_name, dir_name, &options),
+        }?;
+        if is_json_report(args) {
+            // Only the files selected by the filters are counted.
+            report.elem_count = Some(
+                unpack_report.added + unpack_report.changed + unpack_report.unchanged,
+            );
+            report.data_size = Some(counter.written());
+            report.unpack = Some(unpack_report);
         } else {
-            parser::parse_to_folder(&args[0], &args[1], true).unwrap();
+            print_stdout(|out| writeln!(out, "{}", unpack_report))?;
         }
+    } else if single_threaded {
+        let parsed =
+            parser::unpack_to_directory_no_load(file_name, dir_name, true, true)?;
+        ensure_v8file(parsed, &input.name)?;
+    } else {
+        parser::parse_to_folder(file_name, dir_name, true)?;
     }
+
+    Ok(())
 }
 
-fn unpack(app_m: &clap::ArgMatches, single_threaded: bool) {
-    if let Some(v) = app_m.values_of("unpack") {
-        let args: Vec<&str> = v.collect();
+/// Unpacks the container into the archive `archive_name`, removing the
+/// archive on failure.
+fn unpack_to_archive(
+    file_name: &str,
+    archive_name: &str,
+    format: ArchiveFormat,
+    single_threaded: bool,
+    options: &parser::UnpackOptions,
+) -> Result<UnpackReport, V8Error> {
+    let unpack = |sink: &mut dyn OutputSink| {
         if single_threaded {
-            parser::unpack_to_folder(&args[0], &args[1]).unwrap();
+            parser::unpack_to_sink(file_name, sink, options)
         } else {
-            parser::unpack_pipeline(&args[0], &args[1]).unwrap();
+            parser::parse_to_sink(file_name, sink, options)
         }
+    };
+
+    let out = io::BufWriter::new(fs::File::create(archive_name)?);
+    let result = match format {
+        ArchiveFormat::Tar => unpack(&mut TarSink::new(out)),
+        ArchiveFormat::Zip => unpack(&mut ZipSink::new(out)),
+    };
+    if result.is_err() {
+        let _ = fs::remove_file(archive_name);
     }
+
+    result
 }
 
-fn pack(app_m: &clap::ArgMatches, _single_threaded: bool) {
-    if let Some(v) = app_m.values_of("pack") {
-        let args: Vec<&str> = v.collect();
-        builder::pack_from_folder(&args[0], &args[1]).unwrap();
+fn unpack(args: &clap::ArgMatches, report: &mut Report) -> Result<(), CliError> {
+    let input = Input::open(args)?;
+    let file_name = &input.path();
+    let dir_name = args.value_of("OUTDIR").unwrap_or_default();
+    report.input = Some(input.name.clone());
+    report.input_size = file_size(file_name);
+    report.output = Some(dir_name.to_string());
+
+    if args.is_present("single-threaded") {
+        let unpacked = parser::unpack_to_folder(file_name, dir_name)?;
+        ensure_v8file(unpacked, &input.name)?;
+    } else {
+        parser::unpack_pipeline(file_name, dir_name)?;
     }
+
+    if is_json_report(args) {
+        add_elems_summary(report, file_name)?;
+    }
+
+    Ok(())
 }
 
-fn build(app_m: &clap::ArgMatches, no_deflate: bool) {
-    if let Some(v) = app_m.values_of("build") {
-        let args: Vec<&str> = v.collect();
-        builder::build_cf_file(&args[0], &args[1], no_deflate).unwrap();
+/// Adds the summary of the built container to the report.
+fn add_output_summary(report: &mut Report, file_name: &str) -> Result<(), CliError> {
+    report.output_size = file_size(file_name);
+    report.elem_count = parser::file_info(file_name)?.elem_count;
+
+    Ok(())
+}
+
+fn pack(args: &clap::ArgMatches, report: &mut Report) -> Result<(), CliError> {
+    let dir_name = args.value_of("INPUTDIR").unwrap_or_default();
+    let file_name = args.value_of("OUTFILE").unwrap_or_default();
+    report.input = Some(dir_name.to_string());
+    report.output = Some(file_name.to_string());
+
+    builder::pack_from_folder(dir_name, file_name)?;
+
+    if is_json_report(args) {
+        add_output_summary(report, file_name)?;
     }
+
+    Ok(())
 }
 
-fn main() {
-    let app_m = App::new("v8unpack")
-        .version(crate_version!())
-        .author(crate_authors!())
-        .setting(clap::AppSettings::ArgRequiredElseHelp)
-        .about(
-            "\n\t2008 Denis Demidov 2008-03-30\n\t2017 Alexander Andreev\n\
-             Unpack, pack, deflate and inflate 1C v8 file (*.cf)",
-        )
-        .arg(
-            Arg::with_name("parse")
-                .short("p")
-                .long("parse")
-                .help("unzip the files into a directory")
-                .takes_value(true)
-                .value_names(&["INPUTFILE", "OUTDIR"]),
-        )
-        .arg(
-            Arg::with_name("unpack")
-                .short("u")
-                .long("unpack")
-                .help("unzip the binaries into the directory")
-                .takes_value(true)
-                .value_names(&["INPUTFILE", "OUTDIR"]),
-        )
-        .arg(
-            Arg::with_name("single-threaded")
-                .short("s")
-                .long("single-threaded")
-                .help("Do all the work on a single thread."),
-        )
-        .arg(
-            Arg::with_name("pack")
-                .long("pack")
-                .help("Package the binaries in *.cf")
-                .takes_value(true)
-                .value_names(&["INPUTFILE", "OUTDIR"]),
-        )
-        .arg(
-            Arg::with_name("build")
-                .short("b")
-                .long("build")
-                .help("Build the binaries in *.cf from source files")
-                .takes_value(true)
-                .value_names(&["INPUTFILE", "OUTDIR"]),
-        )
-        .arg(
-            Arg::with_name("nopack")
-                .help("Not deflate")
-                .requires("build")
-                .index(1),
-        )
-        .arg(
-            Arg::with_name("verbosity")
-                .short("v")
-                .long("verbosity")
-                .help("Logging verbosity level")
-                .takes_value(true)
-                .value_name("LOG_LEVEL"),
+fn build(args: &clap::ArgMatches, report: &mut Report) -> Result<(), CliError> {
+    let dir_name = args.value_of("INPUTDIR").unwrap_or_default();
+    let file_name = args.value_of("OUTFILE").unwrap_or_default();
+    report.input = Some(dir_name.to_string());
+    report.output = Some(file_name.to_string());
+
+    let mut options = builder::BuildOptions::new()
+        .with_no_deflate(args.is_present("no-deflate") || args.is_present("nopack"))
+        .with_filter(elem_filter(args));
+    if let Some(threads) = args.value_of("threads") {
+        let threads = threads.parse().map_err(|_| {
+            CliError::Usage(format!("Bad number of threads {:?}", threads))
+        })?;
+        options = options.with_threads(threads);
+    }
+    if let Some(cache_dir) = args.value_of("cache-dir") {
+        options = options.with_cache(BlockCache::open(cache_dir)?);
+    }
+    if args.is_present("progress") {
+        options = options.with_progress(Arc::new(progress::ProgressBar::default()));
+    }
+
+    let source: Arc<dyn InputSource> = match ArchiveFormat::from_path(dir_name) {
+        Some(_) if Path::new(dir_name).is_file() => {
+            Arc::new(ArchiveSource::open(dir_name)?)
+        }
+        _ => Arc::new(DirSource::new(dir_name)),
+    };
+    if file_name == STDIO {
+        let mut out = io::stdout().lock();
+        builder::build_cf_to_writer_from_source(source, &mut out, &options)?;
+        return Ok(());
+    }
+    builder::build_cf_file_from_source(source, file_name, &options)?;
+
+    if is_json_report(args) {
+        add_output_summary(report, file_name)?;
+    }
+
+    Ok(())
+}
+
+fn extract(args: &clap::ArgMatches, report: &mut Report) -> Result<(), CliError> {
+    let input = Input::open(args)?;
+    let file_name = &input.path();
+    let elem_path = args.value_of("ELEMENT").unwrap_or_default();
+    let out_path = args.value_of("OUTPATH").unwrap_or_default();
+    report.input = Some(input.name.clone());
+    report.input_size = file_size(file_name);
+    report.output = Some(out_path.to_string());
+
+    let found = if out_path == STDIO {
+        parser::extract_to_writer(file_name, elem_path, &mut io::stdout().lock())?
+    } else {
+        parser::extract_to_path(file_name, elem_path, out_path)?
+    };
+    if !found {
+        return Err(CliError::NotFound(elem_path.to_string()));
+    }
+
+    Ok(())
+}
+
+fn run_batch(args: &clap::ArgMatches, report: &mut Report) -> Result<(), CliError> {
+    let input = args.value_of("INPUT").unwrap_or_default();
+    let dir_name = args.value_of("OUTDIR").unwrap_or_default();
+    report.input = Some(input.to_string());
+    report.output = Some(dir_name.to_string());
+
+    let command = match args.value_of("COMMAND") {
+        Some("build") => BatchCommand::Build,
+        _ => BatchCommand::Parse,
+    };
+    let mut options =
+        BatchOptions::new(command).with_no_deflate(args.is_present("no-deflate"));
+    if let Some(jobs) = args.value_of("jobs") {
+        let jobs = jobs
+            .parse()
+            .map_err(|_| CliError::Usage(format!("Bad number of jobs {:?}", jobs)))?;
+        options = options.with_jobs(jobs);
+    }
+
+    let inputs = if let Some(list_file) = input.strip_prefix('@') {
+        batch::read_input_list(list_file)?
+    } else {
+        batch::collect_inputs(input, command)?
+    };
+    let batch_report = batch::run_batch(&inputs, dir_name, &options);
+    report.elem_count = Some(batch_report.items.len());
+
+    if is_json_report(args) {
+        let items: Vec<_> = batch_report
+            .items
+            .iter()
+            .map(|item| {
+                json!({
+                    "input": item.input.to_string_lossy(),
+                    "output": item.output.to_string_lossy(),
+                    "success": item.error.is_none(),
+                    "error": item.error.as_ref().map(|e| e.to_string()),
+                    "duration_ms": item.duration.as_millis() as u64,
+                })
+            })
+            .collect();
+        report
+            .details
+            .insert("items".to_string(), serde_json::Value::Array(items));
+    } else {
+        for item in batch_report.items.iter() {
+            if let Some(ref e) = item.error {
+                eprintln!("{}: {}", item.input.display(), e);
+            }
+        }
+        print_stdout(|out| writeln!(out, "{}", batch_report))?;
+    }
+
+    if batch_report.failed() > 0 {
+        return Err(CliError::BatchFailed {
+            failed: batch_report.failed(),
+            total: batch_report.items.len(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Formats a time stored in a container as it was written, 1C does not
+/// keep the time zone.
+fn format_v8_time(value: u64) -> Option<String> {
+    let seconds = container::v8_time_to_unix(value)?;
+    let time = chrono::Utc.timestamp_opt(seconds, 0).single()?;
+
+    Some(time.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+fn elem_to_json(elem: &parser::ElemInfo) -> serde_json::Value {
+    json!({
+        "path": elem.path,
+        "depth": elem.depth,
+        "header_addr": elem.header_addr,
+        "data_addr": elem.data_addr,
+        "raw_size": elem.raw_size,
+        "inflated_size": elem.inflated_size,
+        "compressed": elem.compressed,
+        "v8file": elem.v8file,
+        "created": format_v8_time(elem.date_creation),
+        "modified": format_v8_time(elem.date_modification),
+    })
+}
+
+/// Lists the elements, turning a wrong input into `CliError::NotV8File`.
+fn list_elems(
+    input: &Input,
+    max_depth: Option<usize>,
+) -> Result<Vec<parser::ElemInfo>, CliError> {
+    match parser::list_file(&input.path(), max_depth) {
+        Err(V8Error::NotV8File { .. }) => Err(CliError::NotV8File(input.name.clone())),
+        elems => Ok(elems?),
+    }
+}
+
+fn list(args: &clap::ArgMatches, report: &mut Report) -> Result<(), CliError> {
+    let input = Input::open(args)?;
+    let file_name = &input.path();
+    report.input = Some(input.name.clone());
+    report.input_size = file_size(file_name);
+    let max_depth = if args.is_present("recursive") {
+        None
+    } else {
+        match args.value_of("depth") {
+            Some(depth) => Some(
+                depth
+                    .parse()
+                    .map_err(|_| CliError::Usage(format!("Bad depth {:?}", depth)))?,
+            ),
+            None => Some(0),
+        }
+    };
+
+    let elems = list_elems(&input, max_depth)?;
+
+    if is_json_report(args) {
+        report.elem_count = Some(elems.len());
+        let elems: Vec<_> = elems.iter().map(elem_to_json).collect();
+        report
+            .details
+            .insert("elems".to_string(), serde_json::Value::Array(elems));
+        return Ok(());
+    }
+
+    if args.is_present("json") {
+        let elems: Vec<_> = elems.iter().map(elem_to_json).collect();
+        return print_stdout(|out| writeln!(out, "{}", serde_json::Value::Array(elems)));
+    }
+
+    print_stdout(|out| {
+        writeln!(
+            out,
+            "{:>10} {:>10} {:5} {:19} {:>10} {:>10}  PATH",
+            "RAW", "INFLATED", "FLAGS", "MODIFIED", "HEADER", "DATA"
+        )?;
+        for elem in elems.iter() {
+            let flags = format!(
+                "{}{}",
+                if elem.compressed { 'z' } else { '-' },
+                if elem.v8file { 'c' } else { '-' }
+            );
+            writeln!(
+                out,
+                "{:>10} {:>10} {:5} {:19} {:>10} {:>10}  {}",
+                elem.raw_size,
+                elem.inflated_size,
+                flags,
+                format_v8_time(elem.date_modification).unwrap_or_default(),
+                elem.header_addr,
+                elem.data_addr
+                    .map(|addr| addr.to_string())
+                    .unwrap_or_default(),
+                elem.path
+            )?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Reads every element of the container and of the nested containers.
+fn verify(args: &clap::ArgMatches, report: &mut Report) -> Result<(), CliError> {
+    let input = Input::open(args)?;
+    let file_name = &input.path();
+    report.input = Some(input.name.clone());
+    report.input_size = file_size(file_name);
+
+    let elems = list_elems(&input, None)?;
+    report.elem_count = Some(elems.len());
+
+    if !is_json_report(args) {
+        print_stdout(|out| {
+            writeln!(out, "{}: OK, {} elements", input.name, elems.len())
+        })?;
+    }
+
+    Ok(())
+}
+
+fn info(args: &clap::ArgMatches, report: &mut Report) -> Result<(), CliError> {
+    let input = Input::open(args)?;
+    let file_name = &input.path();
+    report.input = Some(input.name.clone());
+    let info = match parser::file_info(file_name) {
+        Err(V8Error::NotV8File { .. }) => {
+            return Err(CliError::NotV8File(input.name.clone()))
+        }
+        info => info?,
+    };
+
+    if is_json_report(args) || args.is_present("json") {
+        let value = json!({
+            "format": info.format.to_string(),
+            "file_size": info.file_size,
+            "next_page_addr": info.next_page_addr,
+            "page_size": info.page_size,
+            "storage_ver": info.storage_ver,
+            "toc_size": info.toc_size,
+            "elem_count": info.elem_count,
+            "platform_version": info.platform_version,
+        });
+        if is_json_report(args) {
+            report.input_size = Some(info.file_size);
+            report.elem_count = info.elem_count;
+            report.details.insert("info".to_string(), value);
+            return Ok(());
+        }
+        return print_stdout(|out| writeln!(out, "{}", value));
+    }
+
+    let or_unknown = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    print_stdout(|out| {
+        writeln!(out, "Format:           {}", info.format)?;
+        writeln!(out, "File size:        {}", info.file_size)?;
+        writeln!(out, "Next page addr:   {:#x}", info.next_page_addr)?;
+        writeln!(out, "Page size:        {}", info.page_size)?;
+        writeln!(out, "Storage version:  {}", info.storage_ver)?;
+        writeln!(out, "TOC size:         {}", info.toc_size)?;
+        writeln!(
+            out,
+            "Elements:         {}",
+            or_unknown(info.elem_count.map(|count| count.to_string()))
+        )?;
+        writeln!(
+            out,
+            "Platform version: {}",
+            or_unknown(info.platform_version)
         )
-        .get_matches();
+    })
+}
 
-    let single_threaded = app_m.is_present("single-threaded");
-    let no_deflate = app_m.is_present("no-deflate");
+/// Describes the error of a command for the JSON report.
+fn error_to_json(error: &CliError) -> serde_json::Value {
+    let (offset, path, container) = match *error {
+        CliError::V8(ref e) => (
+            e.offset(),
+            e.path().filter(|path| !path.is_empty()),
+            e.container(),
+        ),
+        _ => (None, None, None),
+    };
+
+    json!({
+        "message": error.to_string(),
+        "exit_code": error.exit_code(),
+        "offset": offset,
+        "path": path,
+        "container": container,
+    })
+}
+
+fn run(app_m: &clap::ArgMatches, report: &mut Report) -> Result<(), CliError> {
+    let (command, args) = match app_m.subcommand() {
+        (command, Some(args)) => (command, args),
+        _ => return Err(CliError::Usage("No command given".to_string())),
+    };
+    report.command = command.to_string();
+
+    if is_json_report(args) && writes_stdout(args) {
+        return Err(CliError::Usage(
+            "The JSON report can not be printed along with the output to stdout"
+                .to_string(),
+        ));
+    }
+    if args.is_present("verbosity") || is_json_report(args) {
+        setup_logging(
+            args.value_of("verbosity"),
+            is_json_report(args),
+            writes_stdout(args),
+        )?;
+    }
+
+    match command {
+        "parse" => parse(args, report),
+        "unpack" => unpack(args, report),
+        "pack" => pack(args, report),
+        "build" => build(args, report),
+        "extract" => extract(args, report),
+        "batch" => run_batch(args, report),
+        "list" => list(args, report),
+        "verify" => verify(args, report),
+        "info" => info(args, report),
+        _ => Err(CliError::Usage(format!("Unknown command {}", command))),
+    }
+}
 
-    if app_m.is_present("verbosity") {
-        setup_logging(app_m.value_of("verbosity"))
-            .expect("failed to initialize logging.");
+fn main() {
+    let args = app::legacy_args(env::args_os().collect());
+    let app_m = match app::build_app().get_matches_from_safe(args) {
+        Ok(app_m) => app_m,
+        Err(e) if e.use_stderr() => {
+            eprintln!("{}", e.message);
+            process::exit(EXIT_USAGE);
+        }
+        Err(e) => e.exit(),
+    };
+
+    let json_report = app_m.subcommand().1.is_some_and(is_json_report);
+    let mut report = Report::default();
+    let started = Instant::now();
+    let mut result = run(&app_m, &mut report);
+
+    if json_report {
+        let error = result.as_ref().err().map(error_to_json);
+        let json = report.to_json(started.elapsed(), error);
+        result = result.and(print_stdout(|out| writeln!(out, "{}", json)));
     }
 
-    parse(&app_m, single_threaded);
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(e.exit_code());
+    }
+}
 
-    unpack(&app_m, single_threaded);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    pack(&app_m, single_threaded);
+    #[test]
+    fn test_exit_codes() {
+        let io_error = io::Error::new(io::ErrorKind::NotFound, "missing");
+        let cases = vec![
+            (CliError::Usage("usage".to_string()), EXIT_USAGE),
+            (CliError::NotV8File("a.cf".to_string()), EXIT_BAD_FORMAT),
+            (CliError::NotFound("Form/form".to_string()), EXIT_NOT_FOUND),
+            (CliError::V8(V8Error::IoError(io_error)), EXIT_IO),
+            (
+                CliError::V8(V8Error::LimitExceeded {
+                    path: String::new(),
+                    what: "container size",
+                    value: u64::from(u32::MAX) + 1,
+                    limit: u64::from(u32::MAX),
+                }),
+                EXIT_FAILURE,
+            ),
+            (
+                CliError::V8(V8Error::NotV8File { offset: 0 }),
+                EXIT_BAD_FORMAT,
+            ),
+        ];
 
-    build(&app_m, no_deflate);
+        for (error, code) in cases {
+            assert_eq!(error.exit_code(), code, "{}", error);
+        }
+    }
 }
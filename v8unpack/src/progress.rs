@@ -0,0 +1,148 @@
+//! Progress bar printed to stderr.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use v8unpack4rs::progress::Progress;
+
+/// Width of the bar in characters.
+const BAR_WIDTH: usize = 30;
+
+#[derive(Debug, Default)]
+struct State {
+    total: Option<usize>,
+    done: usize,
+    bytes_written: u64,
+}
+
+/// Draws the elements processed and the bytes written on a single line.
+#[derive(Debug, Default)]
+pub struct ProgressBar {
+    state: Mutex<State>,
+}
+
+impl ProgressBar {
+    fn update<F: FnOnce(&mut State)>(&self, f: F) {
+        if let Ok(mut state) = self.state.lock() {
+            f(&mut state);
+            draw(&state);
+        }
+    }
+}
+
+impl Progress for ProgressBar {
+    fn begin(&self, total_elems: Option<usize>, _total_bytes: Option<u64>) {
+        self.update(|state| state.total = total_elems);
+    }
+
+    fn elem_finished(&self, _name: &str) {
+        self.update(|state| state.done += 1);
+    }
+
+    fn bytes_written(&self, bytes: u64) {
+        if let Ok(mut state) = self.state.lock() {
+            state.bytes_written += bytes;
+        }
+    }
+
+    fn end(&self) {
+        self.update(|_| {});
+        eprintln!();
+    }
+}
+
+/// Counts the bytes written for the JSON report and passes the events on to
+/// the progress bar, if any.
+#[derive(Debug, Default)]
+pub struct WrittenCounter {
+    inner: Option<Arc<dyn Progress>>,
+    written: AtomicU64,
+}
+
+impl WrittenCounter {
+    pub fn new(inner: Option<Arc<dyn Progress>>) -> WrittenCounter {
+        WrittenCounter {
+            inner,
+            written: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the number of bytes written so far.
+    pub fn written(&self) -> u64 {
+        self.written.load(Ordering::Relaxed)
+    }
+}
+
+impl Progress for WrittenCounter {
+    fn begin(&self, total_elems: Option<usize>, total_bytes: Option<u64>) {
+        if let Some(ref inner) = self.inner {
+            inner.begin(total_elems, total_bytes);
+        }
+    }
+
+    fn elem_started(&self, name: &str) {
+        if let Some(ref inner) = self.inner {
+            inner.elem_started(name);
+        }
+    }
+
+    fn elem_finished(&self, name: &str) {
+        if let Some(ref inner) = self.inner {
+            inner.elem_finished(name);
+        }
+    }
+
+    fn bytes_read(&self, bytes: u64) {
+        if let Some(ref inner) = self.inner {
+            inner.bytes_read(bytes);
+        }
+    }
+
+    fn bytes_written(&self, bytes: u64) {
+        self.written.fetch_add(bytes, Ordering::Relaxed);
+        if let Some(ref inner) = self.inner {
+            inner.bytes_written(bytes);
+        }
+    }
+
+    fn end(&self) {
+        if let Some(ref inner) = self.inner {
+            inner.end();
+        }
+    }
+}
+
+fn draw(state: &State) {
+    let written = format_bytes(state.bytes_written);
+    match state.total {
+        Some(total) if total > 0 => {
+            let filled = BAR_WIDTH * state.done.min(total) / total;
+            eprint!(
+                "\r[{}{}] {}/{} elements, {} written",
+                "#".repeat(filled),
+                ".".repeat(BAR_WIDTH - filled),
+                state.done,
+                total,
+                written
+            );
+        }
+        _ => eprint!("\r{} elements, {} written", state.done, written),
+    }
+    let _ = io::stderr().flush();
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
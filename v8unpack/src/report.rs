@@ -0,0 +1,74 @@
+//! Structured report of a command, printed with `--format json`.
+
+use serde_json::{json, Map, Value};
+use std::sync::Mutex;
+use std::time::Duration;
+use v8unpack4rs::sink::UnpackReport;
+
+/// Warnings logged by the library while the command runs.
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Records a warning or an error logged by the library.
+pub fn collect_warning(record: &log::Record) {
+    if let Ok(mut warnings) = WARNINGS.lock() {
+        warnings.push(format!("{}", record.args()));
+    }
+}
+
+/// Summary of a command, filled in by the command handlers.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub command: String,
+    pub input: Option<String>,
+    pub output: Option<String>,
+    /// Size of the input file.
+    pub input_size: Option<u64>,
+    /// Size of the output file.
+    pub output_size: Option<u64>,
+    /// Number of the elements processed.
+    pub elem_count: Option<usize>,
+    /// Size of the element data read or written.
+    pub data_size: Option<u64>,
+    /// Counts of the files touched by incremental unpacking.
+    pub unpack: Option<UnpackReport>,
+    /// Data specific to the command, like the elements of `list`.
+    pub details: Map<String, Value>,
+}
+
+impl Report {
+    /// Builds the JSON object of the report, `error` is the description of
+    /// the error the command failed with.
+    pub fn to_json(&self, duration: Duration, error: Option<Value>) -> Value {
+        let warnings = WARNINGS
+            .lock()
+            .map(|warnings| warnings.clone())
+            .unwrap_or_default();
+
+        let mut value = json!({
+            "command": self.command,
+            "success": error.is_none(),
+            "input": self.input,
+            "output": self.output,
+            "input_size": self.input_size,
+            "output_size": self.output_size,
+            "elem_count": self.elem_count,
+            "data_size": self.data_size,
+            "duration_ms": duration.as_millis() as u64,
+            "warnings": warnings,
+            "error": error,
+        });
+        if let Some(unpack) = self.unpack {
+            value["unpack"] = json!({
+                "added": unpack.added,
+                "changed": unpack.changed,
+                "unchanged": unpack.unchanged,
+                "removed": unpack.removed,
+            });
+        }
+        if let Value::Object(ref mut object) = value {
+            object.extend(self.details.clone());
+        }
+
+        value
+    }
+}
@@ -0,0 +1,111 @@
+extern crate tempdir;
+
+use serde_json::Value;
+use tempdir::TempDir;
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn test_file() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../v8unpack4rs/tests/test1.cf")
+}
+
+fn v8unpack(args: &[&Path]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_v8unpack"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_exit_codes() {
+    let dir = TempDir::new("test_exit_codes").unwrap();
+    let out = dir.path().join("out");
+    let test_file = test_file();
+
+    let output = v8unpack(&[Path::new("parse"), &test_file]);
+    assert_eq!(output.status.code(), Some(2));
+
+    let missing = dir.path().join("missing.cf");
+    let output = v8unpack(&[Path::new("parse"), &missing, &out]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("error: "));
+
+    let not_v8file = dir.path().join("not_v8file.cf");
+    std::fs::write(&not_v8file, [b'x'; 64]).unwrap();
+    let output = v8unpack(&[Path::new("parse"), &not_v8file, &out]);
+    assert_eq!(output.status.code(), Some(4));
+
+    let elem = Path::new("NoSuchElement");
+    let output = v8unpack(&[Path::new("extract"), &test_file, elem, &out]);
+    assert_eq!(output.status.code(), Some(5));
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_legacy_flags() {
+    let dir = TempDir::new("test_legacy_flags").unwrap();
+    let parsed = dir.path().join("parsed");
+    let built = dir.path().join("built.cf");
+
+    let output = v8unpack(&[Path::new("-p"), &test_file(), &parsed]);
+    assert!(output.status.success());
+    assert!(std::fs::read_dir(&parsed).unwrap().next().is_some());
+
+    let output = v8unpack(&[Path::new("--build"), &parsed, &built, Path::new("nopack")]);
+    assert!(output.status.success());
+
+    // The elements are stored without compression, so the build is larger.
+    let compressed = dir.path().join("compressed.cf");
+    let output = v8unpack(&[Path::new("build"), &parsed, &compressed]);
+    assert!(output.status.success());
+    let size = |path: &Path| std::fs::metadata(path).unwrap().len();
+    assert!(size(&built) > size(&compressed));
+
+    dir.close().unwrap();
+}
+
+fn json_report(output: &Output) -> Value {
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn test_json_report() {
+    let dir = TempDir::new("test_json_report").unwrap();
+    let json = Path::new("--format=json");
+    let parsed = dir.path().join("parsed");
+
+    let output = v8unpack(&[Path::new("parse"), json, &test_file(), &parsed]);
+    assert!(output.status.success());
+    let report = json_report(&output);
+    assert_eq!(report["command"], "parse");
+    assert_eq!(report["success"], true);
+    assert_eq!(report["error"], Value::Null);
+    assert!(report["elem_count"].as_u64().unwrap() > 0);
+
+    let out = dir.path().join("out");
+    let elem = Path::new("NoSuchElement");
+    let output = v8unpack(&[Path::new("extract"), json, &test_file(), elem, &out]);
+    assert_eq!(output.status.code(), Some(5));
+    let report = json_report(&output);
+    assert_eq!(report["command"], "extract");
+    assert_eq!(report["success"], false);
+    assert_eq!(report["error"]["exit_code"], 5);
+
+    // The block of the table of contents claims more bytes than the file has.
+    let mut damaged = std::fs::read(test_file()).unwrap();
+    damaged[18..26].copy_from_slice(b"00100000");
+    damaged[27..35].copy_from_slice(b"00100000");
+    let damaged_file = dir.path().join("damaged.cf");
+    std::fs::write(&damaged_file, damaged).unwrap();
+    let output = v8unpack(&[Path::new("parse"), json, &damaged_file, &out]);
+    assert_eq!(output.status.code(), Some(4));
+    let report = json_report(&output);
+    assert_eq!(report["success"], false);
+    assert_eq!(report["error"]["exit_code"], 4);
+    assert!(report["error"]["offset"].is_u64());
+    assert_eq!(report["error"]["container"], Value::Null);
+
+    dir.close().unwrap();
+}
@@ -0,0 +1,68 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use tempdir::TempDir;
+use v8unpack4rs::{builder, parser};
+
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+static TEST_FILE1: &[u8] = include_bytes!("../tests/test1.cf");
+
+/// Creates a source tree of many small elements, where padding of blocks
+/// to the page size dominates the amount of written data.
+fn make_sources(root: &Path, files: usize) {
+    fs::create_dir_all(root.join("nested")).unwrap();
+    for i in 0..files {
+        let data = format!("Procedure Test{0}()\r\n\tMessage({0});\r\nEndProcedure", i);
+        fs::write(root.join(format!("elem{}", i)), &data).unwrap();
+        if i % 4 == 0 {
+            fs::write(root.join("nested").join(format!("elem{}", i)), &data).unwrap();
+        }
+    }
+}
+
+fn bench_build(c: &mut Criterion) {
+    let dir = TempDir::new("bench_build").unwrap();
+    let src = dir.path().join("src");
+    make_sources(&src, 2000);
+    let src = src.to_str().unwrap();
+    let out = dir.path().join("out.cf");
+    let out = out.to_str().unwrap();
+
+    c.bench_function("build_cf_file 2000 elements", |b| {
+        b.iter(|| builder::build_cf_file(src, out, false).unwrap())
+    });
+    c.bench_function("build_cf_file 2000 elements no deflate", |b| {
+        b.iter(|| builder::build_cf_file(src, out, true).unwrap())
+    });
+}
+
+fn bench_pack(c: &mut Criterion) {
+    let dir = TempDir::new("bench_pack").unwrap();
+    let src = dir.path().join("src");
+    make_sources(&src, 2000);
+    let built = dir.path().join("built.cf");
+    let built = built.to_str().unwrap();
+    builder::build_cf_file(src.to_str().unwrap(), built, false).unwrap();
+
+    let unpacked = dir.path().join("unpacked");
+    let unpacked = unpacked.to_str().unwrap();
+    parser::unpack_to_folder(built, unpacked).unwrap();
+    let out = dir.path().join("out.cf");
+    let out = out.to_str().unwrap();
+
+    c.bench_function("pack_from_folder 2000 elements", |b| {
+        b.iter(|| builder::pack_from_folder(unpacked, out).unwrap())
+    });
+}
+
+fn bench_get_data(c: &mut Criterion) {
+    let v8file = parser::load_file(&mut Cursor::new(TEST_FILE1), true).unwrap();
+
+    c.bench_function("V8File::get_data test1.cf", |b| {
+        b.iter(|| v8file.get_data().unwrap())
+    });
+}
+
+criterion_group!(benches, bench_build, bench_pack, bench_get_data);
+criterion_main!(benches);
@@ -0,0 +1,303 @@
+#ifndef V8UNPACK_H
+#define V8UNPACK_H
+
+/* Generated by cbindgen from the sources of v8unpack4rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The operation succeeded.
+#define V8_OK 0
+
+// A null pointer or a string which is not UTF-8 was passed.
+#define V8_ERR_INVALID_ARGUMENT 1
+
+// Reading or writing a file failed.
+#define V8_ERR_IO 2
+
+// The input is not a 1C v8 container.
+#define V8_ERR_NOT_V8FILE 3
+
+// The container is damaged.
+#define V8_ERR_BAD_FORMAT 4
+
+// The container has no such element.
+#define V8_ERR_NOT_FOUND 5
+
+// The container exceeds the limits of the 32-bit format.
+#define V8_ERR_LIMIT_EXCEEDED 6
+
+// The operation was cancelled.
+#define V8_ERR_CANCELLED 7
+
+// The library panicked, this is a bug.
+#define V8_ERR_PANIC 8
+
+// A container opened by `v8_open`.
+typedef struct V8Handle V8Handle;
+
+// Callback of the C interface receiving `user_data`, the numbers of the
+// processed and of all elements of the root container and the number of
+// bytes written so far. Null when progress is not needed.
+typedef void (*ProgressCallback)(void *user_data, size_t done, size_t total, uint64_t written);
+
+// Bytes allocated by the library, the caller frees them with
+// `v8_buffer_free`.
+typedef struct V8Buffer {
+  // The bytes, null in the buffer stored on errors.
+  uint8_t *data;
+  // Number of the bytes.
+  size_t len;
+} V8Buffer;
+
+// Description of an element passed to `ListCallback`, see
+// `parser::ElemInfo`.
+typedef struct V8ElemInfo {
+  // Path of the element, like `Form/form`, valid during the call only.
+  const char *path;
+  // Nesting level, `0` for the elements of the root container.
+  size_t depth;
+  // Size of the data as stored in the container.
+  uint64_t raw_size;
+  // Size of the data after inflating.
+  uint64_t inflated_size;
+  // The data is deflated.
+  bool compressed;
+  // The data is a nested container.
+  bool v8file;
+  // Creation time in 1C ticks.
+  uint64_t date_creation;
+  // Modification time in 1C ticks.
+  uint64_t date_modification;
+} V8ElemInfo;
+
+// Callback of `v8_list` receiving `user_data` and an element.
+typedef void (*ListCallback)(void *user_data, const struct V8ElemInfo *info);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// External interface to call the decompression of the file container from
+// other languages.
+//
+// Returns `false` on error, the text of the error is returned by
+// `v8_last_error_message`. `v8_parse` returns the status code instead.
+//
+// # Safety
+//
+// `pfile_name` and `pdir_name` must be null or valid pointers to
+// nul-terminated strings.
+bool parse_cf(const char *pfile_name, const char *pdir_name);
+
+// Same as `parse_cf`, calling `callback` with `user_data` when the number
+// of elements is known and after each element of the root container.
+//
+// # Safety
+//
+// `pfile_name` and `pdir_name` must be null or valid pointers to
+// nul-terminated strings. `callback`, when given, is called on the calling
+// thread and must not unwind.
+bool parse_cf_with_progress(const char *pfile_name,
+                            const char *pdir_name,
+                            ProgressCallback callback,
+                            void *user_data);
+
+// Unpacks the container `file_name` into the directory `dir_name` on the
+// calling thread, calling `callback` with `user_data` when the number of
+// elements is known and after each element of the root container.
+//
+// Returns `V8_OK` or an error status, see `v8_last_error_message`.
+//
+// # Safety
+//
+// `file_name` and `dir_name` must be null or valid pointers to
+// nul-terminated strings. `callback`, when given, is called on the calling
+// thread and must not unwind.
+int v8_parse_with_progress(const char *file_name,
+                           const char *dir_name,
+                           ProgressCallback callback,
+                           void *user_data);
+
+// Frees the bytes of `buffer` returned by the library, an empty buffer is
+// ignored.
+//
+// # Safety
+//
+// `buffer` must be returned by the library and not freed before.
+void v8_buffer_free(struct V8Buffer buffer);
+
+// Opens the container `file_name`, reading it into memory, and stores the
+// handle to close with `v8_close` in `out_handle`.
+//
+// # Safety
+//
+// `file_name` must be null or a valid pointer to a nul-terminated string,
+// `out_handle` must be null or a valid pointer.
+int v8_open(const char *file_name, struct V8Handle **out_handle);
+
+// Opens the container in `len` bytes at `data`, copying them, and stores
+// the handle to close with `v8_close` in `out_handle`.
+//
+// # Safety
+//
+// `data` must be a valid pointer to `len` bytes, `out_handle` must be null
+// or a valid pointer.
+int v8_open_memory(const uint8_t *data, size_t len, struct V8Handle **out_handle);
+
+// Returns the number of elements of the container, `0` for a null handle.
+//
+// # Safety
+//
+// `handle` must be null or returned by `v8_open` and not closed.
+size_t v8_elem_count(const struct V8Handle *handle);
+
+// Returns the name of the element with the given index, or null when the
+// index is out of range. The string is owned by the handle and stays valid
+// until `v8_close`.
+//
+// # Safety
+//
+// `handle` must be null or returned by `v8_open` and not closed.
+const char *v8_elem_name(const struct V8Handle *handle, size_t index);
+
+// Stores the inflated data of the element with the given index in
+// `out_buffer`, the caller frees it with `v8_buffer_free`.
+//
+// # Safety
+//
+// `handle` must be null or returned by `v8_open` and not closed,
+// `out_buffer` must be null or a valid pointer.
+int v8_elem_read(const struct V8Handle *handle, size_t index, struct V8Buffer *out_buffer);
+
+// Opens the nested container stored in the element with the given index
+// and stores its handle in `out_handle`. The nested handle does not depend
+// on `handle` and is closed with `v8_close` too.
+//
+// Returns `V8_ERR_NOT_V8FILE` when the element is not a container.
+//
+// # Safety
+//
+// `handle` must be null or returned by `v8_open` and not closed,
+// `out_handle` must be null or a valid pointer.
+int v8_elem_open(const struct V8Handle *handle, size_t index, struct V8Handle **out_handle);
+
+// Closes the container, a null handle is ignored.
+//
+// # Safety
+//
+// `handle` must be null or returned by `v8_open` and not closed before.
+void v8_close(struct V8Handle *handle);
+
+// Unpacks the container in `len` bytes at `data` into the directory
+// `dir_name`, inflating the elements, like `v8_parse`.
+//
+// # Safety
+//
+// `data` must be null or a valid pointer to `len` bytes, `dir_name` must be
+// null or a valid pointer to a nul-terminated string.
+int v8_parse_memory(const uint8_t *data, size_t len, const char *dir_name);
+
+// Stores the inflated data of the element `elem_path`, like `Form/form`, of
+// the container in `len` bytes at `data` in `out_buffer`, the caller frees
+// it with `v8_buffer_free`. The data of a nested container is the
+// container itself.
+//
+// Returns `V8_ERR_NOT_FOUND` when the container has no such element.
+//
+// # Safety
+//
+// `data` must be null or a valid pointer to `len` bytes, `elem_path` must
+// be null or a valid pointer to a nul-terminated string, `out_buffer` must
+// be null or a valid pointer.
+int v8_extract_memory(const uint8_t *data,
+                      size_t len,
+                      const char *elem_path,
+                      struct V8Buffer *out_buffer);
+
+// Builds a container from the directory `dir_name` made by `v8_parse` and
+// stores it in `out_buffer`, the caller frees it with `v8_buffer_free`. The
+// elements are stored without compression when `no_deflate` is set.
+//
+// # Safety
+//
+// `dir_name` must be null or a valid pointer to a nul-terminated string,
+// `out_buffer` must be null or a valid pointer.
+int v8_build_memory(const char *dir_name, bool no_deflate, struct V8Buffer *out_buffer);
+
+// Unpacks the container `file_name` into the directory `dir_name`,
+// inflating the elements, like the `parse` command.
+//
+// Returns `V8_OK` or an error status, see `v8_last_error_message`.
+//
+// # Safety
+//
+// `file_name` and `dir_name` must be null or valid pointers to
+// nul-terminated strings.
+int v8_parse(const char *file_name, const char *dir_name);
+
+// Unpacks the binary headers and data of the elements of the container
+// `file_name` into the directory `dir_name`, like the `unpack` command.
+//
+// # Safety
+//
+// `file_name` and `dir_name` must be null or valid pointers to
+// nul-terminated strings.
+int v8_unpack(const char *file_name, const char *dir_name);
+
+// Packages the directory `dir_name` made by `v8_unpack` into the container
+// `file_name`, like the `pack` command.
+//
+// # Safety
+//
+// `dir_name` and `file_name` must be null or valid pointers to
+// nul-terminated strings.
+int v8_pack(const char *dir_name, const char *file_name);
+
+// Builds the container `file_name` from the directory `dir_name` made by
+// `v8_parse`, like the `build` command. The elements are stored without
+// compression when `no_deflate` is set.
+//
+// # Safety
+//
+// `dir_name` and `file_name` must be null or valid pointers to
+// nul-terminated strings.
+int v8_build(const char *dir_name, const char *file_name, bool no_deflate);
+
+// Writes the inflated data of the element `elem_path`, like `Form/form`, of
+// the container `file_name` to `out_path`, like the `extract` command. A
+// nested container is unpacked into the directory `out_path`.
+//
+// Returns `V8_ERR_NOT_FOUND` when the container has no such element.
+//
+// # Safety
+//
+// `file_name`, `elem_path` and `out_path` must be null or valid pointers to
+// nul-terminated strings.
+int v8_extract(const char *file_name, const char *elem_path, const char *out_path);
+
+// Calls `callback` with `user_data` for each element of the container
+// `file_name`, like the `list` command. Nested containers are listed up to
+// `max_depth` levels, a negative value lists all of them.
+//
+// # Safety
+//
+// `file_name` must be null or a valid pointer to a nul-terminated string.
+// `callback` is called on the calling thread and must not unwind.
+int v8_list(const char *file_name, int max_depth, ListCallback callback, void *user_data);
+
+// Returns the text of the error of the last call of the C interface on the
+// calling thread, or null when it succeeded.
+//
+// The string is owned by the library and stays valid until the next call
+// on the same thread.
+const char *v8_last_error_message(void);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* V8UNPACK_H */
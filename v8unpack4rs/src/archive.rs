@@ -0,0 +1,392 @@
+//! Unpacking into tar and zip archives and building from them.
+//!
+//! Elements are stored in an archive the same way as in a directory tree:
+//! a nested container is a directory of its elements.
+
+use crate::container::Result;
+use crate::error::V8Error;
+use crate::sink::{OutputSink, ReadSeek, SinkOptions, UnpackReport};
+use crate::source::{InputSource, SourceEntry};
+
+use log::*;
+use std::collections::HashMap;
+use std::io::{self, ErrorKind as ioErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{fmt, fs};
+
+/// Format of an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Detects the format by the extension of `path`, `.tar` or `.zip`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<ArchiveFormat> {
+        let extension = path.as_ref().extension()?.to_str()?;
+        if extension.eq_ignore_ascii_case("tar") {
+            Some(ArchiveFormat::Tar)
+        } else if extension.eq_ignore_ascii_case("zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Writes elements into a tar archive.
+pub struct TarSink<W: Write> {
+    builder: tar::Builder<W>,
+    options: SinkOptions,
+    report: UnpackReport,
+    mtime: u64,
+}
+
+impl<W: Write> TarSink<W> {
+    /// Creates a new instance of `TarSink` writing the archive to `out`.
+    pub fn new(out: W) -> TarSink<W> {
+        TarSink {
+            builder: tar::Builder::new(out),
+            options: SinkOptions::new(),
+            report: UnpackReport::default(),
+            mtime: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs()),
+        }
+    }
+
+    /// Completes the archive and returns the writer.
+    pub fn into_inner(self) -> Result<W> {
+        Ok(self.builder.into_inner()?)
+    }
+
+    fn header(&self, entry_type: tar::EntryType, mode: u32, size: u64) -> tar::Header {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_mode(mode);
+        header.set_mtime(self.mtime);
+        header.set_size(size);
+
+        header
+    }
+}
+
+impl<W: Write> fmt::Debug for TarSink<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TarSink")
+            .field("options", &self.options)
+            .field("report", &self.report)
+            .finish()
+    }
+}
+
+impl<W: Write> OutputSink for TarSink<W> {
+    fn get_options(&self) -> &SinkOptions {
+        &self.options
+    }
+
+    fn get_options_mut(&mut self) -> &mut SinkOptions {
+        &mut self.options
+    }
+
+    fn create_dir(&mut self, path: &Path) -> Result<()> {
+        self.options.get_cancellation().check()?;
+        let name = self.options.elem_path(path);
+        if name.is_empty() {
+            return Ok(());
+        }
+
+        let mut header = self.header(tar::EntryType::Directory, 0o755, 0);
+        self.builder
+            .append_data(&mut header, format!("{}/", name), io::empty())?;
+
+        Ok(())
+    }
+
+    fn write_file(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        self.write_file_from(path, &mut io::Cursor::new(data), data.len() as u64)
+    }
+
+    fn write_file_from(
+        &mut self,
+        path: &Path,
+        src: &mut dyn ReadSeek,
+        len: u64,
+    ) -> Result<()> {
+        self.options.get_cancellation().check()?;
+        if !self.accepts(path) {
+            debug!("skip filtered element {:?}", path);
+            return Ok(());
+        }
+
+        let name = self.options.elem_path(path);
+        debug!("write to tar entry {}", name);
+        let mut header = self.header(tar::EntryType::Regular, 0o644, len);
+        src.seek(SeekFrom::Start(0))?;
+        self.builder.append_data(&mut header, name, src.take(len))?;
+        self.report.added += 1;
+        self.options.report_written(len);
+
+        Ok(())
+    }
+
+    fn get_report(&self) -> &UnpackReport {
+        &self.report
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(self.builder.finish()?)
+    }
+}
+
+/// Writes elements into a zip archive, deflating them.
+pub struct ZipSink<W: Write + Seek> {
+    writer: zip::ZipWriter<W>,
+    /// The writer of the completed archive.
+    finished: Option<W>,
+    options: SinkOptions,
+    report: UnpackReport,
+}
+
+impl<W: Write + Seek> ZipSink<W> {
+    /// Creates a new instance of `ZipSink` writing the archive to `out`.
+    pub fn new(out: W) -> ZipSink<W> {
+        ZipSink {
+            writer: zip::ZipWriter::new(out),
+            finished: None,
+            options: SinkOptions::new(),
+            report: UnpackReport::default(),
+        }
+    }
+
+    /// Completes the archive and returns the writer.
+    pub fn into_inner(mut self) -> Result<W> {
+        match self.finished.take() {
+            Some(out) => Ok(out),
+            None => self.writer.finish().map_err(zip_error),
+        }
+    }
+
+    fn file_options(len: u64) -> zip::write::FileOptions {
+        zip::write::FileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .large_file(len > u64::from(u32::MAX))
+    }
+}
+
+impl<W: Write + Seek> fmt::Debug for ZipSink<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ZipSink")
+            .field("options", &self.options)
+            .field("report", &self.report)
+            .finish()
+    }
+}
+
+impl<W: Write + Seek> OutputSink for ZipSink<W> {
+    fn get_options(&self) -> &SinkOptions {
+        &self.options
+    }
+
+    fn get_options_mut(&mut self) -> &mut SinkOptions {
+        &mut self.options
+    }
+
+    fn create_dir(&mut self, path: &Path) -> Result<()> {
+        self.options.get_cancellation().check()?;
+        let name = self.options.elem_path(path);
+        if name.is_empty() {
+            return Ok(());
+        }
+
+        self.writer
+            .add_directory(name, Self::file_options(0))
+            .map_err(zip_error)
+    }
+
+    fn write_file(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        self.write_file_from(path, &mut io::Cursor::new(data), data.len() as u64)
+    }
+
+    fn write_file_from(
+        &mut self,
+        path: &Path,
+        src: &mut dyn ReadSeek,
+        len: u64,
+    ) -> Result<()> {
+        self.options.get_cancellation().check()?;
+        if !self.accepts(path) {
+            debug!("skip filtered element {:?}", path);
+            return Ok(());
+        }
+
+        let name = self.options.elem_path(path);
+        debug!("write to zip entry {}", name);
+        self.writer
+            .start_file(name, Self::file_options(len))
+            .map_err(zip_error)?;
+        src.seek(SeekFrom::Start(0))?;
+        io::copy(&mut src.take(len), &mut self.writer)?;
+        self.report.added += 1;
+        self.options.report_written(len);
+
+        Ok(())
+    }
+
+    fn get_report(&self) -> &UnpackReport {
+        &self.report
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.finished.is_none() {
+            self.finished = Some(self.writer.finish().map_err(zip_error)?);
+        }
+
+        Ok(())
+    }
+}
+
+/// Source files read from a tar or zip archive.
+///
+/// The whole archive is read into memory, entries keep the order of the
+/// archive. Directories which are not stored in the archive are implied by
+/// the paths of the files.
+#[derive(Debug, Default)]
+pub struct ArchiveSource {
+    dirs: HashMap<String, Vec<SourceEntry>>,
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl ArchiveSource {
+    /// Reads the archive `file_name` in the format given by its extension.
+    pub fn open(file_name: &str) -> Result<ArchiveSource> {
+        let file = io::BufReader::new(fs::File::open(file_name)?);
+        match ArchiveFormat::from_path(file_name) {
+            Some(ArchiveFormat::Tar) => ArchiveSource::from_tar(file),
+            Some(ArchiveFormat::Zip) => ArchiveSource::from_zip(file),
+            None => Err(io::Error::new(
+                ioErrorKind::InvalidInput,
+                format!("{} is neither a tar nor a zip archive", file_name),
+            )
+            .into()),
+        }
+    }
+
+    /// Reads a tar archive.
+    pub fn from_tar<R: Read>(src: R) -> Result<ArchiveSource> {
+        let mut result = ArchiveSource::default();
+        let mut archive = tar::Archive::new(src);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = normalize(&entry.path()?);
+            let entry_type = entry.header().entry_type();
+            if entry_type.is_dir() {
+                result.add_dir(&path);
+            } else if entry_type.is_file() {
+                let mut data = vec![];
+                entry.read_to_end(&mut data)?;
+                result.add_file(path, data);
+            } else {
+                warn!("skip tar entry {} of type {:?}", path, entry_type);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Reads a zip archive.
+    pub fn from_zip<R: Read + Seek>(src: R) -> Result<ArchiveSource> {
+        let mut result = ArchiveSource::default();
+        let mut archive = zip::ZipArchive::new(src).map_err(zip_error)?;
+        for index in 0..archive.len() {
+            let mut entry = archive.by_index(index).map_err(zip_error)?;
+            let path = normalize(Path::new(entry.name()));
+            if entry.is_dir() {
+                result.add_dir(&path);
+            } else {
+                let mut data = vec![];
+                entry.read_to_end(&mut data)?;
+                result.add_file(path, data);
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn add_dir(&mut self, path: &str) {
+        if self.dirs.contains_key(path) {
+            return;
+        }
+
+        self.dirs.insert(path.to_string(), vec![]);
+        if !path.is_empty() {
+            self.add_entry(path, true);
+        }
+    }
+
+    fn add_file(&mut self, path: String, data: Vec<u8>) {
+        if !path.is_empty() && !self.files.contains_key(&path) {
+            self.add_entry(&path, false);
+        }
+        self.files.insert(path, data);
+    }
+
+    /// Adds the entry `path` to the list of its parent directory.
+    fn add_entry(&mut self, path: &str, is_dir: bool) {
+        let (parent, name) = match path.rfind('/') {
+            Some(pos) => (&path[..pos], &path[pos + 1..]),
+            None => ("", path),
+        };
+        self.add_dir(parent);
+        if let Some(entries) = self.dirs.get_mut(parent) {
+            entries.push(SourceEntry {
+                name: name.to_string(),
+                is_dir,
+            });
+        }
+    }
+}
+
+impl InputSource for ArchiveSource {
+    fn read_dir(&self, path: &str) -> Result<Vec<SourceEntry>> {
+        match self.dirs.get(path) {
+            Some(entries) => Ok(entries.clone()),
+            None if path.is_empty() => Ok(vec![]),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        self.files.get(path).cloned().ok_or_else(|| not_found(path))
+    }
+}
+
+/// Turns a path of an archive entry, like `./Form/form`, into `Form/form`.
+fn normalize(path: &Path) -> String {
+    let names: Vec<_> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+
+    names.join("/")
+}
+
+fn not_found(path: &str) -> V8Error {
+    io::Error::new(
+        ioErrorKind::NotFound,
+        format!("{} is not in the archive", path),
+    )
+    .into()
+}
+
+fn zip_error(error: zip::result::ZipError) -> V8Error {
+    match error {
+        zip::result::ZipError::Io(e) => e.into(),
+        e => io::Error::new(ioErrorKind::InvalidData, e.to_string()).into(),
+    }
+}
@@ -0,0 +1,360 @@
+//! Processing of many containers in one run.
+//!
+//! Containers, or the directories with their sources, are taken from a
+//! directory, a glob pattern or a list file and processed into a tree
+//! mirroring the input one: `in/reports/a.epf` is parsed into the directory
+//! `out/reports/a.epf`, which is built back into the file
+//! `out/reports/a.epf`.
+
+use crate::builder::{self, BuildOptions};
+use crate::container::Result;
+use crate::glob;
+use crate::parser::{self, UnpackOptions};
+
+use log::*;
+use std::io::{self, Error as ioError, ErrorKind as ioErrorKind};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use std::{cmp, fmt, fs, thread};
+
+/// Extensions of the containers taken from a directory.
+const CONTAINER_EXTENSIONS: &[&str] = &["cf", "cfe", "cfu", "epf", "erf"];
+
+/// What to do with each container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchCommand {
+    /// Unpack containers into directories, the same as `parse_to_folder`.
+    Parse,
+    /// Build containers from directories, the same as `build_cf_file`.
+    Build,
+}
+
+/// A container, or a directory with its sources, to process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchInput {
+    pub path: PathBuf,
+    /// Path of the result relative to the output directory.
+    pub relative: PathBuf,
+}
+
+/// Options of processing many containers.
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    command: BatchCommand,
+    jobs: usize,
+    no_deflate: bool,
+}
+
+impl BatchOptions {
+    /// Creates a new instance of `BatchOptions` processing one container
+    /// per available core.
+    pub fn new(command: BatchCommand) -> BatchOptions {
+        BatchOptions {
+            command,
+            jobs: thread::available_parallelism().map_or(1, |n| n.get()),
+            no_deflate: false,
+        }
+    }
+
+    pub fn get_command(&self) -> BatchCommand {
+        self.command
+    }
+
+    /// Sets the number of containers processed at once, `0` is treated as
+    /// `1`.
+    pub fn with_jobs(mut self, value: usize) -> Self {
+        self.jobs = value;
+
+        self
+    }
+
+    pub fn get_jobs(&self) -> usize {
+        cmp::max(self.jobs, 1)
+    }
+
+    /// Builds containers without deflating the elements.
+    pub fn with_no_deflate(mut self, value: bool) -> Self {
+        self.no_deflate = value;
+
+        self
+    }
+
+    pub fn get_no_deflate(&self) -> bool {
+        self.no_deflate
+    }
+}
+
+/// Result of processing a single container.
+#[derive(Debug)]
+pub struct BatchItem {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    /// The error the processing failed with, `None` on success.
+    pub error: Option<crate::error::V8Error>,
+    pub duration: Duration,
+}
+
+/// Results of processing containers in the order of the inputs.
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    pub items: Vec<BatchItem>,
+}
+
+impl BatchReport {
+    pub fn succeeded(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|item| item.error.is_none())
+            .count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.items.len() - self.succeeded()
+    }
+}
+
+impl fmt::Display for BatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "processed: {}, succeeded: {}, failed: {}",
+            self.items.len(),
+            self.succeeded(),
+            self.failed()
+        )
+    }
+}
+
+/// Collects the inputs from `spec`, which is a directory or a glob pattern
+/// like `in/**/*.epf`.
+///
+/// From a directory `BatchCommand::Parse` takes the files and
+/// `BatchCommand::Build` takes the directories with the extensions of
+/// containers, like `.cf` and `.epf`. The paths of the results are relative
+/// to the directory, or to the part of the pattern before the first
+/// component with `*` or `?`.
+pub fn collect_inputs(spec: &str, command: BatchCommand) -> Result<Vec<BatchInput>> {
+    let spec = spec.replace('\\', "/");
+    let mut result = vec![];
+
+    if glob::is_pattern(&spec) {
+        let components: Vec<&str> = spec.split('/').collect();
+        let base_len = components
+            .iter()
+            .position(|component| glob::is_pattern(component))
+            .unwrap_or(components.len());
+        let base = match components[..base_len].join("/") {
+            ref base if base.is_empty() => PathBuf::from("."),
+            base => PathBuf::from(base),
+        };
+        let pattern = components[base_len..].join("/");
+        walk(
+            &base,
+            &base,
+            &mut |path, relative| {
+                glob::matches(&pattern, relative) && is_input_kind(path, command)
+            },
+            &mut result,
+        )?;
+    } else {
+        let base = Path::new(&spec);
+        if !base.is_dir() {
+            return Err(ioError::new(
+                ioErrorKind::InvalidInput,
+                format!("{} is neither a directory nor a pattern", spec),
+            )
+            .into());
+        }
+        walk(
+            base,
+            base,
+            &mut |path, _| is_input_kind(path, command) && has_container_extension(path),
+            &mut result,
+        )?;
+    }
+
+    Ok(result)
+}
+
+/// Reads the inputs from a file with one path per line, skipping empty lines
+/// and lines starting with `#`. Results mirror the paths of the inputs
+/// without the root and the `..` components.
+pub fn read_input_list(list_file: &str) -> Result<Vec<BatchInput>> {
+    let text = fs::read_to_string(list_file)?;
+    let inputs = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let path = PathBuf::from(line);
+            let relative = path
+                .components()
+                .filter_map(|component| match component {
+                    Component::Normal(name) => Some(name),
+                    _ => None,
+                })
+                .collect();
+            BatchInput { path, relative }
+        })
+        .collect();
+
+    Ok(inputs)
+}
+
+/// Processes the inputs on `options.get_jobs()` threads, writing the
+/// results into `out_dir`. A failed container does not stop the others.
+pub fn run_batch(
+    inputs: &[BatchInput],
+    out_dir: &str,
+    options: &BatchOptions,
+) -> BatchReport {
+    let next = AtomicUsize::new(0);
+    let jobs = cmp::min(options.get_jobs(), cmp::max(inputs.len(), 1));
+
+    let mut items: Vec<(usize, BatchItem)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = vec![];
+                    loop {
+                        let index = next.fetch_add(1, Ordering::SeqCst);
+                        let input = match inputs.get(index) {
+                            Some(input) => input,
+                            None => break,
+                        };
+                        let output = Path::new(out_dir).join(&input.relative);
+                        done.push((index, process_input(input, output, options)));
+                    }
+                    done
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap_or_default())
+            .collect()
+    });
+
+    items.sort_by_key(|&(index, _)| index);
+    BatchReport {
+        items: items.into_iter().map(|(_, item)| item).collect(),
+    }
+}
+
+fn process_input(
+    input: &BatchInput,
+    output: PathBuf,
+    options: &BatchOptions,
+) -> BatchItem {
+    info!("batch: {} -> {}", input.path.display(), output.display());
+    let started = Instant::now();
+
+    // A panic fails this container only, the report keeps an item for it.
+    let result = catch_unwind(AssertUnwindSafe(|| match options.get_command() {
+        BatchCommand::Parse => parse_one(&input.path, &output),
+        BatchCommand::Build => build_one(&input.path, &output, options),
+    }))
+    .unwrap_or_else(|_| {
+        Err(ioError::other("Processing of the container panicked").into())
+    });
+    if let Err(ref e) = result {
+        error!("batch: failed to process {}: {}", input.path.display(), e);
+    }
+
+    BatchItem {
+        input: input.path.clone(),
+        output,
+        error: result.err(),
+        duration: started.elapsed(),
+    }
+}
+
+fn parse_one(input: &Path, output: &Path) -> Result<()> {
+    let (input_str, output_str) = (path_str(input)?, path_str(output)?);
+    fs::create_dir_all(output)?;
+    let result = parser::unpack_to_directory_with_options(
+        input_str,
+        output_str,
+        &UnpackOptions::new(),
+    );
+    if result.is_err() {
+        // Do not leave an empty directory to be built into a container.
+        let _ = fs::remove_dir(output);
+    }
+
+    result.map(|_| ())
+}
+
+fn build_one(input: &Path, output: &Path, options: &BatchOptions) -> Result<()> {
+    let (input_str, output_str) = (path_str(input)?, path_str(output)?);
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let build_options = BuildOptions::new()
+        .with_threads(1)
+        .with_no_deflate(options.get_no_deflate());
+    builder::build_cf_file_with_options(input_str, output_str, &build_options)?;
+
+    Ok(())
+}
+
+/// Returns the path as the library takes it. A path which is not valid
+/// UTF-8 fails the container instead of being replaced by a lossy copy
+/// which names another file.
+fn path_str(path: &Path) -> Result<&str> {
+    path.to_str().ok_or_else(|| {
+        ioError::new(
+            ioErrorKind::InvalidInput,
+            format!("The path {} is not valid UTF-8", path.display()),
+        )
+        .into()
+    })
+}
+
+/// A file for `BatchCommand::Parse`, a directory for `BatchCommand::Build`.
+fn is_input_kind(path: &Path, command: BatchCommand) -> bool {
+    match command {
+        BatchCommand::Parse => path.is_file(),
+        BatchCommand::Build => path.is_dir(),
+    }
+}
+
+fn has_container_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            CONTAINER_EXTENSIONS
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(extension))
+        })
+}
+
+/// Walks the tree under `dir` in the order of names, adding the entries
+/// accepted by `accept` to `result`. Accepted directories are not descended
+/// into.
+fn walk(
+    base: &Path,
+    dir: &Path,
+    accept: &mut dyn FnMut(&Path, &str) -> bool,
+    result: &mut Vec<BatchInput>,
+) -> Result<()> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<_>>()?;
+    paths.sort();
+
+    for path in paths {
+        let relative = path.strip_prefix(base).unwrap_or(&path).to_path_buf();
+        let relative_str = relative.to_string_lossy().replace('\\', "/");
+        if accept(&path, &relative_str) {
+            result.push(BatchInput { path, relative });
+        } else if path.is_dir() {
+            walk(base, &path, accept, result)?;
+        }
+    }
+
+    Ok(())
+}
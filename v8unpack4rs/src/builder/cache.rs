@@ -0,0 +1,141 @@
+//! On-disk cache of compressed element blocks.
+//!
+//! A block is stored under the SHA-256 of everything it is built from: the
+//! compression mode and the names, order and content of the source files.
+//! A cached block is therefore byte-for-byte the block a clean build would
+//! produce.
+
+use std::fs;
+use std::io::prelude::*;
+use std::io::ErrorKind as ioErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use log::*;
+use sha2::{Digest, Sha256};
+
+use crate::container::Result;
+
+/// Bump when the layout of built blocks changes to invalidate old caches.
+const CACHE_VERSION: &[u8] = b"v8unpack4rs block cache 1";
+
+/// Directory with compressed blocks keyed by the hash of their sources.
+///
+/// Blocks are never removed: every changed source adds a block and the
+/// blocks of old versions stay, so the directory grows without a bound.
+/// Delete it to reclaim the space, the next build fills it again.
+#[derive(Debug)]
+pub struct BlockCache {
+    dir: PathBuf,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl BlockCache {
+    /// Opens the cache in `dir`, creating the directory if needed.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<BlockCache> {
+        fs::create_dir_all(dir.as_ref())?;
+
+        Ok(BlockCache {
+            dir: dir.as_ref().to_path_buf(),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        })
+    }
+
+    /// Computes the key of the block built from the file or directory `path`.
+    pub fn key(path: &Path, no_deflate: bool) -> Result<String> {
+        let mut hasher = Sha256::new();
+        hasher.update(CACHE_VERSION);
+        hasher.update([no_deflate as u8]);
+        let is_dir = fs::symlink_metadata(path)?.file_type().is_dir();
+        hash_entry(&mut hasher, path, is_dir)?;
+
+        Ok(hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect())
+    }
+
+    /// Returns the cached block, if any.
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.path_of(key)) {
+            Ok(data) => {
+                debug!("block cache hit {}", key);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Ok(Some(data))
+            }
+            Err(ref e) if e.kind() == ioErrorKind::NotFound => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Stores a block in the cache.
+    pub fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        let path = self.path_of(key);
+        let parent = path.parent().unwrap_or(&self.dir);
+        fs::create_dir_all(parent)?;
+
+        // Several threads or processes may build the same block, write
+        // under a unique name and rename to never expose a partial block.
+        let mut tmp = tempfile::NamedTempFile::new_in(parent)?;
+        tmp.write_all(data)?;
+        match tmp.persist(&path) {
+            Ok(_) => Ok(()),
+            // The same block stored by someone else is as good as ours.
+            Err(_) if path.is_file() => Ok(()),
+            Err(e) => Err(e.error.into()),
+        }
+    }
+
+    /// Returns the number of blocks found in the cache.
+    pub fn get_hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of blocks which had to be built.
+    pub fn get_misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    fn path_of(&self, key: &str) -> PathBuf {
+        let (prefix, rest) = key.split_at(2);
+
+        self.dir.join(prefix).join(rest)
+    }
+}
+
+/// Hashes the file or directory `path`. Like the builder, a symbolic link is
+/// taken for a file, even when it points to a directory.
+fn hash_entry(hasher: &mut Sha256, path: &Path, is_dir: bool) -> Result<()> {
+    if is_dir {
+        hasher.update(b"d");
+        // The order of entries is the order in which the builder reads
+        // them, so it is part of the key.
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                // The builder skips the entry too.
+                Err(_) => continue,
+            };
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            hasher.update((name.len() as u64).to_le_bytes());
+            hasher.update(name.as_bytes());
+            hash_entry(hasher, &entry.path(), file_type.is_dir())?;
+        }
+        hasher.update(b"e");
+    } else {
+        let data = fs::read(path)?;
+        hasher.update(b"f");
+        hasher.update((data.len() as u64).to_le_bytes());
+        hasher.update(&data);
+    }
+
+    Ok(())
+}
@@ -1,8 +1,35 @@
+pub mod cache;
+
+use self::cache::BlockCache;
+use crate::cancel::CancellationToken;
 use crate::container::*;
+use crate::filter::ElemFilter;
+use crate::progress::{Progress, NO_PROGRESS};
+use crate::source::{DirSource, InputSource, SourceEntry};
+use deflate::write::DeflateEncoder;
+use deflate::Compression;
 use log::*;
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::io::prelude::*;
-use std::io::{Error as ioError, ErrorKind as ioErrorKind, Read, SeekFrom, Write};
-use std::{cmp, ffi::OsStr, fs, path, u32};
+use std::io::{
+    self, BufWriter, Cursor, Error as ioError, ErrorKind as ioErrorKind, Read, SeekFrom,
+    Write,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, spawn, JoinHandle};
+use std::{cmp, ffi::OsStr, fs, path};
+
+/// Size of the buffer in front of the output file.
+const OUT_BUFFER_SIZE: usize = 1 << 16;
+
+/// Size of the chunks of large elements deflated in parallel.
+const DEFLATE_CHUNK_SIZE: usize = 1 << 20;
+
+/// Source of the padding written after blocks.
+static ZEROS: [u8; 4096] = [0; 4096];
 
 #[derive(Debug)]
 struct PackElementEntry {
@@ -12,6 +39,15 @@ struct PackElementEntry {
     data_size: u64,
 }
 
+impl PackElementEntry {
+    fn name(&self) -> String {
+        match self.header_file.file_stem() {
+            Some(stem) => stem.to_string_lossy().into_owned(),
+            None => String::new(),
+        }
+    }
+}
+
 fn prepare_pack_files(dirname: &str) -> Result<Vec<PackElementEntry>> {
     let files = fs::read_dir(dirname)?.filter(|p| {
         if let Ok(entry) = p {
@@ -26,22 +62,20 @@ fn prepare_pack_files(dirname: &str) -> Result<Vec<PackElementEntry>> {
     });
 
     let mut pack_elements = vec![];
-    for file in files {
-        if let Ok(entry) = file {
-            let header_file = entry.path();
-            let header_size = entry.metadata()?.len();
-
-            let mut data_file = entry.path();
-            data_file.set_extension(OsStr::new("data"));
-            let data_size = fs::metadata(data_file.clone())?.len();
-
-            pack_elements.push(PackElementEntry {
-                header_file,
-                data_file,
-                header_size,
-                data_size,
-            });
-        }
+    for entry in files.flatten() {
+        let header_file = entry.path();
+        let header_size = entry.metadata()?.len();
+
+        let mut data_file = entry.path();
+        data_file.set_extension(OsStr::new("data"));
+        let data_size = fs::metadata(data_file.clone())?.len();
+
+        pack_elements.push(PackElementEntry {
+            header_file,
+            data_file,
+            header_size,
+            data_size,
+        });
     }
 
     Ok(pack_elements)
@@ -52,44 +86,36 @@ pub fn pack_from_folder(dirname: &str, filename_out: &str) -> Result<bool> {
     fs::copy(
         path::Path::new(dirname).join("FileHeader"),
         path::Path::new(filename_out),
-    )
-    .expect("SaveFile. Error in creating file!");
+    )?;
 
-    let mut file_out = fs::OpenOptions::new().append(true).open(filename_out)?;
+    let file_out = fs::OpenOptions::new().append(true).open(filename_out)?;
+    let mut file_out = BufWriter::with_capacity(OUT_BUFFER_SIZE, file_out);
     let pack_elements = prepare_pack_files(dirname)?;
 
     save_elem_addrs(&pack_elements, &mut file_out)?;
     save_data(pack_elements, &mut file_out)?;
+    file_out.flush()?;
 
     Ok(true)
 }
 
-fn save_elem_addrs(
+fn save_elem_addrs<W: Write>(
     pack_elems: &[PackElementEntry],
-    file_out: &mut fs::File,
+    file_out: &mut W,
 ) -> Result<()> {
     let mut elem_addrs_bytes: Vec<u8> =
         Vec::with_capacity(pack_elems.len() * ElemAddr::SIZE as usize);
-    let mut cur_elem_addr = FileHeader::SIZE + BlockHeader::SIZE;
-
-    cur_elem_addr += cmp::max(
-        ElemAddr::SIZE * pack_elems.len() as u32,
-        V8_DEFAULT_PAGE_SIZE,
-    );
+    let mut cur_elem_addr = toc_end_addr(pack_elems.len())?;
 
     for pack_elem in pack_elems {
         let elem_header_addr = cur_elem_addr;
-        if pack_elem.header_size > u64::from(u32::MAX) {
-            ioError::new(ioErrorKind::InvalidData, "Invalid header length");
-        }
-        cur_elem_addr += BlockHeader::SIZE + pack_elem.header_size as u32;
+        cur_elem_addr = block_end_addr(cur_elem_addr, pack_elem.header_size, 0)
+            .map_err(|e| e.in_elem(&pack_elem.name()))?;
 
         let elem_data_addr = cur_elem_addr;
-        cur_elem_addr += BlockHeader::SIZE;
-        if pack_elem.data_size > u64::from(u32::MAX) {
-            ioError::new(ioErrorKind::InvalidData, "Invalid data length");
-        }
-        cur_elem_addr += cmp::max(pack_elem.data_size as u32, V8_DEFAULT_PAGE_SIZE);
+        cur_elem_addr =
+            block_end_addr(cur_elem_addr, pack_elem.data_size, V8_DEFAULT_PAGE_SIZE)
+                .map_err(|e| e.in_elem(&pack_elem.name()))?;
 
         elem_addrs_bytes
             .extend(ElemAddr::new(elem_data_addr, elem_header_addr).into_bytes()?);
@@ -100,13 +126,17 @@ fn save_elem_addrs(
     Ok(())
 }
 
-fn save_data(pack_elems: Vec<PackElementEntry>, file_out: &mut fs::File) -> Result<()> {
+fn save_data<W: Write>(
+    pack_elems: Vec<PackElementEntry>,
+    file_out: &mut W,
+) -> Result<()> {
     for elem in pack_elems {
         {
             let mut header_file = fs::File::open(elem.header_file)?;
             let mut buf = vec![];
             header_file.read_to_end(&mut buf)?;
-            save_block_data(file_out, &buf, elem.header_size as u32)?;
+            let page_size = format_u32(elem.header_size, "Block size")?;
+            save_block_data(file_out, &buf, page_size)?;
         }
         {
             let mut data_file = fs::File::open(elem.data_file)?;
@@ -119,67 +149,268 @@ fn save_data(pack_elems: Vec<PackElementEntry>, file_out: &mut fs::File) -> Resu
     Ok(())
 }
 
-fn save_block_data(
-    file_out: &mut fs::File,
+fn save_block_data<W: Write>(
+    file_out: &mut W,
     block_data: &[u8],
     page_size: u32,
-) -> Result<usize> {
-    if block_data.len() > u32::MAX as usize {
-        ioError::new(ioErrorKind::InvalidData, "Invalid data length");
-    }
-
-    let block_size = block_data.len() as u32;
+) -> Result<()> {
+    let block_size = format_u32(block_data.len() as u64, "Block size")?;
     let page_size_actual = if page_size < block_size {
         block_size
     } else {
         page_size
     };
 
-    let mut write_bytes: usize = 0;
     let block_header = BlockHeader::new(block_size, page_size_actual, V8_MAGIC_NUMBER);
 
-    let bh_bytes = block_header.into_bytes()?;
-    file_out.write_all(&bh_bytes)?;
-    write_bytes += bh_bytes.len();
-    file_out.write_all(&block_data)?;
-    write_bytes += block_data.len();
-
+    file_out.write_all(&block_header.into_bytes()?)?;
+    file_out.write_all(block_data)?;
     write_terminal_zeros(file_out, page_size_actual - block_size)?;
-    write_bytes += (page_size_actual - block_size) as usize;
 
-    Ok(write_bytes)
+    Ok(())
 }
 
-fn write_terminal_zeros(file_out: &mut fs::File, count: u32) -> Result<()> {
-    let mut i = 0;
-    while i < count {
-        file_out.write_all(b"\0")?;
-        i += 1;
+/// Writes `count` zero bytes, a chunk at a time.
+fn write_terminal_zeros<W: Write>(file_out: &mut W, count: u32) -> Result<()> {
+    let mut left = count as usize;
+    while left > 0 {
+        let chunk = cmp::min(left, ZEROS.len());
+        file_out.write_all(&ZEROS[..chunk])?;
+        left -= chunk;
     }
 
     Ok(())
 }
 
+/// Options of building a container from source files.
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    no_deflate: bool,
+    threads: usize,
+    cache: Option<Arc<BlockCache>>,
+    filter: ElemFilter,
+    progress: Option<Arc<dyn Progress>>,
+    cancellation: CancellationToken,
+}
+
+impl Default for BuildOptions {
+    fn default() -> BuildOptions {
+        BuildOptions {
+            no_deflate: false,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            cache: None,
+            filter: ElemFilter::new(),
+            progress: None,
+            cancellation: CancellationToken::new(),
+        }
+    }
+}
+
+impl BuildOptions {
+    /// Creates a new instance of `BuildOptions` with one compression thread
+    /// per available core.
+    pub fn new() -> BuildOptions {
+        BuildOptions::default()
+    }
+
+    /// Stores elements as is, without deflating them.
+    pub fn with_no_deflate(mut self, value: bool) -> Self {
+        self.no_deflate = value;
+
+        self
+    }
+
+    pub fn get_no_deflate(&self) -> bool {
+        self.no_deflate
+    }
+
+    /// Sets the number of threads compressing elements, `0` is treated as `1`.
+    pub fn with_threads(mut self, value: usize) -> Self {
+        self.threads = value;
+
+        self
+    }
+
+    pub fn get_threads(&self) -> usize {
+        cmp::max(self.threads, 1)
+    }
+
+    /// Reuses compressed blocks of unchanged files and directories from
+    /// `cache` and stores newly built ones there.
+    pub fn with_cache(mut self, value: BlockCache) -> Self {
+        self.cache = Some(Arc::new(value));
+
+        self
+    }
+
+    pub fn get_cache(&self) -> Option<&BlockCache> {
+        self.cache.as_deref()
+    }
+
+    /// Builds the container of the source files selected by `filter`. The
+    /// cache is not used for the directories of nested containers while the
+    /// filter is set.
+    pub fn with_filter(mut self, value: ElemFilter) -> Self {
+        self.filter = value;
+
+        self
+    }
+
+    pub fn get_filter(&self) -> &ElemFilter {
+        &self.filter
+    }
+
+    /// Reports the progress of building to `progress`.
+    pub fn with_progress(mut self, value: Arc<dyn Progress>) -> Self {
+        self.progress = Some(value);
+
+        self
+    }
+
+    pub fn get_progress(&self) -> &dyn Progress {
+        self.progress.as_deref().unwrap_or(&NO_PROGRESS)
+    }
+
+    /// Stops building with `V8Error::Cancelled` once `token` is cancelled.
+    /// The incomplete output file is removed.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+
+        self
+    }
+
+    pub fn get_cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+}
+
+/// Builds a container from source files, compressing elements on all
+/// available cores.
 pub fn build_cf_file(
     dirname: &str,
     filename_out: &str,
     no_deflate: bool,
 ) -> Result<bool> {
-    let elems_num: u32 = fs::read_dir(dirname)?
-        .filter(|p| p.is_ok())
-        .fold(0, |sum, _| sum + 1);
-    let mut toc: Vec<ElemAddr> = Vec::with_capacity(elems_num as usize);
-    let mut cur_block_addr = FileHeader::SIZE + BlockHeader::SIZE;
-    cur_block_addr += cmp::max(ElemAddr::SIZE * elems_num, V8_DEFAULT_PAGE_SIZE);
-
-    let mut file_out = fs::File::create(filename_out)?;
+    build_cf_file_with_options(
+        dirname,
+        filename_out,
+        &BuildOptions::new().with_no_deflate(no_deflate),
+    )
+}
+
+/// Builds a container from source files.
+///
+/// Elements are compressed in parallel by `options.get_threads()` threads
+/// and written to the output in the order they were read from `dirname`.
+pub fn build_cf_file_with_options(
+    dirname: &str,
+    filename_out: &str,
+    options: &BuildOptions,
+) -> Result<bool> {
+    build_cf_file_from_source(Arc::new(DirSource::new(dirname)), filename_out, options)
+}
+
+/// Builds a container from the source files of `source`, like an archive,
+/// the same way as `build_cf_file_with_options` does. The block cache is
+/// used only for the entries of a source on disk.
+pub fn build_cf_file_from_source(
+    source: Arc<dyn InputSource>,
+    filename_out: &str,
+    options: &BuildOptions,
+) -> Result<bool> {
+    let entries = select_entries(source.as_ref(), options)?;
+    let cur_block_addr = toc_end_addr(entries.len())?;
+
+    options.get_progress().begin(Some(entries.len()), None);
+
+    let file_out = fs::File::create(filename_out)?;
+    let result = write_container(&source, entries, file_out, cur_block_addr, options);
+    if result.is_err() {
+        // Do not leave an incomplete container behind.
+        info!("remove the incomplete container {}", filename_out);
+        let _ = fs::remove_file(filename_out);
+    }
+    result?;
+    options.get_progress().end();
+
+    Ok(true)
+}
+
+/// Builds a container from source files and writes it to `out`, like the
+/// standard output, which does not have to support seeking. The container
+/// is built in a temporary file first.
+pub fn build_cf_to_writer<W: Write>(
+    dirname: &str,
+    out: &mut W,
+    options: &BuildOptions,
+) -> Result<()> {
+    build_cf_to_writer_from_source(Arc::new(DirSource::new(dirname)), out, options)
+}
+
+/// Builds a container from the source files of `source` and writes it to
+/// `out`, see `build_cf_to_writer`.
+pub fn build_cf_to_writer_from_source<W: Write>(
+    source: Arc<dyn InputSource>,
+    out: &mut W,
+    options: &BuildOptions,
+) -> Result<()> {
+    let entries = select_entries(source.as_ref(), options)?;
+    let cur_block_addr = toc_end_addr(entries.len())?;
+
+    options.get_progress().begin(Some(entries.len()), None);
+
+    let mut file = tempfile::tempfile()?;
+    write_container(&source, entries, &mut file, cur_block_addr, options)?;
+    file.seek(SeekFrom::Start(0))?;
+    io::copy(&mut file, out)?;
+    out.flush()?;
+    options.get_progress().end();
+
+    Ok(())
+}
+
+/// Builds a container from the source files of `source` in memory, like
+/// from a `MemTree`, and returns its data.
+pub fn build_to_vec(
+    source: Arc<dyn InputSource>,
+    options: &BuildOptions,
+) -> Result<Vec<u8>> {
+    let entries = select_entries(source.as_ref(), options)?;
+    let cur_block_addr = toc_end_addr(entries.len())?;
+
+    options.get_progress().begin(Some(entries.len()), None);
+
+    let mut out = Cursor::new(vec![]);
+    write_container(&source, entries, &mut out, cur_block_addr, options)?;
+    options.get_progress().end();
+
+    Ok(out.into_inner())
+}
+
+fn write_container<F: Write + Seek>(
+    source: &Arc<dyn InputSource>,
+    entries: Vec<SourceEntry>,
+    file_out: F,
+    cur_block_addr: u32,
+    options: &BuildOptions,
+) -> Result<()> {
+    let mut toc: Vec<ElemAddr> = Vec::with_capacity(entries.len());
+    let mut file_out = BufWriter::with_capacity(OUT_BUFFER_SIZE, file_out);
     write_terminal_zeros(&mut file_out, cur_block_addr)?;
     toc.extend(process_files(
-        dirname,
+        source,
+        entries,
         &mut file_out,
         cur_block_addr,
-        no_deflate,
+        options,
     )?);
+    if let Some(cache) = options.get_cache() {
+        info!(
+            "block cache: {} hits, {} misses",
+            cache.get_hits(),
+            cache.get_misses()
+        );
+    }
 
     let file_header = FileHeader::new(V8_MAGIC_NUMBER, V8_DEFAULT_PAGE_SIZE, 0);
     file_out.seek(SeekFrom::Start(0))?;
@@ -188,110 +419,340 @@ pub fn build_cf_file(
     for toc_elm in toc.into_iter() {
         toc_bytes.extend(toc_elm.into_bytes()?);
     }
-    save_block_data(&mut file_out, &toc_bytes, toc_bytes.len() as u32)?;
+    let toc_size = format_u32(toc_bytes.len() as u64, "Block size")?;
+    save_block_data(&mut file_out, &toc_bytes, toc_size)?;
+    file_out.flush()?;
 
-    Ok(true)
+    Ok(())
 }
 
-fn process_files(
-    dirname: &str,
-    file_out: &mut fs::File,
+/// Reads the source entries selected by the filter of `options`.
+fn select_entries(
+    source: &dyn InputSource,
+    options: &BuildOptions,
+) -> Result<Vec<SourceEntry>> {
+    let mut entries = source.read_dir("")?;
+    entries.retain(|entry| options.filter.selects_source(&entry.name, entry.is_dir));
+
+    Ok(entries)
+}
+
+struct CompressJob {
+    name: String,
+    is_dir: bool,
+    result: SyncSender<Result<Vec<u8>>>,
+}
+
+fn start_compress_threads(
+    source: &Arc<dyn InputSource>,
+    options: &BuildOptions,
+) -> (Sender<CompressJob>, Vec<JoinHandle<()>>) {
+    let (sender, receiver) = channel::<CompressJob>();
+    let receiver = Arc::new(Mutex::new(receiver));
+
+    let no_deflate = options.no_deflate;
+    let threads = options.get_threads();
+    let handles = (0..threads)
+        .map(|_| {
+            let receiver = Arc::clone(&receiver);
+            let source = Arc::clone(source);
+            let cache = options.cache.clone();
+            let filter = options.filter.clone();
+            let cancellation = options.cancellation.clone();
+
+            spawn(move || loop {
+                let job = match receiver.lock() {
+                    Ok(jobs) => match jobs.recv() {
+                        Ok(job) => job,
+                        Err(_) => break,
+                    },
+                    Err(_) => break,
+                };
+
+                let data = cancellation.check().and_then(|_| {
+                    compress_entry(
+                        source.as_ref(),
+                        &job,
+                        no_deflate,
+                        &filter,
+                        cache.as_deref(),
+                        threads,
+                    )
+                });
+
+                if job.result.send(data).is_err() {
+                    break;
+                }
+            })
+        })
+        .collect();
+
+    (sender, handles)
+}
+
+fn process_files<W: Write>(
+    source: &Arc<dyn InputSource>,
+    entries: Vec<SourceEntry>,
+    file_out: &mut W,
     cur_block_addr: u32,
-    no_deflate: bool,
+    options: &BuildOptions,
+) -> Result<Vec<ElemAddr>> {
+    let (jobs, handles) = start_compress_threads(source, options);
+
+    let window = options.get_threads() * 2;
+    let result = write_elements(
+        entries,
+        file_out,
+        cur_block_addr,
+        &jobs,
+        window,
+        options.get_progress(),
+        &options.cancellation,
+    );
+
+    drop(jobs);
+    for handle in handles {
+        if handle.join().is_err() {
+            error!("Compression thread panicked");
+        }
+    }
+
+    result
+}
+
+/// Sends the elements to the compression threads keeping at most `window`
+/// of them in flight, and writes the results in the order of `entries`.
+fn write_elements<W: Write>(
+    entries: Vec<SourceEntry>,
+    file_out: &mut W,
+    cur_block_addr: u32,
+    jobs: &Sender<CompressJob>,
+    window: usize,
+    progress: &dyn Progress,
+    cancellation: &CancellationToken,
 ) -> Result<Vec<ElemAddr>> {
     let mut result = vec![];
     let mut cur_block_addr = cur_block_addr;
-    for entry in fs::read_dir(dirname)? {
-        let entry = entry?;
-        if let Ok(name) = entry.file_name().into_string() {
-            let header = vec![0; ElemHeaderBegin::SIZE as usize];
-            let mut element = V8Elem::new().with_header(header);
-            element.set_name(&name);
-
-            let elem_header_addr = cur_block_addr;
-            {
-                let elem_header = element.get_header();
-                cur_block_addr +=
-                    save_block_data(file_out, elem_header, elem_header.len() as u32)?
-                        as u32;
+    let mut entries = entries.into_iter();
+    let mut pending: VecDeque<(String, Receiver<Result<Vec<u8>>>)> = VecDeque::new();
+
+    loop {
+        cancellation.check()?;
+        while pending.len() < window {
+            let entry = match entries.next() {
+                Some(entry) => entry,
+                None => break,
+            };
+
+            let (sender, receiver) = sync_channel(1);
+            let job = CompressJob {
+                name: entry.name.clone(),
+                is_dir: entry.is_dir,
+                result: sender,
+            };
+            if jobs.send(job).is_err() {
+                return Err(ioError::new(
+                    ioErrorKind::BrokenPipe,
+                    "Compression threads have stopped",
+                )
+                .into());
             }
-            let elem_data_addr = cur_block_addr;
-
-            let elem_addr = ElemAddr::new(elem_data_addr, elem_header_addr);
-            result.push(elem_addr);
-
-            if let Ok(file_type) = entry.file_type() {
-                if file_type.is_dir() {
-                    process_directory(
-                        file_out,
-                        &mut element,
-                        dirname,
-                        &name,
-                        no_deflate,
-                        &mut cur_block_addr,
-                    )?;
-                } else {
-                    process_v8file(
-                        file_out,
-                        &mut element,
-                        dirname,
-                        &name,
-                        no_deflate,
-                        &mut cur_block_addr,
-                    )?;
-                }
-            } else {
-                error!("Couldn't get file type for {:?}", entry.path());
+            pending.push_back((entry.name, receiver));
+        }
+
+        let (name, receiver) = match pending.pop_front() {
+            Some(next) => next,
+            None => break,
+        };
+        progress.elem_started(&name);
+
+        let data = match receiver.recv() {
+            Ok(data) => data?,
+            Err(_) => {
+                return Err(ioError::new(
+                    ioErrorKind::BrokenPipe,
+                    "Compression thread has stopped",
+                )
+                .into());
             }
+        };
+
+        let header = vec![0; ElemHeaderBegin::SIZE as usize];
+        let mut element = V8Elem::new().with_header(header);
+        element.set_name(&name);
+
+        let elem_header = element.get_header();
+        let elem_header_addr = cur_block_addr;
+        let elem_data_addr =
+            block_end_addr(elem_header_addr, elem_header.len() as u64, 0)
+                .map_err(|e| e.in_elem(&name))?;
+        cur_block_addr = block_end_addr(elem_data_addr, data.len() as u64, 0)
+            .map_err(|e| e.in_elem(&name))?;
+
+        save_block_data(file_out, elem_header, elem_header.len() as u32)?;
+        save_block_data(file_out, &data, data.len() as u32)?;
+        progress.bytes_written(
+            u64::from(cur_block_addr).saturating_sub(u64::from(elem_header_addr)),
+        );
+        progress.elem_finished(&name);
+
+        result.push(ElemAddr::new(elem_data_addr, elem_header_addr));
+    }
+
+    Ok(result)
+}
+
+fn compress_entry(
+    source: &dyn InputSource,
+    job: &CompressJob,
+    no_deflate: bool,
+    filter: &ElemFilter,
+    cache: Option<&BlockCache>,
+    threads: usize,
+) -> Result<Vec<u8>> {
+    let build = || {
+        if job.is_dir {
+            process_directory(source, &job.name, no_deflate, filter, threads)
         } else {
-            error!("Couldn't get file name for {:?}", entry.path());
+            process_v8file(source, &job.name, no_deflate, threads)
+        }
+    };
+
+    // The key of a directory does not account for the filtered out files.
+    let (cache, local_path) = match (cache, source.local_path(&job.name)) {
+        (Some(cache), Some(local_path)) if !job.is_dir || filter.is_empty() => {
+            (cache, local_path)
         }
+        _ => return build(),
+    };
+
+    let key = BlockCache::key(&local_path, no_deflate)?;
+    if let Some(data) = cache.get(&key)? {
+        return Ok(data);
     }
 
-    Ok(result)
+    let data = build()?;
+    cache.put(&key, &data)?;
+
+    Ok(data)
 }
+
 fn process_directory(
-    file_out: &mut fs::File,
-    element: &mut V8Elem,
-    dirname: &str,
+    source: &dyn InputSource,
     name: &str,
     no_deflate: bool,
-    cur_elem_addr: &mut u32,
-) -> Result<()> {
-    let new_dir = path::Path::new(dirname).join(name);
+    filter: &ElemFilter,
+    threads: usize,
+) -> Result<Vec<u8>> {
     let mut v8 = V8File::new();
-    v8.load_file_from_folder(new_dir)?;
-    element.set_v8file(true);
-    element.set_unpacked_data(Some(v8));
-    element.pack(!no_deflate)?;
+    v8.load_from_source(source, name, filter)?;
+    let data = v8.get_data()?;
 
-    if let Some(data) = element.get_data() {
-        *cur_elem_addr += save_block_data(file_out, data, data.len() as u32)? as u32;
+    if no_deflate {
+        Ok(data)
+    } else {
+        deflate_data(&data, threads)
     }
-
-    Ok(())
 }
 
 fn process_v8file(
-    file_out: &mut fs::File,
-    element: &mut V8Elem,
-    dirname: &str,
+    source: &dyn InputSource,
     name: &str,
     no_deflate: bool,
-    cur_block_addr: &mut u32,
-) -> Result<()> {
-    element.set_v8file(false);
-    let mut data = vec![];
-    let p_file = path::Path::new(dirname).join(name);
-    let mut cur_file = fs::File::open(p_file)?;
-    cur_file.read_to_end(&mut data)?;
+    threads: usize,
+) -> Result<Vec<u8>> {
+    let data = source.read_file(name)?;
 
-    element.set_data(Some(data));
-    element.pack(!no_deflate)?;
+    if no_deflate {
+        Ok(data)
+    } else {
+        deflate_data(&data, threads)
+    }
+}
 
-    if let Some(data) = element.get_data() {
-        *cur_block_addr += save_block_data(file_out, data, data.len() as u32)? as u32;
+/// Deflates `data` like `deflate::deflate_bytes` does. Data larger than
+/// `DEFLATE_CHUNK_SIZE`, like a large nested container, is split into chunks
+/// compressed on up to `threads` threads of its own. Every chunk but the last
+/// ends with a sync flush, so the chunks join into one deflate stream, and
+/// the result does not depend on the number of threads.
+fn deflate_data(data: &[u8], threads: usize) -> Result<Vec<u8>> {
+    if data.len() <= DEFLATE_CHUNK_SIZE {
+        return Ok(deflate::deflate_bytes(data));
     }
 
-    Ok(())
+    let chunks: Vec<&[u8]> = data.chunks(DEFLATE_CHUNK_SIZE).collect();
+    let next = AtomicUsize::new(0);
+    let mut compressed = thread::scope(|scope| -> Result<Vec<(usize, Vec<u8>)>> {
+        let handles: Vec<_> = (0..cmp::min(threads, chunks.len()))
+            .map(|_| {
+                scope.spawn(|| -> Result<Vec<(usize, Vec<u8>)>> {
+                    let mut done = vec![];
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let chunk = match chunks.get(index) {
+                            Some(chunk) => chunk,
+                            None => return Ok(done),
+                        };
+                        let last = index + 1 == chunks.len();
+                        done.push((index, deflate_chunk(chunk, last)?));
+                    }
+                })
+            })
+            .collect();
+
+        let mut compressed = vec![];
+        for handle in handles {
+            match handle.join() {
+                Ok(done) => compressed.extend(done?),
+                Err(_) => {
+                    return Err(ioError::other("Compression thread panicked").into())
+                }
+            }
+        }
+
+        Ok(compressed)
+    })?;
+
+    compressed.sort_by_key(|&(index, _)| index);
+    let mut result = Vec::with_capacity(compressed.iter().map(|(_, c)| c.len()).sum());
+    for (_, chunk) in compressed {
+        result.extend_from_slice(&chunk);
+    }
+
+    Ok(result)
+}
+
+/// Deflates a chunk of the data, ending the stream after the `last` chunk
+/// and with a sync flush, which continues it, after the others.
+fn deflate_chunk(chunk: &[u8], last: bool) -> Result<Vec<u8>> {
+    if last {
+        return Ok(deflate::deflate_bytes(chunk));
+    }
+
+    let out = RefCell::new(Some(Vec::with_capacity(chunk.len() / 3)));
+    let mut encoder = DeflateEncoder::new(ChunkOutput(&out), Compression::Default);
+    encoder.write_all(chunk)?;
+    encoder.flush()?;
+    let data = out.borrow_mut().take().unwrap_or_default();
+    // The final block written when the encoder is dropped is discarded.
+    drop(encoder);
+
+    Ok(data)
+}
+
+/// Output of `deflate_chunk` which discards the data once it is taken.
+struct ChunkOutput<'a>(&'a RefCell<Option<Vec<u8>>>);
+
+impl Write for ChunkOutput<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(ref mut data) = *self.0.borrow_mut() {
+            data.extend_from_slice(buf);
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
 }
@@ -0,0 +1,43 @@
+//! Cancellation of unpacking and building.
+
+use crate::container::Result;
+use crate::error::V8Error;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Flag shared between the caller and a running operation, which stops the
+/// operation with `V8Error::Cancelled` once set.
+///
+/// The token is checked between elements and between pages of a block, so
+/// an operation stops soon after `cancel` is called from any thread. Clones
+/// share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Creates a new instance of `CancellationToken` which is not cancelled.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Requests the operations using this token to stop.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns `V8Error::Cancelled` if the cancellation was requested.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(V8Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -1,9 +1,8 @@
 use std::io::prelude::*;
-use std::io::{BufReader, Cursor, Error as ioError, ErrorKind as ioErrorKind, SeekFrom};
-use std::{cmp, fmt, fs, path, result, str, u32};
+use std::io::{BufReader, Cursor, ErrorKind as ioErrorKind, SeekFrom};
+use std::{cmp, fmt, fs, path, result, str};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use std::convert::AsMut;
 
 use encoding::all::UTF_16LE;
 use encoding::{EncoderTrap, Encoding};
@@ -12,6 +11,9 @@ use deflate;
 use log::*;
 
 use crate::error;
+use crate::filter::ElemFilter;
+use crate::sink::{DirSink, OutputSink};
+use crate::source::{DirSource, InputSource};
 
 pub type Result<T> = result::Result<T, error::V8Error>;
 
@@ -20,6 +22,10 @@ pub const V8_DEFAULT_PAGE_SIZE: u32 = 512;
 /// Indicates that no further data.
 pub const V8_MAGIC_NUMBER: u32 = 0x7fff_ffff;
 
+/// The largest size and offset of the 32-bit container format, which limits
+/// the container to 4 GiB.
+pub const V8_MAX_ADDR: u64 = 0xffff_ffff;
+
 /// Trait for to get basic information about the container.
 pub trait V8Container {
     /// This method checks that the container is actually the correct file of
@@ -125,16 +131,21 @@ impl FileHeader {
     where
         R: Read + Seek,
     {
-        let mut buf = vec![];
-        let read_bytes = src.take(u64::from(Self::SIZE)).read_to_end(&mut buf)?;
-        if read_bytes < Self::SIZE as usize {
-            return Err(error::V8Error::IoError(ioError::new(
-                ioErrorKind::InvalidData,
-                "Readied too few bytes",
-            )));
+        let offset = src.stream_position()?;
+        let mut buf = [0; Self::SIZE as usize];
+        read_exact_or_invalid(src, &mut buf)?;
+
+        Self::from_bytes(&buf, offset)
+    }
+
+    /// Creates an instance of `FileHeader` from the beginning of a slice,
+    /// `offset` of the slice in the container is reported on errors.
+    pub fn from_bytes(src: &[u8], offset: u64) -> Result<FileHeader> {
+        if src.len() < Self::SIZE as usize {
+            return Err(truncated_block(offset));
         }
 
-        let mut rdr = Cursor::new(buf);
+        let mut rdr = Cursor::new(src);
         let _next_page_addr = rdr.read_u32::<LittleEndian>()?;
         let _page_size = rdr.read_u32::<LittleEndian>()?;
         let _storage_ver = rdr.read_u32::<LittleEndian>()?;
@@ -148,6 +159,22 @@ impl FileHeader {
         })
     }
 
+    /// Gets the offset of the first free page, `V8_MAGIC_NUMBER` if there
+    /// is none.
+    pub fn get_next_page_addr(&self) -> u32 {
+        self.next_page_addr
+    }
+
+    /// Gets the default size of the pages of the container.
+    pub fn get_page_size(&self) -> u32 {
+        self.page_size
+    }
+
+    /// Gets the version of the storage format.
+    pub fn get_storage_ver(&self) -> u32 {
+        self.storage_ver
+    }
+
     pub fn into_bytes(self) -> Result<Vec<u8>> {
         let mut result = Vec::new();
 
@@ -195,9 +222,9 @@ impl Default for BlockHeader {
 
 impl fmt::Display for BlockHeader {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let data_size_hex = str::from_utf8(&self.data_size_hex).unwrap();
-        let page_size_hex = str::from_utf8(&self.page_size_hex).unwrap();
-        let next_page_addr_hex = str::from_utf8(&self.next_page_addr_hex).unwrap();
+        let data_size_hex = String::from_utf8_lossy(&self.data_size_hex);
+        let page_size_hex = String::from_utf8_lossy(&self.page_size_hex);
+        let next_page_addr_hex = String::from_utf8_lossy(&self.next_page_addr_hex);
 
         write!(
             f,
@@ -208,11 +235,16 @@ impl fmt::Display for BlockHeader {
 }
 
 fn convert(value: u32) -> [u8; 8] {
-    let hex = format!("{:08x}", value);
-    let bytes = hex.into_bytes();
-    let arr: [u8; 8] = clone_into_array(&bytes[0..8]);
+    hex_field(format!("{:08x}", value).as_bytes())
+}
+
+/// Copies the 8 hexadecimal digits of a field of `BlockHeader`.
+fn hex_field(src: &[u8]) -> [u8; 8] {
+    let mut field = [0; 8];
+    let len = cmp::min(field.len(), src.len());
+    field[..len].copy_from_slice(&src[..len]);
 
-    arr
+    field
 }
 
 impl BlockHeader {
@@ -220,60 +252,43 @@ impl BlockHeader {
     pub const SIZE: u32 = 1 + 1 + 8 + 1 + 8 + 1 + 8 + 1 + 1 + 1;
 
     pub fn new(data_size: u32, page_size: u32, next_page_addr: u32) -> BlockHeader {
-        let mut default = BlockHeader::default();
-
-        default.data_size_hex = convert(data_size);
-        default.page_size_hex = convert(page_size);
-        default.next_page_addr_hex = convert(next_page_addr);
-
-        default
+        BlockHeader {
+            data_size_hex: convert(data_size),
+            page_size_hex: convert(page_size),
+            next_page_addr_hex: convert(next_page_addr),
+            ..BlockHeader::default()
+        }
     }
     /// Creates an instance of `BlockHeader` from a stream of bytes.
     pub fn from_raw_parts<R>(src: &mut R) -> Result<BlockHeader>
     where
         R: Read + Seek,
     {
-        let mut buf = vec![];
-        let read_bytes = src.take(u64::from(Self::SIZE)).read_to_end(&mut buf)?;
-        if read_bytes < Self::SIZE as usize {
-            return Err(error::V8Error::IoError(ioError::new(
-                ioErrorKind::InvalidData,
-                "Readied too few bytes",
-            )));
-        }
-
-        let mut rdr = Cursor::new(buf);
-        let _eol_0d = rdr.read_u8()?;
-        let _eol_oa = rdr.read_u8()?;
+        let offset = src.stream_position()?;
+        let mut buf = [0; Self::SIZE as usize];
+        read_exact_or_invalid(src, &mut buf)?;
 
-        let _data_size_hex = clone_into_array(&rdr.get_ref()[2..10]);
-
-        rdr.set_position(10);
-        let _space1 = rdr.read_u8()?;
-
-        let _page_size_hex = clone_into_array(&rdr.get_ref()[11..19]);
-
-        rdr.set_position(19);
-        let _space2 = rdr.read_u8()?;
-
-        let mut _next_page_addr_hex = clone_into_array(&rdr.get_ref()[20..28]);
+        Self::from_bytes(&buf, offset)
+    }
 
-        rdr.set_position(28);
-        let _space3 = rdr.read_u8()?;
-        let _eol2_0d = rdr.read_u8()?;
-        let _eol2_oa = rdr.read_u8()?;
+    /// Creates an instance of `BlockHeader` from the beginning of a slice,
+    /// `offset` of the slice in the container is reported on errors.
+    pub fn from_bytes(src: &[u8], offset: u64) -> Result<BlockHeader> {
+        if src.len() < Self::SIZE as usize {
+            return Err(truncated_block(offset));
+        }
 
         Ok(BlockHeader {
-            eol_0d: _eol_0d,
-            eol_0a: _eol_oa,
-            data_size_hex: _data_size_hex,
-            space1: _space1,
-            page_size_hex: _page_size_hex,
-            space2: _space2,
-            next_page_addr_hex: _next_page_addr_hex,
-            space3: _space3,
-            eol2_0d: _eol2_0d,
-            eol2_0a: _eol2_oa,
+            eol_0d: src[0],
+            eol_0a: src[1],
+            data_size_hex: hex_field(&src[2..10]),
+            space1: src[10],
+            page_size_hex: hex_field(&src[11..19]),
+            space2: src[19],
+            next_page_addr_hex: hex_field(&src[20..28]),
+            space3: src[28],
+            eol2_0d: src[29],
+            eol2_0a: src[30],
         })
     }
 
@@ -286,6 +301,9 @@ impl BlockHeader {
             && self.space3 == b'\x20'
             && self.eol2_0d == b'\r'
             && self.eol2_0a == b'\n'
+            && self.data_size_hex.iter().all(u8::is_ascii_hexdigit)
+            && self.page_size_hex.iter().all(u8::is_ascii_hexdigit)
+            && self.next_page_addr_hex.iter().all(u8::is_ascii_hexdigit)
     }
 
     /// Gets the value of the size of the data section from hexadecimal
@@ -306,7 +324,7 @@ impl BlockHeader {
     }
 
     fn get_u32(value: &[u8]) -> Result<u32> {
-        let s = str::from_utf8(&value)?;
+        let s = str::from_utf8(value)?;
 
         Ok(u32::from_str_radix(s, 16)?)
     }
@@ -330,6 +348,36 @@ impl BlockHeader {
     }
 }
 
+/// Decodes the name of an element from its header block which starts at
+/// `offset`.
+pub(crate) fn elem_name(header: &[u8], offset: u64) -> Result<String> {
+    let raw_name = match header.get(ElemHeaderBegin::SIZE as usize..) {
+        Some(raw_name) => raw_name,
+        None => {
+            return Err(error::V8Error::InvalidName {
+                offset,
+                path: String::new(),
+                container: None,
+                source: None,
+            })
+        }
+    };
+    let mut v_raw_name: Vec<u8> = vec![];
+
+    for (i, ch) in raw_name.iter().enumerate() {
+        if i % 2 == 0 && *ch != b'\0' {
+            v_raw_name.push(*ch);
+        }
+    }
+
+    String::from_utf8(v_raw_name).map_err(|e| error::V8Error::InvalidName {
+        offset,
+        path: String::new(),
+        container: None,
+        source: Some(e),
+    })
+}
+
 /// Is the structure and arrangement of data partitions in the container.
 #[derive(Debug, Default)]
 pub struct ElemAddr {
@@ -382,7 +430,9 @@ impl ElemAddr {
     }
 }
 
+/// The beginning of the header block of an element, followed by its name.
 #[allow(dead_code)]
+#[derive(Debug, Default, Clone)]
 pub struct ElemHeaderBegin {
     date_creation: u64,
     date_modification: u64,
@@ -392,6 +442,44 @@ pub struct ElemHeaderBegin {
 impl ElemHeaderBegin {
     /// The size of the data in the file, represented as C structures.
     pub const SIZE: u32 = 8 + 8 + 4;
+
+    /// Creates an instance of `ElemHeaderBegin` from the beginning of the
+    /// data of the header block which starts at `offset` in the container.
+    pub fn from_bytes(src: &[u8], offset: u64) -> Result<ElemHeaderBegin> {
+        if src.len() < Self::SIZE as usize {
+            return Err(truncated_block(offset));
+        }
+
+        let mut rdr = Cursor::new(src);
+        Ok(ElemHeaderBegin {
+            date_creation: rdr.read_u64::<LittleEndian>()?,
+            date_modification: rdr.read_u64::<LittleEndian>()?,
+            res: rdr.read_u32::<LittleEndian>()?,
+        })
+    }
+
+    /// Gets the creation time in 1C ticks, see `v8_time_to_unix`.
+    pub fn get_date_creation(&self) -> u64 {
+        self.date_creation
+    }
+
+    /// Gets the modification time in 1C ticks, see `v8_time_to_unix`.
+    pub fn get_date_modification(&self) -> u64 {
+        self.date_modification
+    }
+}
+
+/// Converts a time stored in a container, counted in 1/10000 of a second
+/// since 0001-01-01, to seconds since the Unix epoch. Returns `None` for a
+/// zero time, which 1C writes when the time is unknown.
+pub fn v8_time_to_unix(value: u64) -> Option<i64> {
+    const UNIX_EPOCH_SECONDS: i64 = 62_135_596_800;
+
+    if value == 0 {
+        return None;
+    }
+
+    Some((value / 10_000) as i64 - UNIX_EPOCH_SECONDS)
 }
 
 /// Describes the structure of the data item container.
@@ -443,7 +531,7 @@ impl V8Elem {
         self.unpacked_data = value;
     }
 
-    ///
+    /// Marks the element as a nested container.
     pub fn this_v8file(mut self, value: bool) -> Self {
         self.is_v8file = value;
 
@@ -460,16 +548,7 @@ impl V8Elem {
 
     /// Gets the name of the file in the container.
     pub fn get_name(&self) -> Result<String> {
-        let (_, raw_name) = self.header.split_at(ElemHeaderBegin::SIZE as usize);
-        let mut v_raw_name: Vec<u8> = vec![];
-
-        for (i, ch) in raw_name.iter().enumerate() {
-            if i % 2 == 0 && *ch != b'\0' {
-                v_raw_name.push(*ch);
-            }
-        }
-
-        Ok(String::from_utf8(v_raw_name)?)
+        elem_name(&self.header, 0)
     }
 
     pub fn set_name(&mut self, value: &str) {
@@ -526,7 +605,7 @@ pub struct V8File {
     ///a collection of elements that describe offsets of the header and data
     /// sections.
     elems_addrs: Vec<ElemAddr>,
-    ///
+    /// The elements of the container.
     elems: Vec<V8Elem>,
 }
 
@@ -555,10 +634,17 @@ impl V8File {
     }
 
     /// Stores data in files on disk.
-    pub fn save_file_to_folder(&self, elem_path: &path::PathBuf) -> Result<bool> {
-        if !elem_path.exists() {
-            fs::create_dir(elem_path.as_path())?;
-        }
+    pub fn save_file_to_folder(&self, elem_path: &path::Path) -> Result<bool> {
+        self.save_file_to_sink(elem_path, &mut DirSink::new(false))
+    }
+
+    /// Stores data in files on disk through `sink`.
+    pub fn save_file_to_sink(
+        &self,
+        elem_path: &path::Path,
+        sink: &mut dyn OutputSink,
+    ) -> Result<bool> {
+        sink.create_dir(elem_path)?;
 
         for elem in self.elems.iter() {
             let name_elem = elem.get_name()?;
@@ -567,11 +653,10 @@ impl V8File {
 
             if !elem.is_v8file {
                 if let Some(out_data) = elem.data.as_ref() {
-                    let mut filename_out = fs::File::create(out_path.as_path())?;
-                    filename_out.write_all(out_data)?;
+                    sink.write_file(&out_path, out_data)?;
                 }
             } else if let Some(out_file) = elem.unpacked_data.as_ref() {
-                out_file.save_file_to_folder(&out_path)?;
+                out_file.save_file_to_sink(&out_path, sink)?;
             }
         }
 
@@ -579,38 +664,72 @@ impl V8File {
     }
 
     pub fn load_file_from_folder(&mut self, dirname: path::PathBuf) -> Result<()> {
+        self.load_file_from_folder_with_filter(dirname, &ElemFilter::new(), "")
+    }
+
+    /// Loads the elements selected by `filter`, `elem_path` is the path of
+    /// the element stored in `dirname`, empty for the root container.
+    pub fn load_file_from_folder_with_filter(
+        &mut self,
+        dirname: path::PathBuf,
+        filter: &ElemFilter,
+        elem_path: &str,
+    ) -> Result<()> {
+        self.load_from_source_at(&DirSource::new(dirname), "", filter, elem_path)
+    }
+
+    /// Loads the elements selected by `filter` from the directory `path` of
+    /// `source`, the empty path for the root container.
+    pub fn load_from_source(
+        &mut self,
+        source: &dyn InputSource,
+        path: &str,
+        filter: &ElemFilter,
+    ) -> Result<()> {
+        self.load_from_source_at(source, path, filter, path)
+    }
+
+    /// Loads the directory `dir` of `source`, `elem_path` is the path of the
+    /// element it holds as seen by `filter`.
+    fn load_from_source_at(
+        &mut self,
+        source: &dyn InputSource,
+        dir: &str,
+        filter: &ElemFilter,
+        elem_path: &str,
+    ) -> Result<()> {
         self.file_header = FileHeader::new(V8_MAGIC_NUMBER, V8_DEFAULT_PAGE_SIZE, 0);
         self.elems.clear();
 
-        for entry in fs::read_dir(dirname.as_path())? {
-            let entry = entry?;
-            if let Ok(name) = entry.file_name().into_string() {
-                let header = vec![0; ElemHeaderBegin::SIZE as usize];
-                let mut element = V8Elem::new().with_header(header);
-                element.set_name(&name);
-
-                if let Ok(file_type) = entry.file_type() {
-                    if file_type.is_dir() {
-                        let new_dir = dirname.join(name);
-                        let mut v8 = V8File::new();
-                        v8.load_file_from_folder(new_dir)?;
-                        element.set_v8file(true);
-                        element.set_unpacked_data(Some(v8));
-                        element.pack(false)?;
-                    } else {
-                        element.set_v8file(false);
-                        let mut file = fs::File::open(entry.path())?;
-                        let mut buf = vec![];
-                        file.read_to_end(&mut buf)?;
-                        element.set_data(Some(buf));
-                    }
+        for entry in source.read_dir(dir)? {
+            let join = |parent: &str| {
+                if parent.is_empty() {
+                    entry.name.clone()
                 } else {
-                    error!("Couldn't get file type for {:?}", entry.path());
+                    format!("{}/{}", parent, entry.name)
                 }
-                self.elems.push(element);
+            };
+            let (source_path, path) = (join(dir), join(elem_path));
+            if !filter.selects_source(&path, entry.is_dir) {
+                debug!("skip filtered element {}", path);
+                continue;
+            }
+
+            let header = vec![0; ElemHeaderBegin::SIZE as usize];
+            let mut element = V8Elem::new().with_header(header);
+            element.set_name(&entry.name);
+
+            if entry.is_dir {
+                let mut v8 = V8File::new();
+                v8.load_from_source_at(source, &source_path, filter, &path)?;
+                element.set_v8file(true);
+                element.set_unpacked_data(Some(v8));
+                element.pack(false)?;
             } else {
-                error!("Couldn't get file name for {:?}", entry.path());
+                element.set_v8file(false);
+                element.set_data(Some(source.read_file(&source_path)?));
             }
+            self.elems.push(element);
         }
 
         Ok(())
@@ -624,11 +743,7 @@ impl V8File {
         let mut elem_addrs_bytes: Vec<u8> =
             Vec::with_capacity(self.elems.len() * ElemAddr::SIZE as usize);
 
-        let mut cur_elem_addr = FileHeader::SIZE + BlockHeader::SIZE;
-        cur_elem_addr += cmp::max(
-            ElemAddr::SIZE * self.elems.len() as u32,
-            V8_DEFAULT_PAGE_SIZE,
-        );
+        let mut cur_elem_addr = toc_end_addr(self.elems.len())?;
 
         let mut new_elems: Vec<V8Elem> = vec![];
 
@@ -664,13 +779,17 @@ impl V8File {
 
         for elem in new_elems.iter() {
             let elem_header_addr = cur_elem_addr;
-            cur_elem_addr += BlockHeader::SIZE + elem.header.len() as u32;
+            cur_elem_addr = block_end_addr(cur_elem_addr, elem.header.len() as u64, 0)?;
 
             let elem_data_addr = cur_elem_addr;
-            cur_elem_addr += BlockHeader::SIZE;
             if let Some(ref data) = elem.data {
-                cur_elem_addr += cmp::max(data.len() as u32, V8_DEFAULT_PAGE_SIZE);
+                cur_elem_addr = block_end_addr(
+                    cur_elem_addr,
+                    data.len() as u64,
+                    V8_DEFAULT_PAGE_SIZE,
+                )?;
             } else {
+                cur_elem_addr = add_addr(cur_elem_addr, u64::from(BlockHeader::SIZE))?;
                 error!("Empty!");
             }
 
@@ -678,6 +797,7 @@ impl V8File {
                 .extend(ElemAddr::new(elem_data_addr, elem_header_addr).into_bytes()?);
         }
 
+        result.reserve((cur_elem_addr as usize).saturating_sub(result.len()));
         V8File::save_block_data_to_buffer(
             &mut result,
             &elem_addrs_bytes,
@@ -688,14 +808,14 @@ impl V8File {
             V8File::save_block_data_to_buffer(
                 &mut result,
                 &elem.header,
-                elem.header.len() as u32,
+                format_u32(elem.header.len() as u64, "Block size")?,
             )?;
 
             if let Some(ref data) = elem.data {
                 V8File::save_block_data_to_buffer(
                     &mut result,
                     data,
-                    cmp::max(data.len() as u32, V8_DEFAULT_PAGE_SIZE),
+                    V8_DEFAULT_PAGE_SIZE,
                 )?;
             } else {
                 error!("Empty!");
@@ -710,11 +830,7 @@ impl V8File {
         block_data: &[u8],
         page_size: u32,
     ) -> Result<()> {
-        if block_data.len() > u32::MAX as usize {
-            ioError::new(ioErrorKind::InvalidData, "Invalid data length");
-        }
-
-        let block_size = block_data.len() as u32;
+        let block_size = format_u32(block_data.len() as u64, "Block size")?;
         let page_size_actual = if page_size < block_size {
             block_size
         } else {
@@ -725,25 +841,90 @@ impl V8File {
             BlockHeader::new(block_size, page_size_actual, V8_MAGIC_NUMBER);
 
         buffer.extend(&block_header.into_bytes()?);
-        buffer.extend(block_data.iter());
+        buffer.extend_from_slice(block_data);
 
-        let mut i = 0;
-        while i < (page_size_actual - block_size) {
-            buffer.push(0);
-            i += 1;
-        }
+        let padded_len = buffer.len() + (page_size_actual - block_size) as usize;
+        buffer.resize(padded_len, 0);
 
         Ok(())
     }
 }
 
-// (c) https://stackoverflow.com/questions/25428920/how-to-get-a-slice-as-an-array-in-rust
-fn clone_into_array<A, T>(slice: &[T]) -> A
+pub(crate) fn limit_exceeded(what: &'static str, value: u64) -> error::V8Error {
+    error::V8Error::LimitExceeded {
+        path: String::new(),
+        what,
+        value,
+        limit: V8_MAX_ADDR,
+    }
+}
+
+/// Converts a size or an offset to the 32-bit representation of the format.
+pub(crate) fn format_u32(value: u64, what: &'static str) -> Result<u32> {
+    if value > V8_MAX_ADDR {
+        return Err(limit_exceeded(what, value));
+    }
+
+    Ok(value as u32)
+}
+
+/// Moves an offset in the container forward by `size` bytes.
+pub(crate) fn add_addr(addr: u32, size: u64) -> Result<u32> {
+    let end = u64::from(addr).saturating_add(size);
+    format_u32(end, "Container size")
+}
+
+/// Returns the offset following a block with `data_size` bytes of data
+/// padded to `page_size` which header starts at `addr`.
+pub(crate) fn block_end_addr(addr: u32, data_size: u64, page_size: u32) -> Result<u32> {
+    format_u32(data_size, "Block size")?;
+    let block_size =
+        u64::from(BlockHeader::SIZE) + cmp::max(data_size, u64::from(page_size));
+    add_addr(addr, block_size)
+}
+
+/// Returns the offset following the table of contents of `elems_num`
+/// elements, where the first element starts.
+pub(crate) fn toc_end_addr(elems_num: usize) -> Result<u32> {
+    let toc_size = (elems_num as u64).saturating_mul(u64::from(ElemAddr::SIZE));
+    block_end_addr(FileHeader::SIZE, toc_size, V8_DEFAULT_PAGE_SIZE)
+}
+
+pub(crate) fn truncated_block(offset: u64) -> error::V8Error {
+    error::V8Error::TruncatedBlock {
+        offset,
+        path: String::new(),
+        container: None,
+    }
+}
+
+pub(crate) fn inflate_failed(offset: u64, message: String) -> error::V8Error {
+    error::V8Error::InflateFailed {
+        offset,
+        path: String::new(),
+        container: None,
+        message,
+    }
+}
+
+pub(crate) fn bad_block_header(offset: u64) -> error::V8Error {
+    error::V8Error::BadBlockHeader {
+        offset,
+        path: String::new(),
+        container: None,
+    }
+}
+
+fn read_exact_or_invalid<R>(src: &mut R, buf: &mut [u8]) -> Result<()>
 where
-    A: Sized + Default + AsMut<[T]>,
-    T: Clone,
+    R: Read + Seek,
 {
-    let mut a = Default::default();
-    <A as AsMut<[T]>>::as_mut(&mut a).clone_from_slice(slice);
-    a
+    let offset = src.stream_position()?;
+    match src.read_exact(buf) {
+        Ok(()) => Ok(()),
+        Err(ref e) if e.kind() == ioErrorKind::UnexpectedEof => {
+            Err(truncated_block(offset))
+        }
+        Err(e) => Err(e.into()),
+    }
 }
@@ -1,14 +1,186 @@
-use std::{fmt, io, num, str, string};
+use std::{error, fmt, io, num, str, string};
 
+/// Errors of reading and writing containers.
+///
+/// Errors found inside a container carry the offset of the damaged
+/// structure and the path of the element it belongs to, like
+/// `Form/Module.0/text`. Offsets inside a nested container are counted from
+/// the beginning of its inflated data, `container` is the path of that
+/// nested container and `None` for offsets in the container file.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum V8Error {
-    NotV8File { offset: u64 },
+    NotV8File {
+        offset: u64,
+    },
+    /// A block ends before its declared size.
+    TruncatedBlock {
+        offset: u64,
+        path: String,
+        container: Option<String>,
+    },
+    /// A block header is not in the `\r\nXXXXXXXX XXXXXXXX XXXXXXXX \r\n`
+    /// format.
+    BadBlockHeader {
+        offset: u64,
+        path: String,
+        container: Option<String>,
+    },
+    /// An entry of the table of contents is damaged.
+    BadTocEntry {
+        offset: u64,
+        path: String,
+        container: Option<String>,
+        index: usize,
+    },
+    /// The name of an element can not be decoded from its header.
+    InvalidName {
+        offset: u64,
+        path: String,
+        container: Option<String>,
+        source: Option<string::FromUtf8Error>,
+    },
+    /// The data block at `offset` inflates into the beginning of a nested
+    /// container and then breaks off.
+    InflateFailed {
+        offset: u64,
+        path: String,
+        container: Option<String>,
+        message: String,
+    },
+    /// A size or an offset does not fit into the 32-bit container format.
+    LimitExceeded {
+        path: String,
+        what: &'static str,
+        value: u64,
+        limit: u64,
+    },
+    /// The operation was stopped by a `CancellationToken`.
+    Cancelled,
     IoError(io::Error),
     FromUtf8Error(string::FromUtf8Error),
     Utf8Error(str::Utf8Error),
     ParseIntError(num::ParseIntError),
 }
 
+impl V8Error {
+    /// Returns the offset of the damaged structure in the container.
+    pub fn offset(&self) -> Option<u64> {
+        match *self {
+            V8Error::NotV8File { offset }
+            | V8Error::TruncatedBlock { offset, .. }
+            | V8Error::BadBlockHeader { offset, .. }
+            | V8Error::BadTocEntry { offset, .. }
+            | V8Error::InvalidName { offset, .. }
+            | V8Error::InflateFailed { offset, .. } => Some(offset),
+            _ => None,
+        }
+    }
+
+    /// Returns the path of the element where the error occurred, empty for
+    /// the structures of the root container.
+    pub fn path(&self) -> Option<&str> {
+        match *self {
+            V8Error::TruncatedBlock { ref path, .. }
+            | V8Error::BadBlockHeader { ref path, .. }
+            | V8Error::BadTocEntry { ref path, .. }
+            | V8Error::InvalidName { ref path, .. }
+            | V8Error::InflateFailed { ref path, .. }
+            | V8Error::LimitExceeded { ref path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Returns the path of the nested container which inflated data the
+    /// offset is counted from, `None` for offsets in the container file.
+    pub fn container(&self) -> Option<&str> {
+        match *self {
+            V8Error::TruncatedBlock { ref container, .. }
+            | V8Error::BadBlockHeader { ref container, .. }
+            | V8Error::BadTocEntry { ref container, .. }
+            | V8Error::InvalidName { ref container, .. }
+            | V8Error::InflateFailed { ref container, .. } => container.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Marks the offset as counted from the inflated data of the nested
+    /// container which name the following `in_elem` prepends.
+    pub(crate) fn nested(mut self) -> V8Error {
+        match self {
+            V8Error::TruncatedBlock {
+                ref mut container, ..
+            }
+            | V8Error::BadBlockHeader {
+                ref mut container, ..
+            }
+            | V8Error::BadTocEntry {
+                ref mut container, ..
+            }
+            | V8Error::InvalidName {
+                ref mut container, ..
+            }
+            | V8Error::InflateFailed {
+                ref mut container, ..
+            } => {
+                container.get_or_insert_with(String::new);
+            }
+            _ => {}
+        }
+
+        self
+    }
+
+    /// Prepends the name of the enclosing element to the path of the error
+    /// and to the path of the nested container, if any.
+    pub fn in_elem(mut self, name: &str) -> V8Error {
+        match self {
+            V8Error::TruncatedBlock {
+                ref mut path,
+                ref mut container,
+                ..
+            }
+            | V8Error::BadBlockHeader {
+                ref mut path,
+                ref mut container,
+                ..
+            }
+            | V8Error::BadTocEntry {
+                ref mut path,
+                ref mut container,
+                ..
+            }
+            | V8Error::InvalidName {
+                ref mut path,
+                ref mut container,
+                ..
+            }
+            | V8Error::InflateFailed {
+                ref mut path,
+                ref mut container,
+                ..
+            } => {
+                prepend(path, name);
+                if let Some(container) = container {
+                    prepend(container, name);
+                }
+            }
+            V8Error::LimitExceeded { ref mut path, .. } => prepend(path, name),
+            _ => {}
+        }
+
+        self
+    }
+}
+
+fn prepend(path: &mut String, name: &str) {
+    *path = if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", name, path)
+    };
+}
+
 impl From<io::Error> for V8Error {
     fn from(other: io::Error) -> V8Error {
         V8Error::IoError(other)
@@ -33,6 +205,39 @@ impl From<num::ParseIntError> for V8Error {
     }
 }
 
+struct InElem<'a>(&'a str);
+
+impl<'a> fmt::Display for InElem<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            write!(f, " in element {:?}", self.0)
+        }
+    }
+}
+
+/// The offset of an error with the nested container it is counted in.
+struct At<'a>(u64, &'a Option<String>);
+
+impl<'a> fmt::Display for At<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self.1 {
+            Some(ref container) if !container.is_empty() => write!(
+                f,
+                "offset {} of the inflated data of the nested container {:?}",
+                self.0, container
+            ),
+            Some(_) => write!(
+                f,
+                "offset {} of the inflated data of a nested container",
+                self.0
+            ),
+            None => write!(f, "offset {}", self.0),
+        }
+    }
+}
+
 impl fmt::Display for V8Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -40,9 +245,96 @@ impl fmt::Display for V8Error {
             V8Error::NotV8File { offset } => {
                 write!(f, "Not correct V8 file offset: {0}", offset)
             }
+            V8Error::TruncatedBlock {
+                offset,
+                ref path,
+                ref container,
+            } => write!(
+                f,
+                "Block at {}{} ends before its declared size",
+                At(offset, container),
+                InElem(path)
+            ),
+            V8Error::BadBlockHeader {
+                offset,
+                ref path,
+                ref container,
+            } => write!(
+                f,
+                "Bad block header at {}{}",
+                At(offset, container),
+                InElem(path)
+            ),
+            V8Error::BadTocEntry {
+                offset,
+                ref path,
+                ref container,
+                index,
+            } => write!(
+                f,
+                "Bad table of contents entry {} at {}{}",
+                index,
+                At(offset, container),
+                InElem(path)
+            ),
+            V8Error::InvalidName {
+                offset,
+                ref path,
+                ref container,
+                ..
+            } => write!(
+                f,
+                "Invalid element name in header at {}{}",
+                At(offset, container),
+                InElem(path)
+            ),
+            V8Error::InflateFailed {
+                offset,
+                ref path,
+                ref container,
+                ref message,
+            } => write!(
+                f,
+                "Couldn't inflate the nested container at {}{}: {}",
+                At(offset, container),
+                InElem(path),
+                message
+            ),
+            V8Error::LimitExceeded {
+                ref path,
+                what,
+                value,
+                limit,
+            } => write!(
+                f,
+                "{} {} exceeds the limit {} of the 32-bit container format{}; \
+                 containers larger than 4 GiB need the 64-bit format, which is not \
+                 supported yet",
+                what,
+                value,
+                limit,
+                InElem(path)
+            ),
+            V8Error::Cancelled => write!(f, "The operation was cancelled"),
             V8Error::FromUtf8Error(ref e) => fmt::Display::fmt(e, f),
             V8Error::Utf8Error(ref e) => fmt::Display::fmt(e, f),
             V8Error::ParseIntError(ref e) => fmt::Display::fmt(e, f),
         }
     }
 }
+
+impl error::Error for V8Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            V8Error::IoError(ref e) => Some(e),
+            V8Error::InvalidName {
+                source: Some(ref e),
+                ..
+            } => Some(e),
+            V8Error::FromUtf8Error(ref e) => Some(e),
+            V8Error::Utf8Error(ref e) => Some(e),
+            V8Error::ParseIntError(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
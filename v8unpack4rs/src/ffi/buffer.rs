@@ -0,0 +1,59 @@
+//! Buffers allocated by the library and returned through the C interface.
+
+use std::ptr;
+
+/// Bytes allocated by the library, the caller frees them with
+/// `v8_buffer_free`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct V8Buffer {
+    /// The bytes, null in the buffer stored on errors.
+    pub data: *mut u8,
+    /// Number of the bytes.
+    pub len: usize,
+}
+
+impl V8Buffer {
+    pub(crate) fn empty() -> V8Buffer {
+        V8Buffer {
+            data: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    pub(crate) fn from_vec(data: Vec<u8>) -> V8Buffer {
+        let data = Box::into_raw(data.into_boxed_slice());
+
+        V8Buffer {
+            len: data.len(),
+            data: data as *mut u8,
+        }
+    }
+}
+
+/// Stores an empty buffer in `out_buffer` so that it is set on errors too.
+///
+/// # Safety
+///
+/// `out_buffer` must be null or a valid pointer.
+pub(crate) unsafe fn reset_buffer(out_buffer: *mut V8Buffer) {
+    if !out_buffer.is_null() {
+        out_buffer.write(V8Buffer::empty());
+    }
+}
+
+/// Frees the bytes of `buffer` returned by the library, an empty buffer is
+/// ignored.
+///
+/// # Safety
+///
+/// `buffer` must be returned by the library and not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn v8_buffer_free(buffer: V8Buffer) {
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            buffer.data,
+            buffer.len,
+        )));
+    }
+}
@@ -0,0 +1,235 @@
+//! Opened containers of the C interface.
+//!
+//! A container opened by `v8_open` or `v8_open_memory` is kept in memory
+//! until `v8_close`. Names of the elements are owned by the handle, data of
+//! the elements is returned in a `V8Buffer` owned by the caller. A nested
+//! container opened by `v8_elem_open` is independent of its parent and is
+//! closed separately. Output handles and buffers are null on errors.
+
+use std::ffi::CString;
+use std::fs;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::slice;
+
+use super::buffer::{reset_buffer, V8Buffer};
+use super::status::{call, get_str, write_out, FfiError, FfiResult};
+use crate::container::V8_MAGIC_NUMBER;
+use crate::error::V8Error;
+use crate::parser::inflate_elem;
+use crate::view::ContainerView;
+
+/// A container opened by `v8_open`.
+#[derive(Debug)]
+pub struct V8Handle {
+    data: Vec<u8>,
+    elems: Vec<HandleElem>,
+}
+
+#[derive(Debug)]
+struct HandleElem {
+    name: CString,
+    data_addr: u32,
+}
+
+impl V8Handle {
+    fn new(data: Vec<u8>) -> FfiResult<V8Handle> {
+        let mut elems = vec![];
+        {
+            let view = ContainerView::new(&data)?;
+            for elem in view.iter() {
+                let elem = elem?;
+                elems.push(HandleElem {
+                    name: CString::new(elem.get_name()?.replace('\0', " "))
+                        .unwrap_or_default(),
+                    data_addr: elem.get_data_addr(),
+                });
+            }
+        }
+
+        Ok(V8Handle { data, elems })
+    }
+
+    fn elem(&self, index: usize) -> FfiResult<&HandleElem> {
+        self.elems.get(index).ok_or_else(|| {
+            FfiError::InvalidArgument(format!(
+                "index {} is out of range of {} elements",
+                index,
+                self.elems.len()
+            ))
+        })
+    }
+
+    /// Returns the inflated data of the element.
+    fn read(&self, index: usize) -> FfiResult<Vec<u8>> {
+        let elem = self.elem(index)?;
+        if elem.data_addr == V8_MAGIC_NUMBER {
+            return Ok(vec![]);
+        }
+
+        let view = ContainerView::without_toc(&self.data)?;
+        let data = view
+            .read_block(elem.data_addr)
+            .map_err(|e| e.in_elem(&elem.name.to_string_lossy()))?;
+
+        let data = inflate_elem(data, u64::from(elem.data_addr))
+            .map_err(|e| e.in_elem(&elem.name.to_string_lossy()))?;
+        Ok(data.into_owned())
+    }
+}
+
+unsafe fn get_handle<'a>(handle: *const V8Handle) -> FfiResult<&'a V8Handle> {
+    handle
+        .as_ref()
+        .ok_or_else(|| FfiError::InvalidArgument("handle is null".to_string()))
+}
+
+fn into_raw(handle: V8Handle) -> *mut V8Handle {
+    Box::into_raw(Box::new(handle))
+}
+
+/// Stores null in `out_handle` so that it is set on errors too.
+unsafe fn reset_handle(out_handle: *mut *mut V8Handle) {
+    if !out_handle.is_null() {
+        out_handle.write(ptr::null_mut());
+    }
+}
+
+/// Opens the container `file_name`, reading it into memory, and stores the
+/// handle to close with `v8_close` in `out_handle`.
+///
+/// # Safety
+///
+/// `file_name` must be null or a valid pointer to a nul-terminated string,
+/// `out_handle` must be null or a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn v8_open(
+    file_name: *const c_char,
+    out_handle: *mut *mut V8Handle,
+) -> c_int {
+    reset_handle(out_handle);
+
+    call(|| {
+        let file_name = get_str(file_name, "file_name")?;
+        let handle = V8Handle::new(fs::read(file_name).map_err(V8Error::from)?)?;
+
+        write_out(out_handle, "out_handle", into_raw(handle))
+    })
+}
+
+/// Opens the container in `len` bytes at `data`, copying them, and stores
+/// the handle to close with `v8_close` in `out_handle`.
+///
+/// # Safety
+///
+/// `data` must be a valid pointer to `len` bytes, `out_handle` must be null
+/// or a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn v8_open_memory(
+    data: *const u8,
+    len: usize,
+    out_handle: *mut *mut V8Handle,
+) -> c_int {
+    reset_handle(out_handle);
+
+    call(|| {
+        if data.is_null() {
+            return Err(FfiError::InvalidArgument("data is null".to_string()));
+        }
+        let handle = V8Handle::new(slice::from_raw_parts(data, len).to_vec())?;
+
+        write_out(out_handle, "out_handle", into_raw(handle))
+    })
+}
+
+/// Returns the number of elements of the container, `0` for a null handle.
+///
+/// # Safety
+///
+/// `handle` must be null or returned by `v8_open` and not closed.
+#[no_mangle]
+pub unsafe extern "C" fn v8_elem_count(handle: *const V8Handle) -> usize {
+    handle.as_ref().map_or(0, |handle| handle.elems.len())
+}
+
+/// Returns the name of the element with the given index, or null when the
+/// index is out of range. The string is owned by the handle and stays valid
+/// until `v8_close`.
+///
+/// # Safety
+///
+/// `handle` must be null or returned by `v8_open` and not closed.
+#[no_mangle]
+pub unsafe extern "C" fn v8_elem_name(
+    handle: *const V8Handle,
+    index: usize,
+) -> *const c_char {
+    match handle.as_ref().and_then(|handle| handle.elems.get(index)) {
+        Some(elem) => elem.name.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Stores the inflated data of the element with the given index in
+/// `out_buffer`, the caller frees it with `v8_buffer_free`.
+///
+/// # Safety
+///
+/// `handle` must be null or returned by `v8_open` and not closed,
+/// `out_buffer` must be null or a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn v8_elem_read(
+    handle: *const V8Handle,
+    index: usize,
+    out_buffer: *mut V8Buffer,
+) -> c_int {
+    reset_buffer(out_buffer);
+
+    call(|| {
+        let data = get_handle(handle)?.read(index)?;
+
+        write_out(out_buffer, "out_buffer", V8Buffer::from_vec(data))
+    })
+}
+
+/// Opens the nested container stored in the element with the given index
+/// and stores its handle in `out_handle`. The nested handle does not depend
+/// on `handle` and is closed with `v8_close` too.
+///
+/// Returns `V8_ERR_NOT_V8FILE` when the element is not a container.
+///
+/// # Safety
+///
+/// `handle` must be null or returned by `v8_open` and not closed,
+/// `out_handle` must be null or a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn v8_elem_open(
+    handle: *const V8Handle,
+    index: usize,
+    out_handle: *mut *mut V8Handle,
+) -> c_int {
+    reset_handle(out_handle);
+
+    call(|| {
+        let handle = get_handle(handle)?;
+        let name = handle.elem(index)?.name.to_string_lossy().into_owned();
+        let nested = V8Handle::new(handle.read(index)?).map_err(|e| match e {
+            FfiError::V8(e) => FfiError::V8(e.nested().in_elem(&name)),
+            e => e,
+        })?;
+
+        write_out(out_handle, "out_handle", into_raw(nested))
+    })
+}
+
+/// Closes the container, a null handle is ignored.
+///
+/// # Safety
+///
+/// `handle` must be null or returned by `v8_open` and not closed before.
+#[no_mangle]
+pub unsafe extern "C" fn v8_close(handle: *mut V8Handle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
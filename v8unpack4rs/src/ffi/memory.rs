@@ -0,0 +1,102 @@
+//! Functions of the C interface working with containers in memory.
+
+use std::os::raw::{c_char, c_int};
+use std::slice;
+use std::sync::Arc;
+
+use super::buffer::{reset_buffer, V8Buffer};
+use super::status::{call, get_str, write_out, FfiError, FfiResult};
+use crate::builder::{self, BuildOptions};
+use crate::parser::{self, UnpackOptions};
+use crate::source::DirSource;
+
+/// Returns `len` bytes at `data` as a slice.
+///
+/// # Safety
+///
+/// `data` must be null or a valid pointer to `len` bytes.
+unsafe fn get_bytes<'a>(data: *const u8, len: usize) -> FfiResult<&'a [u8]> {
+    if data.is_null() {
+        return Err(FfiError::InvalidArgument("data is null".to_string()));
+    }
+
+    Ok(slice::from_raw_parts(data, len))
+}
+
+/// Unpacks the container in `len` bytes at `data` into the directory
+/// `dir_name`, inflating the elements, like `v8_parse`.
+///
+/// # Safety
+///
+/// `data` must be null or a valid pointer to `len` bytes, `dir_name` must be
+/// null or a valid pointer to a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn v8_parse_memory(
+    data: *const u8,
+    len: usize,
+    dir_name: *const c_char,
+) -> c_int {
+    call(|| {
+        let data = get_bytes(data, len)?;
+        let dir_name = get_str(dir_name, "dir_name")?;
+        parser::unpack_bytes_to_directory(data, dir_name, &UnpackOptions::new())?;
+
+        Ok(())
+    })
+}
+
+/// Stores the inflated data of the element `elem_path`, like `Form/form`, of
+/// the container in `len` bytes at `data` in `out_buffer`, the caller frees
+/// it with `v8_buffer_free`. The data of a nested container is the
+/// container itself.
+///
+/// Returns `V8_ERR_NOT_FOUND` when the container has no such element.
+///
+/// # Safety
+///
+/// `data` must be null or a valid pointer to `len` bytes, `elem_path` must
+/// be null or a valid pointer to a nul-terminated string, `out_buffer` must
+/// be null or a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn v8_extract_memory(
+    data: *const u8,
+    len: usize,
+    elem_path: *const c_char,
+    out_buffer: *mut V8Buffer,
+) -> c_int {
+    reset_buffer(out_buffer);
+
+    call(|| {
+        let data = get_bytes(data, len)?;
+        let elem_path = get_str(elem_path, "elem_path")?;
+        let elem_data = parser::find_elem(data, elem_path)?
+            .ok_or_else(|| FfiError::NotFound(elem_path.to_string()))?;
+
+        write_out(out_buffer, "out_buffer", V8Buffer::from_vec(elem_data))
+    })
+}
+
+/// Builds a container from the directory `dir_name` made by `v8_parse` and
+/// stores it in `out_buffer`, the caller frees it with `v8_buffer_free`. The
+/// elements are stored without compression when `no_deflate` is set.
+///
+/// # Safety
+///
+/// `dir_name` must be null or a valid pointer to a nul-terminated string,
+/// `out_buffer` must be null or a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn v8_build_memory(
+    dir_name: *const c_char,
+    no_deflate: bool,
+    out_buffer: *mut V8Buffer,
+) -> c_int {
+    reset_buffer(out_buffer);
+
+    call(|| {
+        let dir_name = get_str(dir_name, "dir_name")?;
+        let options = BuildOptions::new().with_no_deflate(no_deflate);
+        let data = builder::build_to_vec(Arc::new(DirSource::new(dir_name)), &options)?;
+
+        write_out(out_buffer, "out_buffer", V8Buffer::from_vec(data))
+    })
+}
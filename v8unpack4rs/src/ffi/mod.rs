@@ -1,32 +1,156 @@
-use std::ffi::CStr;
-use std::os::raw::c_char;
-use std::panic::catch_unwind;
-use std::str::Utf8Error;
+use std::os::raw::{c_char, c_int, c_void};
+use std::sync::{Arc, Mutex};
 
-use crate::parser::unpack_to_directory_no_load;
+use crate::error::V8Error;
+use crate::parser::UnpackOptions;
+use crate::parser::{unpack_to_directory_no_load, unpack_to_directory_with_options};
+use crate::progress::Progress;
 
-unsafe fn get_string(ptr: *const c_char) -> Result<String, Utf8Error> {
-    Ok(CStr::from_ptr(ptr).to_str()?.to_owned())
-}
+mod buffer;
+mod handle;
+mod memory;
+mod ops;
+mod status;
+
+pub use self::buffer::*;
+pub use self::handle::*;
+pub use self::memory::*;
+pub use self::ops::*;
+pub use self::status::*;
+
+use self::status::{call, get_str};
 
 /// External interface to call the decompression of the file container from
 /// other languages.
+///
+/// Returns `false` on error, the text of the error is returned by
+/// `v8_last_error_message`. `v8_parse` returns the status code instead.
+///
+/// # Safety
+///
+/// `pfile_name` and `pdir_name` must be null or valid pointers to
+/// nul-terminated strings.
 #[no_mangle]
 pub unsafe extern "C" fn parse_cf(
     pfile_name: *const c_char,
     pdir_name: *const c_char,
 ) -> bool {
-    let result = catch_unwind(|| {
-        let file_name = get_string(pfile_name).unwrap();
-        let dir_name = get_string(pdir_name).unwrap();
+    let status = call(|| {
+        let file_name = get_str(pfile_name, "pfile_name")?;
+        let dir_name = get_str(pdir_name, "pdir_name")?;
+        if !unpack_to_directory_no_load(file_name, dir_name, true, true)? {
+            return Err(V8Error::NotV8File { offset: 0 }.into());
+        }
 
-        unpack_to_directory_no_load(&file_name, &dir_name, true, true).unwrap()
+        Ok(())
     });
 
-    if result.is_err() {
-        eprintln!("Error parse!");
-        return false;
+    status == V8_OK
+}
+
+/// Callback of the C interface receiving `user_data`, the numbers of the
+/// processed and of all elements of the root container and the number of
+/// bytes written so far. Null when progress is not needed.
+pub type ProgressCallback = Option<
+    extern "C" fn(user_data: *mut c_void, done: usize, total: usize, written: u64),
+>;
+
+#[derive(Debug, Default)]
+struct CallbackState {
+    done: usize,
+    total: usize,
+    written: u64,
+}
+
+#[derive(Debug)]
+struct CallbackProgress {
+    callback: ProgressCallback,
+    user_data: *mut c_void,
+    state: Mutex<CallbackState>,
+}
+
+// The callback is only called on the thread of `v8_parse_with_progress`,
+// `user_data` is never touched by the library.
+unsafe impl Send for CallbackProgress {}
+unsafe impl Sync for CallbackProgress {}
+
+impl CallbackProgress {
+    fn update<F: FnOnce(&mut CallbackState)>(&self, f: F) {
+        if let Ok(mut state) = self.state.lock() {
+            f(&mut state);
+            if let Some(callback) = self.callback {
+                callback(self.user_data, state.done, state.total, state.written);
+            }
+        }
+    }
+}
+
+impl Progress for CallbackProgress {
+    fn begin(&self, total_elems: Option<usize>, _total_bytes: Option<u64>) {
+        self.update(|state| state.total = total_elems.unwrap_or(0));
     }
 
-    true
+    fn elem_finished(&self, _name: &str) {
+        self.update(|state| state.done += 1);
+    }
+
+    fn bytes_written(&self, bytes: u64) {
+        if let Ok(mut state) = self.state.lock() {
+            state.written += bytes;
+        }
+    }
+}
+
+/// Same as `parse_cf`, calling `callback` with `user_data` when the number
+/// of elements is known and after each element of the root container.
+///
+/// # Safety
+///
+/// `pfile_name` and `pdir_name` must be null or valid pointers to
+/// nul-terminated strings. `callback`, when given, is called on the calling
+/// thread and must not unwind.
+#[no_mangle]
+pub unsafe extern "C" fn parse_cf_with_progress(
+    pfile_name: *const c_char,
+    pdir_name: *const c_char,
+    callback: ProgressCallback,
+    user_data: *mut c_void,
+) -> bool {
+    v8_parse_with_progress(pfile_name, pdir_name, callback, user_data) == V8_OK
+}
+
+/// Unpacks the container `file_name` into the directory `dir_name` on the
+/// calling thread, calling `callback` with `user_data` when the number of
+/// elements is known and after each element of the root container.
+///
+/// Returns `V8_OK` or an error status, see `v8_last_error_message`.
+///
+/// # Safety
+///
+/// `file_name` and `dir_name` must be null or valid pointers to
+/// nul-terminated strings. `callback`, when given, is called on the calling
+/// thread and must not unwind.
+#[no_mangle]
+pub unsafe extern "C" fn v8_parse_with_progress(
+    file_name: *const c_char,
+    dir_name: *const c_char,
+    callback: ProgressCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    call(|| {
+        let file_name = get_str(file_name, "file_name")?;
+        let dir_name = get_str(dir_name, "dir_name")?;
+
+        let mut options = UnpackOptions::new();
+        if callback.is_some() {
+            options = options.with_progress(Arc::new(CallbackProgress {
+                callback,
+                user_data,
+                state: Mutex::new(CallbackState::default()),
+            }));
+        }
+        unpack_to_directory_with_options(file_name, dir_name, &options)?;
+
+        Ok(())
+    })
 }
@@ -0,0 +1,204 @@
+//! File operations of the C interface, the same as the commands of the
+//! command line utility.
+
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_void};
+
+use super::status::{call, get_str, FfiError};
+use crate::builder::{self, BuildOptions};
+use crate::error::V8Error;
+use crate::parser::{self, UnpackOptions};
+
+/// Unpacks the container `file_name` into the directory `dir_name`,
+/// inflating the elements, like the `parse` command.
+///
+/// Returns `V8_OK` or an error status, see `v8_last_error_message`.
+///
+/// # Safety
+///
+/// `file_name` and `dir_name` must be null or valid pointers to
+/// nul-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn v8_parse(
+    file_name: *const c_char,
+    dir_name: *const c_char,
+) -> c_int {
+    call(|| {
+        let file_name = get_str(file_name, "file_name")?;
+        let dir_name = get_str(dir_name, "dir_name")?;
+        parser::parse_to_folder_with_options(
+            file_name,
+            dir_name,
+            &UnpackOptions::new(),
+        )?;
+
+        Ok(())
+    })
+}
+
+/// Unpacks the binary headers and data of the elements of the container
+/// `file_name` into the directory `dir_name`, like the `unpack` command.
+///
+/// # Safety
+///
+/// `file_name` and `dir_name` must be null or valid pointers to
+/// nul-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn v8_unpack(
+    file_name: *const c_char,
+    dir_name: *const c_char,
+) -> c_int {
+    call(|| {
+        let file_name = get_str(file_name, "file_name")?;
+        let dir_name = get_str(dir_name, "dir_name")?;
+        if !parser::unpack_to_folder(file_name, dir_name)? {
+            return Err(V8Error::NotV8File { offset: 0 }.into());
+        }
+
+        Ok(())
+    })
+}
+
+/// Packages the directory `dir_name` made by `v8_unpack` into the container
+/// `file_name`, like the `pack` command.
+///
+/// # Safety
+///
+/// `dir_name` and `file_name` must be null or valid pointers to
+/// nul-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn v8_pack(
+    dir_name: *const c_char,
+    file_name: *const c_char,
+) -> c_int {
+    call(|| {
+        let dir_name = get_str(dir_name, "dir_name")?;
+        let file_name = get_str(file_name, "file_name")?;
+        builder::pack_from_folder(dir_name, file_name)?;
+
+        Ok(())
+    })
+}
+
+/// Builds the container `file_name` from the directory `dir_name` made by
+/// `v8_parse`, like the `build` command. The elements are stored without
+/// compression when `no_deflate` is set.
+///
+/// # Safety
+///
+/// `dir_name` and `file_name` must be null or valid pointers to
+/// nul-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn v8_build(
+    dir_name: *const c_char,
+    file_name: *const c_char,
+    no_deflate: bool,
+) -> c_int {
+    call(|| {
+        let dir_name = get_str(dir_name, "dir_name")?;
+        let file_name = get_str(file_name, "file_name")?;
+        let options = BuildOptions::new().with_no_deflate(no_deflate);
+        builder::build_cf_file_with_options(dir_name, file_name, &options)?;
+
+        Ok(())
+    })
+}
+
+/// Writes the inflated data of the element `elem_path`, like `Form/form`, of
+/// the container `file_name` to `out_path`, like the `extract` command. A
+/// nested container is unpacked into the directory `out_path`.
+///
+/// Returns `V8_ERR_NOT_FOUND` when the container has no such element.
+///
+/// # Safety
+///
+/// `file_name`, `elem_path` and `out_path` must be null or valid pointers to
+/// nul-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn v8_extract(
+    file_name: *const c_char,
+    elem_path: *const c_char,
+    out_path: *const c_char,
+) -> c_int {
+    call(|| {
+        let file_name = get_str(file_name, "file_name")?;
+        let elem_path = get_str(elem_path, "elem_path")?;
+        let out_path = get_str(out_path, "out_path")?;
+        if !parser::extract_to_path(file_name, elem_path, out_path)? {
+            return Err(FfiError::NotFound(elem_path.to_string()));
+        }
+
+        Ok(())
+    })
+}
+
+/// Description of an element passed to `ListCallback`, see
+/// `parser::ElemInfo`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct V8ElemInfo {
+    /// Path of the element, like `Form/form`, valid during the call only.
+    pub path: *const c_char,
+    /// Nesting level, `0` for the elements of the root container.
+    pub depth: usize,
+    /// Size of the data as stored in the container.
+    pub raw_size: u64,
+    /// Size of the data after inflating.
+    pub inflated_size: u64,
+    /// The data is deflated.
+    pub compressed: bool,
+    /// The data is a nested container.
+    pub v8file: bool,
+    /// Creation time in 1C ticks.
+    pub date_creation: u64,
+    /// Modification time in 1C ticks.
+    pub date_modification: u64,
+}
+
+/// Callback of `v8_list` receiving `user_data` and an element.
+pub type ListCallback =
+    Option<extern "C" fn(user_data: *mut c_void, info: *const V8ElemInfo)>;
+
+/// Calls `callback` with `user_data` for each element of the container
+/// `file_name`, like the `list` command. Nested containers are listed up to
+/// `max_depth` levels, a negative value lists all of them.
+///
+/// # Safety
+///
+/// `file_name` must be null or a valid pointer to a nul-terminated string.
+/// `callback` is called on the calling thread and must not unwind.
+#[no_mangle]
+pub unsafe extern "C" fn v8_list(
+    file_name: *const c_char,
+    max_depth: c_int,
+    callback: ListCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    call(|| {
+        let file_name = get_str(file_name, "file_name")?;
+        let callback = callback
+            .ok_or_else(|| FfiError::InvalidArgument("callback is null".to_string()))?;
+        let max_depth = if max_depth < 0 {
+            None
+        } else {
+            Some(max_depth as usize)
+        };
+
+        for elem in parser::list_file(file_name, max_depth)? {
+            let path = CString::new(elem.path.replace('\0', " ")).unwrap_or_default();
+            let info = V8ElemInfo {
+                path: path.as_ptr(),
+                depth: elem.depth,
+                raw_size: elem.raw_size,
+                inflated_size: elem.inflated_size,
+                compressed: elem.compressed,
+                v8file: elem.v8file,
+                date_creation: elem.date_creation,
+                date_modification: elem.date_modification,
+            };
+            callback(user_data, &info);
+        }
+
+        Ok(())
+    })
+}
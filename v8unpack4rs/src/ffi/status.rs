@@ -0,0 +1,138 @@
+//! Status codes and the text of the last error of the C interface.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use crate::error::V8Error;
+
+/// The operation succeeded.
+pub const V8_OK: c_int = 0;
+/// A null pointer or a string which is not UTF-8 was passed.
+pub const V8_ERR_INVALID_ARGUMENT: c_int = 1;
+/// Reading or writing a file failed.
+pub const V8_ERR_IO: c_int = 2;
+/// The input is not a 1C v8 container.
+pub const V8_ERR_NOT_V8FILE: c_int = 3;
+/// The container is damaged.
+pub const V8_ERR_BAD_FORMAT: c_int = 4;
+/// The container has no such element.
+pub const V8_ERR_NOT_FOUND: c_int = 5;
+/// The container exceeds the limits of the 32-bit format.
+pub const V8_ERR_LIMIT_EXCEEDED: c_int = 6;
+/// The operation was cancelled.
+pub const V8_ERR_CANCELLED: c_int = 7;
+/// The library panicked, this is a bug.
+pub const V8_ERR_PANIC: c_int = 8;
+
+/// Errors of the C interface with their status codes.
+#[derive(Debug)]
+pub(crate) enum FfiError {
+    InvalidArgument(String),
+    NotFound(String),
+    V8(V8Error),
+}
+
+impl FfiError {
+    pub(crate) fn status(&self) -> c_int {
+        match *self {
+            FfiError::InvalidArgument(_) => V8_ERR_INVALID_ARGUMENT,
+            FfiError::NotFound(_) => V8_ERR_NOT_FOUND,
+            FfiError::V8(ref e) => match *e {
+                V8Error::IoError(_) => V8_ERR_IO,
+                V8Error::NotV8File { .. } => V8_ERR_NOT_V8FILE,
+                V8Error::LimitExceeded { .. } => V8_ERR_LIMIT_EXCEEDED,
+                V8Error::Cancelled => V8_ERR_CANCELLED,
+                _ => V8_ERR_BAD_FORMAT,
+            },
+        }
+    }
+
+    fn message(&self) -> String {
+        match *self {
+            FfiError::InvalidArgument(ref message) => message.clone(),
+            FfiError::NotFound(ref elem_path) => {
+                format!("The container has no element {}", elem_path)
+            }
+            FfiError::V8(ref e) => e.to_string(),
+        }
+    }
+}
+
+impl From<V8Error> for FfiError {
+    fn from(other: V8Error) -> FfiError {
+        FfiError::V8(other)
+    }
+}
+
+pub(crate) type FfiResult<T> = Result<T, FfiError>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: Option<String>) {
+    let message = message
+        .map(|message| CString::new(message.replace('\0', " ")).unwrap_or_default());
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+/// Runs `f`, turning its errors and panics into status codes and storing
+/// the text of the error for `v8_last_error_message`.
+pub(crate) fn call<F: FnOnce() -> FfiResult<()>>(f: F) -> c_int {
+    set_last_error(None);
+    let (status, message) = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => (V8_OK, None),
+        Ok(Err(e)) => (e.status(), Some(e.message())),
+        Err(_) => (V8_ERR_PANIC, Some("The library panicked".to_string())),
+    };
+    set_last_error(message);
+
+    status
+}
+
+/// Reads the nul-terminated UTF-8 string `ptr`, `name` is the name of the
+/// argument for the error message.
+///
+/// # Safety
+///
+/// `ptr` must be null or a valid pointer to a nul-terminated string.
+pub(crate) unsafe fn get_str<'a>(ptr: *const c_char, name: &str) -> FfiResult<&'a str> {
+    if ptr.is_null() {
+        return Err(FfiError::InvalidArgument(format!("{} is null", name)));
+    }
+
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|e| FfiError::InvalidArgument(format!("{} is not UTF-8: {}", name, e)))
+}
+
+/// Stores `value` in the output argument `out`, `name` is the name of the
+/// argument for the error message.
+///
+/// # Safety
+///
+/// `out` must be null or a valid pointer to write a `T` to.
+pub(crate) unsafe fn write_out<T>(out: *mut T, name: &str, value: T) -> FfiResult<()> {
+    if out.is_null() {
+        return Err(FfiError::InvalidArgument(format!("{} is null", name)));
+    }
+    out.write(value);
+
+    Ok(())
+}
+
+/// Returns the text of the error of the last call of the C interface on the
+/// calling thread, or null when it succeeded.
+///
+/// The string is owned by the library and stays valid until the next call
+/// on the same thread.
+#[no_mangle]
+pub extern "C" fn v8_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| match *last.borrow() {
+        Some(ref message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
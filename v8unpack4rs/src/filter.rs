@@ -0,0 +1,102 @@
+//! Selection of elements by glob patterns on their paths.
+
+use crate::glob;
+
+/// Include and exclude patterns on the paths of elements, like `Form/form`
+/// or `*/text`.
+///
+/// An element is selected when there are no include patterns or its path,
+/// or the path of an enclosing container, matches one of them, and its path
+/// matches no exclude pattern. `*` matches within a path component, `**`
+/// matches across components and `?` matches a single character.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ElemFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl ElemFilter {
+    /// Creates a new instance of `ElemFilter` selecting all elements.
+    pub fn new() -> ElemFilter {
+        ElemFilter::default()
+    }
+
+    /// Adds a pattern of the paths of the selected elements.
+    pub fn with_include(mut self, pattern: &str) -> Self {
+        self.include.push(pattern.to_string());
+
+        self
+    }
+
+    pub fn get_include(&self) -> &[String] {
+        &self.include
+    }
+
+    /// Adds a pattern of the paths of the skipped elements.
+    pub fn with_exclude(mut self, pattern: &str) -> Self {
+        self.exclude.push(pattern.to_string());
+
+        self
+    }
+
+    pub fn get_exclude(&self) -> &[String] {
+        &self.exclude
+    }
+
+    /// Checks whether the filter selects all elements.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Checks whether the element at `path` is selected.
+    pub fn accepts(&self, path: &str) -> bool {
+        !self.excluded(path) && self.included(path)
+    }
+
+    /// Checks whether the element at `path` is selected or may be a nested
+    /// container with selected elements, that is whether it has to be read
+    /// and inflated at all.
+    pub fn visits(&self, path: &str) -> bool {
+        if self.excluded(path) {
+            return false;
+        }
+
+        let below = format!("{}/", path);
+        self.included(path)
+            || self
+                .include
+                .iter()
+                .any(|pattern| glob::matches_prefix(pattern, &below))
+    }
+
+    /// Checks whether a source file, or a directory of a nested container
+    /// when `is_dir` is set, goes into the built container.
+    pub fn selects_source(&self, path: &str, is_dir: bool) -> bool {
+        if is_dir {
+            self.visits(path)
+        } else {
+            self.accepts(path)
+        }
+    }
+
+    fn excluded(&self, path: &str) -> bool {
+        self.exclude
+            .iter()
+            .any(|pattern| glob::matches(pattern, path))
+    }
+
+    fn included(&self, path: &str) -> bool {
+        if self.include.is_empty() {
+            return true;
+        }
+
+        path.match_indices('/')
+            .map(|(end, _)| &path[..end])
+            .chain(Some(path))
+            .any(|prefix| {
+                self.include
+                    .iter()
+                    .any(|pattern| glob::matches(pattern, prefix))
+            })
+    }
+}
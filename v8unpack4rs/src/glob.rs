@@ -0,0 +1,52 @@
+//! Matching of paths against glob patterns.
+//!
+//! `*` matches any characters except `/`, `**` matches any characters
+//! including `/` and `?` matches a single character except `/`. A `**/`
+//! also matches no directories at all, so `**/form` matches `form`.
+
+/// Checks whether `path` with `/` separators matches the `pattern`.
+pub(crate) fn matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+
+    match_from(&pattern, &path, false)
+}
+
+/// Checks whether `path` is the beginning of some path matching the
+/// `pattern`, so that `Form/` may lead to a match of `*/text`.
+pub(crate) fn matches_prefix(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+
+    match_from(&pattern, &path, true)
+}
+
+/// Checks whether `text` contains the special characters of a pattern.
+pub(crate) fn is_pattern(text: &str) -> bool {
+    text.contains(['*', '?'])
+}
+
+fn match_from(pattern: &[char], path: &[char], prefix: bool) -> bool {
+    if prefix && path.is_empty() {
+        return true;
+    }
+
+    match pattern.first() {
+        None => path.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            (rest.first() == Some(&'/') && match_from(&rest[1..], path, prefix))
+                || (0..=path.len()).any(|i| match_from(rest, &path[i..], prefix))
+        }
+        Some('*') => (0..=path.len())
+            .take_while(|&i| i == 0 || path[i - 1] != '/')
+            .any(|i| match_from(&pattern[1..], &path[i..], prefix)),
+        Some('?') => match path.first() {
+            Some(&c) if c != '/' => match_from(&pattern[1..], &path[1..], prefix),
+            _ => false,
+        },
+        Some(c) => {
+            path.first() == Some(c) && match_from(&pattern[1..], &path[1..], prefix)
+        }
+    }
+}
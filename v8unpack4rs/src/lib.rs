@@ -1,8 +1,19 @@
+#[cfg(feature = "archive")]
+pub mod archive;
+pub mod batch;
 pub mod builder;
+pub mod cancel;
 pub mod container;
 pub mod error;
+pub mod filter;
 pub mod parser;
+pub mod progress;
+pub mod sink;
+pub mod source;
+pub mod tree;
+pub mod view;
 
 mod ffi;
+mod glob;
 
 pub use ffi::*;
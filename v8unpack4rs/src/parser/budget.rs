@@ -0,0 +1,236 @@
+//! Byte-based backpressure for the multi-threaded pipeline.
+
+use std::io::prelude::*;
+use std::io::{self, BufReader, Cursor, SeekFrom};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::{cmp, fs};
+
+use log::*;
+
+use crate::container::{inflate_failed, Result, V8Container};
+use crate::view::ContainerView;
+
+/// Limits the number of bytes of element data held in memory by all stages
+/// of the pipeline at once.
+///
+/// The bytes of inflated elements are also counted separately: only they are
+/// released without the help of the inflate stage, so it waits for them and
+/// never for the compressed elements queued before it.
+#[derive(Debug)]
+pub(crate) struct MemoryBudget {
+    limit: u64,
+    usage: Mutex<Usage>,
+    released: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    used: u64,
+    inflated: u64,
+}
+
+impl MemoryBudget {
+    pub(crate) fn new(limit: Option<u64>) -> Arc<MemoryBudget> {
+        Arc::new(MemoryBudget {
+            limit: limit.unwrap_or(u64::MAX),
+            usage: Mutex::new(Usage::default()),
+            released: Condvar::new(),
+        })
+    }
+
+    /// Elements with more bytes than this are spilled to temporary files.
+    pub(crate) fn spill_threshold(&self) -> u64 {
+        self.limit
+    }
+
+    /// Waits until `bytes` of compressed data fit into the budget. The caller
+    /// is let through once nothing else is held, so a single element larger
+    /// than the rest of the budget does not wait forever.
+    pub(crate) fn acquire(this: &Arc<MemoryBudget>, bytes: u64) -> Reservation {
+        let mut usage = this.lock();
+        while usage.used > 0 && usage.used.saturating_add(bytes) > this.limit {
+            usage = this.released.wait(usage).unwrap_or_else(|e| e.into_inner());
+        }
+        usage.used = usage.used.saturating_add(bytes);
+
+        Reservation {
+            budget: Arc::clone(this),
+            bytes,
+            inflated: false,
+        }
+    }
+
+    /// Returns an empty reservation for inflated data, see
+    /// `Reservation::grow`.
+    pub(crate) fn inflated(this: &Arc<MemoryBudget>) -> Reservation {
+        Reservation {
+            budget: Arc::clone(this),
+            bytes: 0,
+            inflated: true,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Usage> {
+        self.usage.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn release(&self, bytes: u64, inflated: bool) {
+        let mut usage = self.lock();
+        usage.used = usage.used.saturating_sub(bytes);
+        if inflated {
+            usage.inflated = usage.inflated.saturating_sub(bytes);
+        }
+        self.released.notify_all();
+    }
+}
+
+/// Bytes reserved in a `MemoryBudget`, returned when dropped.
+#[derive(Debug)]
+pub(crate) struct Reservation {
+    budget: Arc<MemoryBudget>,
+    bytes: u64,
+    inflated: bool,
+}
+
+impl Reservation {
+    /// Reserves `bytes` more inflated bytes. With `wait` it waits while the
+    /// later stages hold inflated data, which they release on their own.
+    /// Returns `false` if the bytes do not fit, the data has to be spilled
+    /// then.
+    pub(crate) fn grow(&mut self, bytes: u64, wait: bool) -> bool {
+        let budget = &self.budget;
+        let mut usage = budget.lock();
+        while wait
+            && usage.inflated > self.bytes
+            && usage.used.saturating_add(bytes) > budget.limit
+        {
+            usage = budget
+                .released
+                .wait(usage)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        if usage.used.saturating_add(bytes) > budget.limit {
+            return false;
+        }
+
+        usage.used += bytes;
+        usage.inflated += bytes;
+        self.bytes += bytes;
+        true
+    }
+
+    /// Returns all the bytes to the budget.
+    pub(crate) fn clear(&mut self) {
+        self.budget.release(self.bytes, self.inflated);
+        self.bytes = 0;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Data of an element, in memory or spilled to a temporary file.
+#[derive(Debug)]
+pub(crate) enum ElemData {
+    Memory(Vec<u8>),
+    Spilled { file: fs::File, len: u64 },
+}
+
+impl ElemData {
+    /// Returns a reader positioned at the beginning of the data.
+    pub(crate) fn reader(&mut self) -> Result<Box<dyn BufRead + '_>> {
+        match *self {
+            ElemData::Memory(ref data) => Ok(Box::new(Cursor::new(data.as_slice()))),
+            ElemData::Spilled { ref mut file, .. } => {
+                file.seek(SeekFrom::Start(0))?;
+                Ok(Box::new(BufReader::new(file)))
+            }
+        }
+    }
+}
+
+/// Inflates the data read from `src`, keeping in memory as much of the
+/// output as `reservation` can grow to, see `Reservation::grow`, and
+/// spilling the rest to a temporary file. Returns `None` if the data is not
+/// deflated, and fails with `V8Error::InflateFailed` at `offset` if it breaks
+/// off after inflating the beginning of a nested container.
+pub(crate) fn inflate_elem_data<R: BufRead>(
+    src: R,
+    reservation: &mut Reservation,
+    wait: bool,
+    offset: u64,
+) -> Result<Option<ElemData>> {
+    let mut decoder = inflate::DeflateDecoderBuf::new(src);
+    let mut memory = vec![];
+    let mut spill: Option<fs::File> = None;
+    let mut buf = vec![0; 64 * 1024];
+
+    loop {
+        let read = match decoder.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                reservation.clear();
+                let is_v8file = match spill {
+                    Some(ref file) => BufReader::new(file.try_clone()?).is_v8file(),
+                    None => ContainerView::is_v8file(&memory),
+                };
+                if is_v8file {
+                    return Err(inflate_failed(offset, e.to_string()));
+                }
+                return Ok(None);
+            }
+        };
+
+        if spill.is_none() && !reserve(&mut memory, read, reservation, wait) {
+            debug!("spill inflated element to a temporary file");
+            let mut file = tempfile::tempfile()?;
+            file.write_all(&memory)?;
+            memory = vec![];
+            reservation.clear();
+            spill = Some(file);
+        }
+        match spill {
+            Some(ref mut file) => file.write_all(&buf[..read])?,
+            None => memory.extend_from_slice(&buf[..read]),
+        }
+    }
+
+    Ok(Some(match spill {
+        Some(file) => {
+            let len = decoder.total_out();
+            ElemData::Spilled { file, len }
+        }
+        None => ElemData::Memory(memory),
+    }))
+}
+
+/// Makes room for `additional` more bytes in `memory`, growing `reservation`
+/// by the capacity allocated.
+fn reserve(
+    memory: &mut Vec<u8>,
+    additional: usize,
+    reservation: &mut Reservation,
+    wait: bool,
+) -> bool {
+    let needed = memory.len() + additional;
+    let capacity = memory.capacity();
+    if needed <= capacity {
+        return true;
+    }
+
+    // Doubling keeps the copying linear, the exact size is the fallback
+    // when the budget is nearly used up.
+    for target in [cmp::max(needed, capacity * 2), needed] {
+        if reservation.grow((target - capacity) as u64, wait) {
+            memory.reserve_exact(target - memory.len());
+            return true;
+        }
+    }
+
+    false
+}
@@ -0,0 +1,148 @@
+//! Extraction of single elements without unpacking the whole container.
+
+use crate::container::*;
+use crate::error;
+use crate::sink::DirSink;
+use crate::view::ContainerView;
+
+use super::file::ContainerFile;
+use super::single::{inflate_elem, save_view_to_sink};
+
+use log::*;
+use std::borrow::Cow;
+use std::io::Write;
+use std::{fs, path};
+
+/// Returns the inflated data of the element at `elem_path`, like
+/// `Form/form`, descending into nested containers. Returns `None` when the
+/// container has no such element.
+pub fn find_elem(data: &[u8], elem_path: &str) -> Result<Option<Vec<u8>>> {
+    let mut current = Cow::Borrowed(data);
+    let mut parent = String::new();
+
+    for name in elem_path.split('/').filter(|name| !name.is_empty()) {
+        if !ContainerView::is_v8file(&current) {
+            return Ok(None);
+        }
+
+        let found = find_in_container(&current, name).map_err(|e| {
+            if parent.is_empty() {
+                e
+            } else {
+                e.nested().in_elem(&parent)
+            }
+        })?;
+        match found {
+            Some(elem_data) => current = Cow::Owned(elem_data),
+            None => return Ok(None),
+        }
+
+        if !parent.is_empty() {
+            parent.push('/');
+        }
+        parent.push_str(name);
+    }
+
+    Ok(Some(current.into_owned()))
+}
+
+fn find_in_container(data: &[u8], name: &str) -> Result<Option<Vec<u8>>> {
+    let view = ContainerView::new(data)?;
+    for elem in view.iter() {
+        let elem = elem?;
+        if elem.get_name()? == name {
+            let raw_data = Cow::Borrowed(elem.get_data().unwrap_or(&[]));
+            let elem_data = inflate_elem(raw_data, u64::from(elem.get_data_addr()))
+                .map_err(|e| e.in_elem(name))?;
+            return Ok(Some(elem_data.into_owned()));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Finds the element like `find_elem` does, reading from the container file
+/// only the headers of the root elements and the data of the found one.
+fn find_in_file(file_name: &str, elem_path: &str) -> Result<Option<Vec<u8>>> {
+    let mut container = match ContainerFile::open(file_name) {
+        Err(e @ error::V8Error::NotV8File { .. }) => {
+            error!("the file is not in the correct format");
+            return Err(e);
+        }
+        container => container?,
+    };
+
+    let mut names = elem_path.split('/').filter(|name| !name.is_empty());
+    let name = match names.next() {
+        Some(name) => name,
+        None => return Ok(Some(fs::read(file_name)?)),
+    };
+    let elem_data = match container.find(name)? {
+        Some(index) => {
+            let raw_data = container.read_data(index)?.unwrap_or_default();
+            let data_addr = container.get_elems_addrs()[index].elem_data_addr;
+            inflate_elem(Cow::Owned(raw_data), u64::from(data_addr))
+                .map_err(|e| e.in_elem(name))?
+                .into_owned()
+        }
+        None => return Ok(None),
+    };
+
+    let rest: Vec<&str> = names.collect();
+    if rest.is_empty() {
+        return Ok(Some(elem_data));
+    }
+
+    find_elem(&elem_data, &rest.join("/")).map_err(|e| e.nested().in_elem(name))
+}
+
+/// Extracts the element at `elem_path` of the container `file_name` to
+/// `out_path`. A nested container is unpacked into the directory `out_path`
+/// the same way as `unpack_to_directory_no_load` does.
+///
+/// Returns `false` when the container has no such element.
+pub fn extract_to_path(
+    file_name: &str,
+    elem_path: &str,
+    out_path: &str,
+) -> Result<bool> {
+    info!("extract element {} from {}", elem_path, file_name);
+    let elem_data = match find_in_file(file_name, elem_path)? {
+        Some(elem_data) => elem_data,
+        None => return Ok(false),
+    };
+
+    let out_path = path::Path::new(out_path);
+    if ContainerView::is_v8file(&elem_data) {
+        ContainerView::new(&elem_data)
+            .and_then(|view| {
+                save_view_to_sink(&view, out_path, &mut DirSink::new(false))
+            })
+            .map_err(|e| e.nested().in_elem(elem_path.trim_matches('/')))?;
+    } else {
+        fs::write(out_path, &elem_data)?;
+    }
+
+    Ok(true)
+}
+
+/// Writes the inflated data of the element at `elem_path` of the container
+/// `file_name` to `out`. A nested container is written as is, as a container
+/// file.
+///
+/// Returns `false` when the container has no such element.
+pub fn extract_to_writer<W: Write>(
+    file_name: &str,
+    elem_path: &str,
+    out: &mut W,
+) -> Result<bool> {
+    info!("extract element {} from {}", elem_path, file_name);
+    match find_in_file(file_name, elem_path)? {
+        Some(elem_data) => {
+            out.write_all(&elem_data)?;
+            out.flush()?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
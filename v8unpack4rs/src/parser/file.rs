@@ -0,0 +1,104 @@
+//! Reading of the elements of a container file one by one, without loading
+//! the whole file into memory.
+
+use crate::container::*;
+use crate::error;
+
+use super::single::{read_block_data, read_elems_addrs};
+
+use std::io::{BufReader, Seek, SeekFrom};
+use std::{fs, path};
+
+/// Container file with its table of contents read.
+pub(crate) struct ContainerFile {
+    rdr: BufReader<fs::File>,
+    file_header: FileHeader,
+    toc_size: u32,
+    elems_addrs: Vec<ElemAddr>,
+}
+
+impl ContainerFile {
+    /// Opens the container file and reads its table of contents.
+    pub(crate) fn open<P: AsRef<path::Path>>(file_name: P) -> Result<ContainerFile> {
+        let mut rdr = BufReader::new(fs::File::open(file_name)?);
+        if !rdr.is_v8file() {
+            return Err(error::V8Error::NotV8File { offset: 0 });
+        }
+
+        let file_header = rdr.get_file_header()?;
+        let toc_header = rdr.get_first_block_header()?;
+        let mut elems_addrs = read_elems_addrs(&mut rdr, &toc_header)?;
+        if let Some(end) = elems_addrs
+            .iter()
+            .position(|elem_addr| elem_addr.fffffff != V8_MAGIC_NUMBER)
+        {
+            elems_addrs.truncate(end);
+        }
+
+        Ok(ContainerFile {
+            rdr,
+            file_header,
+            toc_size: toc_header.get_data_size()?,
+            elems_addrs,
+        })
+    }
+
+    pub(crate) fn get_file_header(&self) -> &FileHeader {
+        &self.file_header
+    }
+
+    /// Returns the size of the data of the table of contents.
+    pub(crate) fn get_toc_size(&self) -> u32 {
+        self.toc_size
+    }
+
+    pub(crate) fn get_elems_addrs(&self) -> &[ElemAddr] {
+        &self.elems_addrs
+    }
+
+    /// Returns the number of elements in the container.
+    pub(crate) fn len(&self) -> usize {
+        self.elems_addrs.len()
+    }
+
+    /// Reads the header of the element with the given index.
+    pub(crate) fn read_header(&mut self, index: usize) -> Result<Vec<u8>> {
+        let offset = self.elems_addrs[index].elem_header_addr;
+        self.read_block(offset)
+    }
+
+    /// Reads the raw data of the element with the given index, `None` for an
+    /// element without data.
+    pub(crate) fn read_data(&mut self, index: usize) -> Result<Option<Vec<u8>>> {
+        let offset = self.elems_addrs[index].elem_data_addr;
+        if offset == V8_MAGIC_NUMBER {
+            return Ok(None);
+        }
+
+        self.read_block(offset).map(Some)
+    }
+
+    /// Returns the index of the element with the given name.
+    pub(crate) fn find(&mut self, name: &str) -> Result<Option<usize>> {
+        for index in 0..self.len() {
+            let header = self.read_header(index)?;
+            let offset = u64::from(self.elems_addrs[index].elem_header_addr);
+            if elem_name(&header, offset)? == name {
+                return Ok(Some(index));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn read_block(&mut self, offset: u32) -> Result<Vec<u8>> {
+        self.rdr.seek(SeekFrom::Start(u64::from(offset)))?;
+        let block_header = BlockHeader::from_raw_parts(&mut self.rdr)
+            .map_err(|_| truncated_block(u64::from(offset)))?;
+        if !block_header.is_correct() {
+            return Err(bad_block_header(u64::from(offset)));
+        }
+
+        read_block_data(&mut self.rdr, &block_header)
+    }
+}
@@ -0,0 +1,182 @@
+//! Summary of the headers of a container, used to diagnose damaged files.
+
+use crate::container::*;
+use crate::error;
+use crate::view::ContainerView;
+
+use super::file::ContainerFile;
+use super::single::{try_inflate_bytes, try_inflate_slice};
+
+use byteorder::{ByteOrder, LittleEndian};
+use std::io::Read;
+use std::{fmt, fs, str};
+
+/// Size of the file header of the 64-bit format.
+const FILE_HEADER_64_SIZE: usize = 8 + 4 + 4 + 4;
+/// Size of the block header of the 64-bit format,
+/// `\r\nXXXXXXXXXXXXXXXX XXXXXXXXXXXXXXXX XXXXXXXXXXXXXXXX \r\n`.
+const BLOCK_HEADER_64_SIZE: usize = 2 + 16 + 1 + 16 + 1 + 16 + 1 + 2;
+
+/// Variant of the container format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerFormat {
+    /// Offsets and sizes of 32 bits, files up to 4 GiB.
+    Format32,
+    /// Offsets and sizes of 64 bits, detected but not supported yet.
+    Format64,
+}
+
+impl fmt::Display for ContainerFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ContainerFormat::Format32 => write!(f, "32-bit"),
+            ContainerFormat::Format64 => write!(f, "64-bit"),
+        }
+    }
+}
+
+/// Summary of the headers of a container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerInfo {
+    pub format: ContainerFormat,
+    pub file_size: u64,
+    /// Fields of the file header.
+    pub next_page_addr: u64,
+    pub page_size: u32,
+    pub storage_ver: u32,
+    /// Size of the data of the table of contents.
+    pub toc_size: u64,
+    /// Number of the elements of the root container, `None` for the 64-bit
+    /// format.
+    pub elem_count: Option<usize>,
+    /// Platform version stored in the `version` element, like `216.0`.
+    pub platform_version: Option<String>,
+}
+
+/// Reads the summary of the headers of the container in `data`.
+pub fn container_info(data: &[u8]) -> Result<ContainerInfo> {
+    if ContainerView::is_v8file(data) {
+        let view = ContainerView::new(data)?;
+        let file_header = view.get_file_header();
+        let toc_header = BlockHeader::from_bytes(
+            &data[FileHeader::SIZE as usize..],
+            u64::from(FileHeader::SIZE),
+        )?;
+
+        Ok(ContainerInfo {
+            format: ContainerFormat::Format32,
+            file_size: data.len() as u64,
+            next_page_addr: u64::from(file_header.get_next_page_addr()),
+            page_size: file_header.get_page_size(),
+            storage_ver: file_header.get_storage_ver(),
+            toc_size: u64::from(toc_header.get_data_size()?),
+            elem_count: Some(view.get_elems_addrs().len()),
+            platform_version: platform_version(&view)?,
+        })
+    } else {
+        info_64(data, data.len() as u64)
+    }
+}
+
+/// Reads the summary of the headers of the container file, see
+/// `container_info`. Only the headers, the table of contents and the
+/// `version` element are read from the file.
+pub fn file_info(file_name: &str) -> Result<ContainerInfo> {
+    let file_size = fs::metadata(file_name)?.len();
+    let mut container = match ContainerFile::open(file_name) {
+        Ok(container) => container,
+        Err(error::V8Error::NotV8File { .. }) => {
+            let mut head = vec![];
+            fs::File::open(file_name)?
+                .take((FILE_HEADER_64_SIZE + BLOCK_HEADER_64_SIZE) as u64)
+                .read_to_end(&mut head)?;
+            return info_64(&head, file_size);
+        }
+        Err(e) => return Err(e),
+    };
+
+    let mut platform_version = None;
+    if let Some(index) = container.find("version")? {
+        let data = try_inflate_bytes(container.read_data(index)?.unwrap_or_default());
+        platform_version = parse_version(&String::from_utf8_lossy(&data));
+    }
+
+    let file_header = container.get_file_header();
+    Ok(ContainerInfo {
+        format: ContainerFormat::Format32,
+        file_size,
+        next_page_addr: u64::from(file_header.get_next_page_addr()),
+        page_size: file_header.get_page_size(),
+        storage_ver: file_header.get_storage_ver(),
+        toc_size: u64::from(container.get_toc_size()),
+        elem_count: Some(container.len()),
+        platform_version,
+    })
+}
+
+/// Reads the summary of the headers of the 64-bit format from the beginning
+/// of the file in `head`.
+fn info_64(head: &[u8], file_size: u64) -> Result<ContainerInfo> {
+    match block_header_64_data_size(head) {
+        Some(toc_size) => Ok(ContainerInfo {
+            format: ContainerFormat::Format64,
+            file_size,
+            next_page_addr: LittleEndian::read_u64(&head[..8]),
+            page_size: LittleEndian::read_u32(&head[8..12]),
+            storage_ver: LittleEndian::read_u32(&head[12..16]),
+            toc_size,
+            elem_count: None,
+            platform_version: None,
+        }),
+        None => Err(error::V8Error::NotV8File { offset: 0 }),
+    }
+}
+
+/// Returns the size of the table of contents when `data` starts with the
+/// headers of the 64-bit format.
+fn block_header_64_data_size(data: &[u8]) -> Option<u64> {
+    let header =
+        data.get(FILE_HEADER_64_SIZE..FILE_HEADER_64_SIZE + BLOCK_HEADER_64_SIZE)?;
+    if &header[..2] != b"\r\n" || &header[BLOCK_HEADER_64_SIZE - 3..] != b" \r\n" {
+        return None;
+    }
+
+    let mut fields = header[2..BLOCK_HEADER_64_SIZE - 3].split(|&c| c == b' ');
+    let data_size = fields.next()?;
+    let is_hex =
+        |field: &[u8]| field.len() == 16 && field.iter().all(u8::is_ascii_hexdigit);
+    if !is_hex(data_size) || !fields.all(is_hex) {
+        return None;
+    }
+
+    u64::from_str_radix(str::from_utf8(data_size).ok()?, 16).ok()
+}
+
+fn platform_version(view: &ContainerView) -> Result<Option<String>> {
+    for elem in view.iter() {
+        let elem = elem?;
+        if elem.get_name()? == "version" {
+            let data = try_inflate_slice(elem.get_data().unwrap_or(&[]));
+            return Ok(parse_version(&String::from_utf8_lossy(&data)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Takes the first group of numbers of the `version` element, like
+/// `{{216,0}}`, and joins them with dots.
+fn parse_version(text: &str) -> Option<String> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let group = &text[start..];
+    let group = &group[..group.find('}')?];
+    let numbers: Vec<&str> = group.split(',').map(str::trim).collect();
+    if numbers
+        .iter()
+        .any(|n| n.is_empty() || !n.bytes().all(|c| c.is_ascii_digit()))
+    {
+        return None;
+    }
+
+    Some(numbers.join("."))
+}
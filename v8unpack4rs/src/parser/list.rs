@@ -0,0 +1,226 @@
+//! Listing of the elements of a container without unpacking it.
+
+use crate::container::*;
+use crate::view::ContainerView;
+
+use super::file::ContainerFile;
+
+use inflate::DeflateDecoderBuf;
+use std::io::Read;
+
+/// Description of an element of a container.
+///
+/// Offsets of the elements of a nested container are counted from the
+/// beginning of the nested container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElemInfo {
+    /// Path of the element, like `Form/form`.
+    pub path: String,
+    /// Nesting level, `0` for the elements of the root container.
+    pub depth: usize,
+    /// The offset of the header block.
+    pub header_addr: u32,
+    /// The offset of the data block, `None` for an element without data.
+    pub data_addr: Option<u32>,
+    /// Size of the data as stored in the container.
+    pub raw_size: u64,
+    /// Size of the data after inflating.
+    pub inflated_size: u64,
+    /// The data is deflated.
+    pub compressed: bool,
+    /// The data is a nested container.
+    pub v8file: bool,
+    /// Creation time in 1C ticks, see `container::v8_time_to_unix`.
+    pub date_creation: u64,
+    /// Modification time in 1C ticks, see `container::v8_time_to_unix`.
+    pub date_modification: u64,
+}
+
+/// Lists the elements of the container in `data` in the order of the table
+/// of contents, each nested container followed by its elements.
+///
+/// Nested containers are listed up to `max_depth` levels, `None` lists all
+/// of them and `Some(0)` only the elements of the root container.
+pub fn list_elems(data: &[u8], max_depth: Option<usize>) -> Result<Vec<ElemInfo>> {
+    let mut result = vec![];
+    list_view(&ContainerView::new(data)?, "", 0, max_depth, &mut result)?;
+
+    Ok(result)
+}
+
+/// Lists the elements of the container file, see `list_elems`. The
+/// elements are read one by one, and only the nested containers which are
+/// listed are kept in memory after inflating.
+pub fn list_file(file_name: &str, max_depth: Option<usize>) -> Result<Vec<ElemInfo>> {
+    let mut container = ContainerFile::open(file_name)?;
+    let mut result = vec![];
+    for index in 0..container.len() {
+        let elem_addr = &container.get_elems_addrs()[index];
+        let (header_addr, data_addr) =
+            (elem_addr.elem_header_addr, elem_addr.elem_data_addr);
+        let header = container.read_header(index)?;
+        let data = container.read_data(index)?;
+        list_elem(
+            &RawElem {
+                header: &header,
+                data: data.as_deref(),
+                header_addr,
+                data_addr,
+            },
+            "",
+            0,
+            max_depth,
+            &mut result,
+        )?;
+    }
+
+    Ok(result)
+}
+
+/// Header and raw data of an element with their offsets.
+struct RawElem<'a> {
+    header: &'a [u8],
+    data: Option<&'a [u8]>,
+    header_addr: u32,
+    data_addr: u32,
+}
+
+/// Result of inflating the data of an element.
+struct Inflated {
+    size: u64,
+    v8file: bool,
+    /// The inflated data, kept only for a nested container to be listed.
+    data: Option<Vec<u8>>,
+}
+
+fn list_view(
+    view: &ContainerView,
+    parent: &str,
+    depth: usize,
+    max_depth: Option<usize>,
+    result: &mut Vec<ElemInfo>,
+) -> Result<()> {
+    for elem in view.iter() {
+        let elem = elem?;
+        list_elem(
+            &RawElem {
+                header: elem.get_header(),
+                data: elem.get_data(),
+                header_addr: elem.get_header_addr(),
+                data_addr: elem.get_data_addr(),
+            },
+            parent,
+            depth,
+            max_depth,
+            result,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn list_elem(
+    elem: &RawElem,
+    parent: &str,
+    depth: usize,
+    max_depth: Option<usize>,
+    result: &mut Vec<ElemInfo>,
+) -> Result<()> {
+    let name = elem_name(elem.header, u64::from(elem.header_addr))?;
+    let path = if parent.is_empty() {
+        name.clone()
+    } else {
+        format!("{}/{}", parent, name)
+    };
+
+    let raw_data = elem.data.unwrap_or(&[]);
+    let nested_listed = depth < max_depth.unwrap_or(usize::MAX);
+    let inflated = inflate_elem(raw_data, nested_listed, u64::from(elem.data_addr))
+        .map_err(|e| e.in_elem(&name))?;
+    let (inflated_size, v8file) = match inflated {
+        Some(ref inflated) => (inflated.size, inflated.v8file),
+        None => (raw_data.len() as u64, ContainerView::is_v8file(raw_data)),
+    };
+
+    let header = ElemHeaderBegin::from_bytes(elem.header, u64::from(elem.header_addr))?;
+    result.push(ElemInfo {
+        path: path.clone(),
+        depth,
+        header_addr: elem.header_addr,
+        data_addr: elem.data.map(|_| elem.data_addr),
+        raw_size: raw_data.len() as u64,
+        inflated_size,
+        compressed: inflated.is_some(),
+        v8file,
+        date_creation: header.get_date_creation(),
+        date_modification: header.get_date_modification(),
+    });
+
+    if v8file && nested_listed {
+        let out_data = inflated
+            .as_ref()
+            .and_then(|inflated| inflated.data.as_deref())
+            .unwrap_or(raw_data);
+        let nested = ContainerView::new(out_data)
+            .and_then(|nested| list_view(&nested, &path, depth + 1, max_depth, result));
+        nested.map_err(|e| e.nested().in_elem(&name))?;
+    }
+
+    Ok(())
+}
+
+/// Inflates the data, counting the inflated bytes. The inflated data is kept
+/// only for a nested container when `keep_v8file` is set. Returns `None`
+/// when the data is not deflated, and fails with `V8Error::InflateFailed` at
+/// `offset` when a nested container breaks off.
+fn inflate_elem(
+    raw_data: &[u8],
+    keep_v8file: bool,
+    offset: u64,
+) -> Result<Option<Inflated>> {
+    // A file header and the header of the table of contents.
+    const HEAD_SIZE: usize = (FileHeader::SIZE + BlockHeader::SIZE) as usize;
+
+    let mut decoder = DeflateDecoderBuf::new(raw_data);
+    let mut data = vec![];
+    let mut buf = vec![0; 64 * 1024];
+    let mut size = 0;
+    let mut v8file = None;
+    loop {
+        let read = match decoder.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) => {
+                if v8file.unwrap_or_else(|| ContainerView::is_v8file(&data)) {
+                    return Err(inflate_failed(offset, e.to_string()));
+                }
+                return Ok(None);
+            }
+        };
+        size += read as u64;
+
+        if v8file.is_none() {
+            data.extend_from_slice(&buf[..read]);
+            if data.len() >= HEAD_SIZE {
+                let is_v8file = ContainerView::is_v8file(&data);
+                if !(is_v8file && keep_v8file) {
+                    data = vec![];
+                }
+                v8file = Some(is_v8file);
+            }
+        } else if !data.is_empty() {
+            data.extend_from_slice(&buf[..read]);
+        }
+    }
+
+    let v8file = v8file.unwrap_or(false);
+    Ok(Some(Inflated {
+        size,
+        v8file,
+        data: if v8file && keep_v8file {
+            Some(data)
+        } else {
+            None
+        },
+    }))
+}
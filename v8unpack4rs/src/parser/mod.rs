@@ -1,5 +1,17 @@
+mod budget;
+pub mod extract;
+mod file;
+pub mod info;
+pub mod list;
 pub mod multi;
+pub mod options;
 pub mod single;
+pub mod stream;
 
+pub use self::extract::*;
+pub use self::info::*;
+pub use self::list::*;
 pub use self::multi::*;
+pub use self::options::*;
 pub use self::single::*;
+pub use self::stream::*;
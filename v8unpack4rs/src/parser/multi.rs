@@ -1,41 +1,77 @@
+use crate::cancel::CancellationToken;
 use crate::container::*;
 use crate::error;
+use crate::filter::ElemFilter;
+use crate::progress::Progress;
+use crate::sink::*;
+use crate::view::ContainerView;
 
 use std::io::prelude::*;
-use std::io::{BufReader, Cursor, SeekFrom};
+use std::io::{self, BufReader, SeekFrom};
 use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::Arc;
 use std::thread::{spawn, JoinHandle};
-use std::{fs, path, str};
+use std::{cmp, fs, path, str};
 
+use super::budget::*;
+use super::options::UnpackOptions;
 use super::single;
-use inflate;
 use log::*;
 
+/// Element passed between the stages of the pipeline.
+///
+/// `reservation` accounts for the data held in memory and is returned to the
+/// budget when the element is dropped by the last stage.
+struct PipelineElem {
+    header_addr: u64,
+    header: Vec<u8>,
+    data_addr: u64,
+    data: Option<ElemData>,
+    reservation: Option<Reservation>,
+}
+
+impl PipelineElem {
+    fn get_name(&self) -> Result<String> {
+        elem_name(&self.header, self.header_addr)
+    }
+}
+
 fn start_inflate_thread(
-    v8_elems: Receiver<V8Elem>,
-) -> (Receiver<V8Elem>, JoinHandle<Result<()>>) {
+    v8_elems: Receiver<PipelineElem>,
+    budget: Arc<MemoryBudget>,
+    cancellation: CancellationToken,
+) -> (Receiver<PipelineElem>, JoinHandle<Result<()>>) {
     let (sender, receiver) = sync_channel(128);
 
     let handle = spawn(move || {
         for v8_elem in v8_elems {
+            cancellation.check()?;
             let mut out_element = v8_elem;
-
-            let mut inf_bytes_tuple = (vec![], false);
-
-            if let Some(block_data) = out_element.get_data() {
-                match inflate::inflate_bytes(&block_data) {
-                    Ok(bytes) => {
-                        inf_bytes_tuple.0 = bytes;
-                        inf_bytes_tuple.1 = true;
-                    }
-                    Err(_) => {
-                        inf_bytes_tuple.1 = false;
+            let data_addr = out_element.data_addr;
+
+            if let Some(ref mut block_data) = out_element.data {
+                let mut reservation = MemoryBudget::inflated(&budget);
+                let inflated = inflate_elem_data(
+                    block_data.reader()?,
+                    &mut reservation,
+                    true,
+                    data_addr,
+                );
+                let inflated = match inflated {
+                    Ok(inflated) => inflated,
+                    Err(e) => {
+                        let name =
+                            elem_name(&out_element.header, out_element.header_addr)?;
+                        return Err(e.in_elem(&name));
                     }
-                }
-            }
+                };
 
-            if inf_bytes_tuple.1 {
-                out_element = out_element.with_data(inf_bytes_tuple.0);
+                if let Some(inflated) = inflated {
+                    // Replacing the reservation returns the bytes of the
+                    // compressed data.
+                    *block_data = inflated;
+                    out_element.reservation = Some(reservation);
+                }
             }
 
             if sender.send(out_element).is_err() {
@@ -49,69 +85,344 @@ fn start_inflate_thread(
 }
 
 fn start_file_parse(
-    v8_elems: Receiver<V8Elem>,
+    v8_elems: Receiver<PipelineElem>,
     p_dir: &path::Path,
-    bool_inflate: bool,
-) -> Result<bool> {
+    sink: &mut dyn OutputSink,
+    progress: &dyn Progress,
+    budget: &Arc<MemoryBudget>,
+) -> Result<()> {
     for v8_elem in v8_elems {
         let name = v8_elem.get_name()?;
         info!("parse element {}", name);
-        let elem_path = p_dir.join(name);
+        progress.elem_started(&name);
+        let elem_path = p_dir.join(&name);
+
+        match v8_elem.data {
+            Some(data) => save_elem_data(data, &elem_path, sink, budget)
+                .map_err(|e| e.in_elem(&name))?,
+            None => debug!("skip element {} without data", name),
+        }
+        progress.elem_finished(&name);
+    }
+    Ok(())
+}
 
-        if let Some(out_data) = v8_elem.get_data() {
-            let mut rdr = Cursor::new(&out_data);
+/// Writes the data of an element through `sink`. The elements of nested
+/// containers are inflated one at a time, the inflated data is counted
+/// against `budget` and spilled to temporary files when it does not fit.
+fn save_elem_data(
+    data: ElemData,
+    elem_path: &path::Path,
+    sink: &mut dyn OutputSink,
+    budget: &Arc<MemoryBudget>,
+) -> Result<()> {
+    match data {
+        ElemData::Memory(ref out_data) => {
+            save_elem_slice(out_data, elem_path, sink, budget)?
+        }
+        ElemData::Spilled { file, len } => {
+            let mut rdr = BufReader::new(file);
             if rdr.is_v8file() {
-                single::load_file(&mut rdr, bool_inflate)?
-                    .save_file_to_folder(&elem_path)?;
+                save_spilled_container(&mut rdr, elem_path, sink, budget)
+                    .map_err(error::V8Error::nested)?;
             } else {
-                fs::File::create(elem_path.as_path())?.write_all(&out_data)?;
+                rdr.seek(SeekFrom::Start(0))?;
+                sink.write_file_from(elem_path, &mut rdr, len)?;
             }
-        } else {
-            return Ok(false);
         }
     }
-    Ok(true)
+    Ok(())
+}
+
+fn save_elem_slice(
+    data: &[u8],
+    elem_path: &path::Path,
+    sink: &mut dyn OutputSink,
+    budget: &Arc<MemoryBudget>,
+) -> Result<()> {
+    if ContainerView::is_v8file(data) {
+        ContainerView::new(data)
+            .and_then(|view| save_view_elems(&view, elem_path, sink, budget))
+            .map_err(error::V8Error::nested)
+    } else {
+        sink.write_file(elem_path, data)
+    }
+}
+
+/// Stores the elements of a nested container held in memory, like
+/// `single::save_view_to_sink` does.
+fn save_view_elems(
+    view: &ContainerView,
+    elem_path: &path::Path,
+    sink: &mut dyn OutputSink,
+    budget: &Arc<MemoryBudget>,
+) -> Result<()> {
+    sink.create_dir(elem_path)?;
+
+    for elem in view.iter() {
+        sink.get_cancellation().check()?;
+        let elem = elem?;
+        let name = elem.get_name()?;
+        let out_path = elem_path.join(&name);
+        if !sink.visits(&out_path) {
+            debug!("skip filtered element {}", name);
+            continue;
+        }
+        info!("parse element {}", name);
+
+        let raw = elem.get_data().unwrap_or(&[]);
+        let data_addr = u64::from(elem.get_data_addr());
+        let mut reservation = MemoryBudget::inflated(budget);
+        let inflated = inflate_elem_data(raw, &mut reservation, false, data_addr)
+            .map_err(|e| e.in_elem(&name))?;
+        match inflated {
+            Some(data) => save_elem_data(data, &out_path, sink, budget),
+            None => save_elem_slice(raw, &out_path, sink, budget),
+        }
+        .map_err(|e| e.in_elem(&name))?;
+    }
+
+    Ok(())
+}
+
+fn save_spilled_container(
+    rdr: &mut BufReader<fs::File>,
+    p_dir: &path::Path,
+    sink: &mut dyn OutputSink,
+    budget: &Arc<MemoryBudget>,
+) -> Result<()> {
+    let first_block_header = rdr.get_first_block_header()?;
+    sink.create_dir(p_dir)?;
+
+    let elems_addrs = single::read_elems_addrs(rdr, &first_block_header)?;
+    for cur_elem in elems_addrs.iter() {
+        debug!("{:?}", cur_elem);
+        sink.get_cancellation().check()?;
+        if cur_elem.fffffff != V8_MAGIC_NUMBER {
+            break;
+        }
+
+        let pos = rdr.seek(SeekFrom::Start(u64::from(cur_elem.elem_header_addr)))?;
+        let header = BlockHeader::from_raw_parts(rdr)?;
+        if !header.is_correct() {
+            error!("the file is not in the correct format");
+            return Err(bad_block_header(pos));
+        }
+
+        let name = elem_name(&single::read_block_data(rdr, &header)?, pos)?;
+        let elem_path = p_dir.join(&name);
+        if !sink.visits(&elem_path) || cur_elem.elem_data_addr == V8_MAGIC_NUMBER {
+            continue;
+        }
+
+        rdr.seek(SeekFrom::Start(u64::from(cur_elem.elem_data_addr)))?;
+        let mut raw_reservation = MemoryBudget::inflated(budget);
+        let mut data =
+            read_elem_data(rdr, &mut raw_reservation, sink.get_cancellation())?;
+        let mut reservation = MemoryBudget::inflated(budget);
+        let data_addr = u64::from(cur_elem.elem_data_addr);
+        let inflated =
+            inflate_elem_data(data.reader()?, &mut reservation, false, data_addr)
+                .map_err(|e| e.in_elem(&name))?;
+        if let Some(inflated) = inflated {
+            data = inflated;
+            raw_reservation.clear();
+        }
+        save_elem_data(data, &elem_path, sink, budget).map_err(|e| e.in_elem(&name))?;
+    }
+
+    Ok(())
+}
+
+/// Reads the data block at the current position into memory if
+/// `reservation` can grow to hold it, or into a temporary file otherwise.
+fn read_elem_data(
+    rdr: &mut BufReader<fs::File>,
+    reservation: &mut Reservation,
+    cancellation: &CancellationToken,
+) -> Result<ElemData> {
+    let pos = rdr.stream_position()?;
+    let header = BlockHeader::from_raw_parts(rdr)?;
+    if !header.is_correct() {
+        error!("the file is not in the correct format");
+        return Err(bad_block_header(pos));
+    }
+
+    let data_size = u64::from(header.get_data_size()?);
+    if reservation.grow(data_size, false) {
+        let file_len = rdr.get_ref().metadata()?.len();
+        Ok(ElemData::Memory(read_block_to_vec(
+            rdr,
+            &header,
+            file_len,
+            cancellation,
+        )?))
+    } else {
+        debug!("spill nested element to a temporary file");
+        let mut file = tempfile::tempfile()?;
+        let len = single::read_block_data_to_cancellable(
+            rdr,
+            &header,
+            &mut file,
+            cancellation,
+        )?;
+        Ok(ElemData::Spilled { file, len })
+    }
+}
+
+/// Reads a data block into a buffer allocated at once for the size declared
+/// in `header`, which is not trusted beyond `file_len`, so that the buffer
+/// does not grow past the bytes reserved for it.
+fn read_block_to_vec<R: Read + Seek>(
+    rdr: &mut R,
+    header: &BlockHeader,
+    file_len: u64,
+    cancellation: &CancellationToken,
+) -> Result<Vec<u8>> {
+    let data_size = u64::from(header.get_data_size()?);
+    let mut data = Vec::with_capacity(cmp::min(data_size, file_len) as usize);
+    single::read_block_data_to_cancellable(rdr, header, &mut data, cancellation)?;
+
+    Ok(data)
 }
 
 pub fn parse_to_folder(
     file_name: &str,
     dir_name: &str,
-    bool_inflate: bool,
+    _bool_inflate: bool,
 ) -> Result<bool> {
-    let p_dir = path::Path::new(dir_name);
-    if !p_dir.exists() {
+    let mut sink = DirSink::new(false);
+    create_root_dir(dir_name)?;
+
+    parse_into_sink(
+        file_name,
+        path::Path::new(dir_name),
+        &UnpackOptions::new(),
+        &mut sink,
+    )?;
+
+    Ok(true)
+}
+
+/// Multi-threaded variant of `single::unpack_to_directory_with_options`.
+///
+/// With `UnpackOptions::with_max_memory` the stages of the pipeline hold at
+/// most that many bytes of element data at once, elements which do not fit
+/// are passed through temporary files.
+pub fn parse_to_folder_with_options(
+    file_name: &str,
+    dir_name: &str,
+    options: &UnpackOptions,
+) -> Result<UnpackReport> {
+    let mut sink = DirSink::new(options.get_incremental())
+        .with_options(options.sink_options(path::Path::new(dir_name)));
+
+    create_root_dir(dir_name)?;
+    parse_into_sink(file_name, path::Path::new(dir_name), options, &mut sink)?;
+    if options.get_remove_stale() {
+        sink.remove_stale(path::Path::new(dir_name))?;
+    } else {
+        sink.save_manifest(path::Path::new(dir_name))?;
+    }
+
+    info!(
+        "parsing file {} completed ({})",
+        file_name,
+        sink.get_report()
+    );
+    Ok(*sink.get_report())
+}
+
+/// Multi-threaded unpacking of the container into `sink`, like an archive.
+/// The paths of the elements are relative to the root of the sink, and the
+/// filter, progress observer and cancellation token of `options` replace
+/// those of the sink.
+pub fn parse_to_sink(
+    file_name: &str,
+    sink: &mut dyn OutputSink,
+    options: &UnpackOptions,
+) -> Result<UnpackReport> {
+    *sink.get_options_mut() = options.sink_options(path::Path::new(""));
+    parse_into_sink(file_name, path::Path::new(""), options, sink)?;
+    sink.finish()?;
+
+    info!(
+        "parsing file {} completed ({})",
+        file_name,
+        sink.get_report()
+    );
+    Ok(*sink.get_report())
+}
+
+fn create_root_dir(dir_name: &str) -> Result<()> {
+    if !path::Path::new(dir_name).exists() {
         fs::create_dir(dir_name)?;
     };
 
+    Ok(())
+}
+
+fn parse_into_sink(
+    file_name: &str,
+    p_dir: &path::Path,
+    options: &UnpackOptions,
+    sink: &mut dyn OutputSink,
+) -> Result<()> {
     info!("the beginning of the file parsing {}", file_name);
+    let budget = MemoryBudget::new(options.get_max_memory());
     let (_, elems_addrs) = read_content(file_name)?;
-    let (v8_elems, h1) =
-        start_file_reader_thread(path::PathBuf::from(file_name), elems_addrs);
-    let (inf_data, h2) = start_inflate_thread(v8_elems);
-
-    let result = start_file_parse(inf_data, p_dir, bool_inflate);
-
-    let r1 = h1.join().unwrap();
-    let r2 = h2.join().unwrap();
+    let progress = options.get_progress();
+    progress.begin(
+        Some(elems_addrs.len()),
+        fs::metadata(file_name).ok().map(|meta| meta.len()),
+    );
+    let (v8_elems, h1) = start_file_reader_thread(
+        path::PathBuf::from(file_name),
+        elems_addrs,
+        options.get_filter().clone(),
+        options.shared_progress(),
+        options.get_cancellation().clone(),
+        Arc::clone(&budget),
+    );
+    let (inf_data, h2) = start_inflate_thread(
+        v8_elems,
+        Arc::clone(&budget),
+        options.get_cancellation().clone(),
+    );
+
+    let result = start_file_parse(inf_data, p_dir, sink, progress, &budget);
+
+    let r1 = join_stage(h1);
+    let r2 = join_stage(h2);
 
     r1?;
     r2?;
+    progress.end();
 
     result
 }
 
+/// Reads the elements selected by `filter` in a separate thread, the data
+/// of the skipped elements is not read at all. The bytes read are reported
+/// to `progress`. The thread stops once `cancellation` is cancelled.
 fn start_file_reader_thread(
     file_name: path::PathBuf,
     elems_addrs: Vec<ElemAddr>,
-) -> (Receiver<V8Elem>, JoinHandle<Result<()>>) {
+    filter: ElemFilter,
+    progress: Option<Arc<dyn Progress>>,
+    cancellation: CancellationToken,
+    budget: Arc<MemoryBudget>,
+) -> (Receiver<PipelineElem>, JoinHandle<Result<()>>) {
     let (sender, receiver) = sync_channel(128);
 
     let handle = spawn(move || {
         let file = fs::File::open(file_name)?;
+        let file_len = file.metadata()?.len();
         let mut buf_reader = BufReader::new(file);
 
         for cur_elem in elems_addrs.iter() {
             debug!("{:?}", cur_elem);
+            cancellation.check()?;
             if cur_elem.fffffff != V8_MAGIC_NUMBER {
                 break;
             }
@@ -121,21 +432,56 @@ fn start_file_reader_thread(
             let elem_block_header = BlockHeader::from_raw_parts(&mut buf_reader)?;
             if !elem_block_header.is_correct() {
                 error!("the file is not in the correct format");
-                return Err(error::V8Error::NotV8File { offset: pos });
+                return Err(bad_block_header(pos));
             }
 
-            let elem_block_data =
-                single::read_block_data(&mut buf_reader, &elem_block_header)?;
-            let mut v8_elem = V8Elem::new().with_header(elem_block_data);
+            let mut v8_elem = PipelineElem {
+                header_addr: pos,
+                header: single::read_block_data(&mut buf_reader, &elem_block_header)?,
+                data_addr: u64::from(cur_elem.elem_data_addr),
+                data: None,
+                reservation: None,
+            };
+            if !filter.is_empty() {
+                let name = v8_elem.get_name()?;
+                if !filter.visits(&name) {
+                    debug!("skip filtered element {}", name);
+                    if let Some(ref progress) = progress {
+                        progress.elem_started(&name);
+                        progress.elem_finished(&name);
+                    }
+                    continue;
+                }
+            }
 
             if cur_elem.elem_data_addr != V8_MAGIC_NUMBER {
                 buf_reader.seek(SeekFrom::Start(u64::from(cur_elem.elem_data_addr)))?;
                 let block_header_data = BlockHeader::from_raw_parts(&mut buf_reader)?;
+                let data_size = u64::from(block_header_data.get_data_size()?);
+                if let Some(ref progress) = progress {
+                    progress.bytes_read(data_size);
+                }
 
-                v8_elem = v8_elem.with_data(single::read_block_data(
-                    &mut buf_reader,
-                    &block_header_data,
-                )?);
+                if data_size > budget.spill_threshold() {
+                    debug!("spill element of {} bytes to a temporary file", data_size);
+                    let mut file = tempfile::tempfile()?;
+                    let len = single::read_block_data_to_cancellable(
+                        &mut buf_reader,
+                        &block_header_data,
+                        &mut file,
+                        &cancellation,
+                    )?;
+                    v8_elem.data = Some(ElemData::Spilled { file, len });
+                } else {
+                    v8_elem.reservation =
+                        Some(MemoryBudget::acquire(&budget, data_size));
+                    v8_elem.data = Some(ElemData::Memory(read_block_to_vec(
+                        &mut buf_reader,
+                        &block_header_data,
+                        file_len,
+                        &cancellation,
+                    )?));
+                }
             }
 
             if sender.send(v8_elem).is_err() {
@@ -148,19 +494,22 @@ fn start_file_reader_thread(
     (receiver, handle)
 }
 
-fn start_file_write(v8_elems: Receiver<V8Elem>, p_dir: &path::Path) -> Result<bool> {
+fn start_file_write(
+    v8_elems: Receiver<PipelineElem>,
+    p_dir: &path::Path,
+) -> Result<bool> {
     for v8_elem in v8_elems {
         let elem_name = v8_elem.get_name()?;
 
         let file_elem_header = format!("{0}.{1}", elem_name, "header");
         info!("write to file {}", file_elem_header);
-        fs::File::create(p_dir.join(&file_elem_header))?
-            .write_all(&v8_elem.get_header())?;
+        fs::File::create(p_dir.join(&file_elem_header))?.write_all(&v8_elem.header)?;
 
         let file_elem_data = format!("{0}.{1}", elem_name, "data");
         info!("write to file {}", file_elem_header);
-        if let Some(block_data) = v8_elem.get_data() {
-            fs::File::create(p_dir.join(&file_elem_data))?.write_all(block_data)?;
+        if let Some(mut block_data) = v8_elem.data {
+            let mut file_out = fs::File::create(p_dir.join(&file_elem_data))?;
+            io::copy(&mut block_data.reader()?, &mut file_out)?;
         }
     }
 
@@ -176,26 +525,41 @@ pub fn unpack_pipeline(file_name: &str, dir_name: &str) -> Result<bool> {
     let (file_header, elems_addrs) = read_content(file_name)?;
     fs::File::create(p_dir.join("FileHeader"))?.write_all(&file_header.into_bytes()?)?;
 
-    let (v8_elems, h1) =
-        start_file_reader_thread(path::PathBuf::from(file_name), elems_addrs);
+    let (v8_elems, h1) = start_file_reader_thread(
+        path::PathBuf::from(file_name),
+        elems_addrs,
+        ElemFilter::new(),
+        None,
+        CancellationToken::new(),
+        MemoryBudget::new(None),
+    );
 
     let result = start_file_write(v8_elems, p_dir);
 
-    let r1 = h1.join().unwrap();
-
-    r1?;
+    join_stage(h1)?;
 
     result
 }
 
+/// Waits for a stage of the pipeline, turning its panic into an error.
+fn join_stage(handle: JoinHandle<Result<()>>) -> Result<()> {
+    match handle.join() {
+        Ok(result) => result,
+        Err(_) => {
+            error!("a stage of the pipeline panicked");
+            Err(error::V8Error::IoError(io::Error::other(
+                "A stage of the pipeline panicked",
+            )))
+        }
+    }
+}
+
 fn read_content(file_name: &str) -> Result<(FileHeader, Vec<ElemAddr>)> {
     let file = fs::File::open(file_name)?;
     let mut buf_reader = BufReader::new(file);
     if !buf_reader.is_v8file() {
         error!("the file is not in the correct format");
-        return Err(error::V8Error::NotV8File {
-            offset: buf_reader.seek(SeekFrom::Current(0))?,
-        });
+        return Err(error::V8Error::NotV8File { offset: 0 });
     }
 
     let file_header = buf_reader.get_file_header()?;
@@ -0,0 +1,133 @@
+use crate::cancel::CancellationToken;
+use crate::filter::ElemFilter;
+use crate::progress::{Progress, NO_PROGRESS};
+use crate::sink::SinkOptions;
+
+use std::path::Path;
+use std::sync::Arc;
+
+/// Options of unpacking a container to a directory.
+#[derive(Debug, Clone)]
+pub struct UnpackOptions {
+    inflate: bool,
+    incremental: bool,
+    remove_stale: bool,
+    max_memory: Option<u64>,
+    filter: ElemFilter,
+    progress: Option<Arc<dyn Progress>>,
+    cancellation: CancellationToken,
+}
+
+impl Default for UnpackOptions {
+    fn default() -> UnpackOptions {
+        UnpackOptions {
+            inflate: true,
+            incremental: false,
+            remove_stale: false,
+            max_memory: None,
+            filter: ElemFilter::new(),
+            progress: None,
+            cancellation: CancellationToken::new(),
+        }
+    }
+}
+
+impl UnpackOptions {
+    /// Creates a new instance of `UnpackOptions`.
+    pub fn new() -> UnpackOptions {
+        UnpackOptions::default()
+    }
+
+    pub fn with_inflate(mut self, value: bool) -> Self {
+        self.inflate = value;
+
+        self
+    }
+
+    pub fn get_inflate(&self) -> bool {
+        self.inflate
+    }
+
+    /// Rewrites only the files which content differs from the one on disk.
+    pub fn with_incremental(mut self, value: bool) -> Self {
+        self.incremental = value;
+
+        self
+    }
+
+    pub fn get_incremental(&self) -> bool {
+        self.incremental
+    }
+
+    /// Deletes files of the output directory which an earlier unpack wrote
+    /// and which are no longer in the container.
+    pub fn with_remove_stale(mut self, value: bool) -> Self {
+        self.remove_stale = value;
+
+        self
+    }
+
+    pub fn get_remove_stale(&self) -> bool {
+        self.remove_stale
+    }
+
+    /// Limits the bytes of element data held in memory by the
+    /// multi-threaded pipeline; larger elements go through temporary files.
+    pub fn with_max_memory(mut self, value: Option<u64>) -> Self {
+        self.max_memory = value;
+
+        self
+    }
+
+    pub fn get_max_memory(&self) -> Option<u64> {
+        self.max_memory
+    }
+
+    /// Unpacks only the elements selected by `filter`. Skipped elements are
+    /// not inflated, and neither are nested containers which can not hold
+    /// selected elements.
+    pub fn with_filter(mut self, value: ElemFilter) -> Self {
+        self.filter = value;
+
+        self
+    }
+
+    pub fn get_filter(&self) -> &ElemFilter {
+        &self.filter
+    }
+
+    /// Reports the progress of unpacking to `progress`.
+    pub fn with_progress(mut self, value: Arc<dyn Progress>) -> Self {
+        self.progress = Some(value);
+
+        self
+    }
+
+    pub fn get_progress(&self) -> &dyn Progress {
+        self.progress.as_deref().unwrap_or(&NO_PROGRESS)
+    }
+
+    pub(crate) fn shared_progress(&self) -> Option<Arc<dyn Progress>> {
+        self.progress.clone()
+    }
+
+    /// Options of a sink writing the elements under `root`.
+    pub(crate) fn sink_options(&self, root: &Path) -> SinkOptions {
+        SinkOptions::new()
+            .with_filter(root, self.filter.clone())
+            .with_progress(self.progress.clone())
+            .with_cancellation(self.cancellation.clone())
+    }
+
+    /// Stops unpacking with `V8Error::Cancelled` once `token` is cancelled.
+    /// Files already written are kept, no file is left half-written.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+
+        self
+    }
+
+    pub fn get_cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+}
@@ -1,16 +1,29 @@
+use crate::cancel::CancellationToken;
 use crate::container::*;
 use crate::error;
+use crate::progress::{Progress, NO_PROGRESS};
+use crate::sink::*;
+use crate::tree::{MemSink, MemTree};
+use crate::view::ContainerView;
 
+use super::options::UnpackOptions;
+
+use inflate::DeflateDecoderBuf;
 use log::*;
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::io::prelude::*;
-use std::io::{BufReader, Cursor, Error as ioError, ErrorKind as ioErrorKind, SeekFrom};
+use std::io::{self, BufReader, Cursor, SeekFrom};
 use std::{cmp, fs, path, str};
 
+/// The largest buffer allocated for a block before its data is read.
+const MAX_PREALLOCATED_BLOCK_SIZE: u32 = 1 << 20;
+
 /// Makes the unpacking of the container to a directory on disk.
 pub fn unpack_to_directory_no_load(
     file_name: &str,
     dir_name: &str,
-    bool_inflate: bool,
+    _bool_inflate: bool,
     _unpack_when_need: bool,
 ) -> Result<bool> {
     info!("the beginning of the file parsing {}", file_name);
@@ -21,17 +34,149 @@ pub fn unpack_to_directory_no_load(
         return Ok(false);
     }
 
-    let first_block_header = buf_reader.get_first_block_header()?;
+    let mut sink = DirSink::new(false);
+    unpack_elems(
+        &mut buf_reader,
+        path::Path::new(dir_name),
+        &mut sink,
+        &NO_PROGRESS,
+    )?;
 
-    let p_dir = path::Path::new(dir_name);
-    if !p_dir.exists() {
-        fs::create_dir(dir_name)?;
+    info!("parsing file {} completed successfully", file_name);
+    Ok(true)
+}
+
+/// Makes the unpacking of the container to a directory on disk according
+/// to `options` and reports which files were touched.
+pub fn unpack_to_directory_with_options(
+    file_name: &str,
+    dir_name: &str,
+    options: &UnpackOptions,
+) -> Result<UnpackReport> {
+    info!("the beginning of the file parsing {}", file_name);
+    let file = fs::File::open(file_name)?;
+    let mut buf_reader = BufReader::new(file);
+
+    if !buf_reader.is_v8file() {
+        error!("the file is not in the correct format");
+        return Err(error::V8Error::NotV8File { offset: 0 });
     }
 
-    let elems_addrs = read_elems_addrs(&mut buf_reader, &first_block_header)?;
+    let mut sink = DirSink::new(options.get_incremental())
+        .with_options(options.sink_options(path::Path::new(dir_name)));
+    let progress = options.get_progress();
+    unpack_elems(
+        &mut buf_reader,
+        path::Path::new(dir_name),
+        &mut sink,
+        progress,
+    )?;
+    if options.get_remove_stale() {
+        sink.remove_stale(path::Path::new(dir_name))?;
+    } else {
+        sink.save_manifest(path::Path::new(dir_name))?;
+    }
+
+    progress.end();
+
+    info!(
+        "parsing file {} completed successfully ({})",
+        file_name,
+        sink.get_report()
+    );
+    Ok(*sink.get_report())
+}
+
+/// Unpacks the container into `sink`, like an archive, one element at a
+/// time. The paths of the elements are relative to the root of the sink,
+/// and the filter, progress observer and cancellation token of `options`
+/// replace those of the sink.
+pub fn unpack_to_sink(
+    file_name: &str,
+    sink: &mut dyn OutputSink,
+    options: &UnpackOptions,
+) -> Result<UnpackReport> {
+    info!("the beginning of the file parsing {}", file_name);
+    let file = fs::File::open(file_name)?;
+    let mut buf_reader = BufReader::new(file);
+
+    if !buf_reader.is_v8file() {
+        error!("the file is not in the correct format");
+        return Err(error::V8Error::NotV8File { offset: 0 });
+    }
+
+    *sink.get_options_mut() = options.sink_options(path::Path::new(""));
+    let progress = options.get_progress();
+    unpack_elems(&mut buf_reader, path::Path::new(""), sink, progress)?;
+    sink.finish()?;
+    progress.end();
+
+    info!(
+        "parsing file {} completed successfully ({})",
+        file_name,
+        sink.get_report()
+    );
+    Ok(*sink.get_report())
+}
+
+/// Unpacks the container held in `data` into a `MemTree`, without touching
+/// the disk.
+pub fn unpack_to_tree(data: &[u8], options: &UnpackOptions) -> Result<MemTree> {
+    let view = ContainerView::new(data)?;
+    let mut sink = MemSink::new();
+    *sink.get_options_mut() = options.sink_options(path::Path::new(""));
+    let progress = options.get_progress();
+    progress.begin(Some(view.len()), None);
+    save_view_to_sink(&view, path::Path::new(""), &mut sink)?;
+    progress.end();
+
+    Ok(sink.into_tree())
+}
+
+/// Unpacks the container held in `data` into the directory `dir_name`, the
+/// same way as `unpack_to_directory_with_options` does.
+pub fn unpack_bytes_to_directory(
+    data: &[u8],
+    dir_name: &str,
+    options: &UnpackOptions,
+) -> Result<UnpackReport> {
+    let view = ContainerView::new(data)?;
+    let mut sink = DirSink::new(options.get_incremental())
+        .with_options(options.sink_options(path::Path::new(dir_name)));
+    let progress = options.get_progress();
+    progress.begin(Some(view.len()), None);
+    save_view_to_sink(&view, path::Path::new(dir_name), &mut sink)?;
+    if options.get_remove_stale() {
+        sink.remove_stale(path::Path::new(dir_name))?;
+    } else {
+        sink.save_manifest(path::Path::new(dir_name))?;
+    }
+    progress.end();
+
+    Ok(*sink.get_report())
+}
+
+/// Unpacks the elements of the container read from `buf_reader` into the
+/// directory `p_dir`, one element at a time, reporting them to `progress`.
+pub(crate) fn unpack_elems(
+    buf_reader: &mut BufReader<fs::File>,
+    p_dir: &path::Path,
+    sink: &mut dyn OutputSink,
+    progress: &dyn Progress,
+) -> Result<()> {
+    let first_block_header = buf_reader.get_first_block_header()?;
+
+    sink.create_dir(p_dir)?;
+
+    let elems_addrs = read_elems_addrs(buf_reader, &first_block_header)?;
+    progress.begin(
+        Some(elems_addrs.len()),
+        buf_reader.get_ref().metadata().ok().map(|meta| meta.len()),
+    );
 
     for cur_elem in elems_addrs.iter() {
         debug!("{:?}", cur_elem);
+        sink.get_cancellation().check()?;
         if cur_elem.fffffff != V8_MAGIC_NUMBER {
             break;
         }
@@ -39,26 +184,35 @@ pub fn unpack_to_directory_no_load(
         let pos =
             buf_reader.seek(SeekFrom::Start(u64::from(cur_elem.elem_header_addr)))?;
 
-        let elem_block_header = BlockHeader::from_raw_parts(&mut buf_reader)?;
+        let elem_block_header = BlockHeader::from_raw_parts(buf_reader)?;
 
         if !elem_block_header.is_correct() {
             error!("the file is not in the correct format");
-            return Err(error::V8Error::NotV8File { offset: pos });
+            return Err(bad_block_header(pos));
         }
 
-        let elem_block_data = read_block_data(&mut buf_reader, &elem_block_header)?;
-        let elem_name = V8Elem::new().with_header(elem_block_data).get_name()?;
+        let elem_block_data = read_block_data(buf_reader, &elem_block_header)?;
+        let elem_name = elem_name(&elem_block_data, pos)?;
 
         let elem_path = p_dir.join(&elem_name);
+        progress.elem_started(&elem_name);
+        if !sink.visits(&elem_path) {
+            debug!("skip filtered element {}", elem_name);
+            progress.elem_finished(&elem_name);
+            continue;
+        }
 
         if cur_elem.elem_data_addr != V8_MAGIC_NUMBER {
-            buf_reader.seek(SeekFrom::Start(u64::from(cur_elem.elem_data_addr)))?;
-            let _result = process_data(&mut buf_reader, bool_inflate, &elem_path)?;
+            let start =
+                buf_reader.seek(SeekFrom::Start(u64::from(cur_elem.elem_data_addr)))?;
+            process_data_to_sink(buf_reader, true, &elem_path, sink)
+                .map_err(|e| e.in_elem(&elem_name))?;
+            progress.bytes_read(buf_reader.stream_position()?.saturating_sub(start));
         }
+        progress.elem_finished(&elem_name);
     }
 
-    info!("parsing file {} completed successfully", file_name);
-    Ok(true)
+    Ok(())
 }
 
 /// Parses the container into its component parts so that the elements
@@ -97,19 +251,19 @@ pub fn unpack_to_folder(file_name: &str, dir_name: &str) -> Result<bool> {
 
         if !elem_block_header.is_correct() {
             error!("the file is not in the correct format");
-            return Err(error::V8Error::NotV8File { offset: pos });
+            return Err(bad_block_header(pos));
         }
 
         let elem_block_data = read_block_data(&mut buf_reader, &elem_block_header)?;
+        let elem_name = elem_name(&elem_block_data, pos)?;
         let v8_elem = V8Elem::new().with_header(elem_block_data);
-        let elem_name = v8_elem.get_name()?;
 
         let mut file_elem_header = String::new();
         file_elem_header.push_str(&elem_name);
         file_elem_header.push_str(".header");
 
         fs::File::create(p_dir.join(&file_elem_header))?
-            .write_all(&v8_elem.get_header())?;
+            .write_all(v8_elem.get_header())?;
 
         if cur_elem.elem_data_addr != V8_MAGIC_NUMBER {
             buf_reader.seek(SeekFrom::Start(u64::from(cur_elem.elem_data_addr)))?;
@@ -138,6 +292,9 @@ where
     R: Read + Seek,
 {
     info!("read the file table of contents");
+    let toc_offset = src
+        .stream_position()?
+        .saturating_sub(u64::from(BlockHeader::SIZE));
     let block_data = read_block_data(src, block_header)?;
     let data_size = block_data.len() as u64;
     let mut rdr = Cursor::new(block_data);
@@ -145,6 +302,14 @@ where
     let mut elems_addrs: Vec<ElemAddr> = vec![];
 
     while rdr.position() < data_size {
+        if data_size - rdr.position() < u64::from(ElemAddr::SIZE) {
+            return Err(error::V8Error::BadTocEntry {
+                offset: toc_offset,
+                path: String::new(),
+                container: None,
+                index: elems_addrs.len(),
+            });
+        }
         elems_addrs.push(ElemAddr::from_raw_parts(&mut rdr)?);
     }
 
@@ -153,74 +318,178 @@ where
 }
 
 pub fn read_block_data<R>(src: &mut R, block_header: &BlockHeader) -> Result<Vec<u8>>
+where
+    R: Read + Seek,
+{
+    read_block_data_cancellable(src, block_header, &CancellationToken::new())
+}
+
+/// Reads the data of a block, checking `cancellation` between pages.
+pub(crate) fn read_block_data_cancellable<R>(
+    src: &mut R,
+    block_header: &BlockHeader,
+    cancellation: &CancellationToken,
+) -> Result<Vec<u8>>
 where
     R: Read + Seek,
 {
     let data_size = block_header.get_data_size()?;
     info!("start reading a block of data from a file");
-    let mut result: Vec<u8> = Vec::with_capacity(data_size as usize);
+    // The declared size is not trusted until the pages are actually read.
+    let capacity = cmp::min(data_size, MAX_PREALLOCATED_BLOCK_SIZE);
+    let mut result: Vec<u8> = Vec::with_capacity(capacity as usize);
+
+    read_block_data_to_cancellable(src, block_header, &mut result, cancellation)?;
+
+    info!("{} bytes read", result.len());
+    Ok(result)
+}
 
+/// Copies the data of a block, page by page, to `out` and returns the
+/// number of bytes copied.
+pub fn read_block_data_to<R, W>(
+    src: &mut R,
+    block_header: &BlockHeader,
+    out: &mut W,
+) -> Result<u64>
+where
+    R: Read + Seek,
+    W: Write,
+{
+    read_block_data_to_cancellable(src, block_header, out, &CancellationToken::new())
+}
+
+/// Copies the data of a block to `out`, checking `cancellation` between
+/// pages.
+pub(crate) fn read_block_data_to_cancellable<R, W>(
+    src: &mut R,
+    block_header: &BlockHeader,
+    out: &mut W,
+    cancellation: &CancellationToken,
+) -> Result<u64>
+where
+    R: Read + Seek,
+    W: Write,
+{
+    let data_size = block_header.get_data_size()?;
     let mut read_in_bytes = 0;
+    let data_addr = src.stream_position()?;
+    let file_len = src.seek(SeekFrom::End(0))?;
+    src.seek(SeekFrom::Start(data_addr))?;
+    let mut page_addr = data_addr.saturating_sub(u64::from(BlockHeader::SIZE));
+    let mut visited = HashSet::new();
 
     let mut local_block_header = block_header.clone();
     while read_in_bytes < data_size {
+        cancellation.check()?;
+        visited.insert(page_addr);
         let page_size = local_block_header.get_page_size()?;
         let next_page_addr = local_block_header.get_next_page_addr()?;
 
         let bytes_to_read = cmp::min(page_size, data_size - read_in_bytes);
-        let mut buf: Vec<u8> = Vec::with_capacity(bytes_to_read as usize);
-        let read_b = src.take(u64::from(bytes_to_read)).read_to_end(&mut buf)?;
+        let read_b = io::copy(&mut src.take(u64::from(bytes_to_read)), out)?;
 
         read_in_bytes += bytes_to_read;
-        if read_b < bytes_to_read as usize {
-            return Err(error::V8Error::IoError(ioError::new(
-                ioErrorKind::InvalidData,
-                "Readied too few bytes",
-            )));
+        if read_b < u64::from(bytes_to_read) {
+            return Err(truncated_block(page_addr));
+        }
+        if read_in_bytes >= data_size {
+            break;
         }
 
-        result.extend(buf.iter());
+        // The chain ends before the data does, loops or holds more data
+        // than the file.
+        if next_page_addr == V8_MAGIC_NUMBER {
+            return Err(truncated_block(page_addr));
+        }
+        if visited.contains(&u64::from(next_page_addr))
+            || u64::from(read_in_bytes) > file_len
+        {
+            return Err(bad_block_header(page_addr));
+        }
 
-        if next_page_addr != V8_MAGIC_NUMBER {
-            src.seek(SeekFrom::Start(u64::from(next_page_addr)))?;
-            local_block_header = BlockHeader::from_raw_parts(src)?;
-        } else {
-            break;
+        page_addr = src.seek(SeekFrom::Start(u64::from(next_page_addr)))?;
+        local_block_header = BlockHeader::from_raw_parts(src)?;
+        if !local_block_header.is_correct() {
+            return Err(bad_block_header(page_addr));
         }
     }
 
-    info!("{} bytes read", result.len());
-    Ok(result)
+    Ok(u64::from(read_in_bytes))
 }
 
 pub fn process_data(
     src: &mut BufReader<fs::File>,
     _need_unpack: bool,
-    elem_path: &path::PathBuf,
+    elem_path: &path::Path,
 ) -> Result<bool> {
+    process_data_to_sink(src, _need_unpack, elem_path, &mut DirSink::new(false))
+}
+
+/// Reads the data block of an element and writes it through `sink`.
+pub fn process_data_to_sink(
+    src: &mut BufReader<fs::File>,
+    _need_unpack: bool,
+    elem_path: &path::Path,
+    sink: &mut dyn OutputSink,
+) -> Result<bool> {
+    let offset = src.stream_position()?;
     let header = BlockHeader::from_raw_parts(src)?;
     if !header.is_correct() {
         error!("the file is not in the correct format");
-        return Err(error::V8Error::NotV8File {
-            offset: src.seek(SeekFrom::Current(0))?,
-        });
+        return Err(bad_block_header(offset));
     }
 
-    let block_data = read_block_data(src, &header)?;
-    let out_data = try_inflate_bytes(block_data);
-
-    let mut rdr = Cursor::new(&out_data);
+    let block_data = read_block_data_cancellable(src, &header, sink.get_cancellation())?;
+    let out_data = inflate_elem(Cow::Owned(block_data), offset)?;
 
-    if rdr.is_v8file() {
-        load_file(&mut rdr, _need_unpack)?.save_file_to_folder(elem_path)?;
+    if ContainerView::is_v8file(&out_data) {
+        ContainerView::new(&out_data)
+            .and_then(|view| save_view_to_sink(&view, elem_path, sink))
+            .map_err(error::V8Error::nested)?;
     } else {
-        fs::File::create(elem_path.as_path())?.write_all(&out_data)?;
+        sink.write_file(elem_path, &out_data)?;
     }
 
     Ok(true)
 }
 
-pub fn load_file<R>(src: &mut R, bool_inflate: bool) -> Result<V8File>
+/// Stores the elements of a nested container in files on disk, inflating
+/// and writing one element at a time.
+pub fn save_view_to_sink(
+    view: &ContainerView,
+    elem_path: &path::Path,
+    sink: &mut dyn OutputSink,
+) -> Result<()> {
+    sink.create_dir(elem_path)?;
+
+    for elem in view.iter() {
+        sink.get_cancellation().check()?;
+        let elem = elem?;
+        let name_elem = elem.get_name()?;
+        let out_path = elem_path.join(&name_elem);
+        if !sink.visits(&out_path) {
+            debug!("skip filtered element {}", name_elem);
+            continue;
+        }
+        info!("parse element {}", name_elem);
+
+        let raw_data = Cow::Borrowed(elem.get_data().unwrap_or(&[]));
+        let out_data = inflate_elem(raw_data, u64::from(elem.get_data_addr()))
+            .map_err(|e| e.in_elem(&name_elem))?;
+        if ContainerView::is_v8file(&out_data) {
+            ContainerView::new(&out_data)
+                .and_then(|view| save_view_to_sink(&view, &out_path, sink))
+                .map_err(|e| e.nested().in_elem(&name_elem))?;
+        } else {
+            sink.write_file(&out_path, &out_data)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn load_file<R>(src: &mut R, _bool_inflate: bool) -> Result<V8File>
 where
     R: Read + Seek + V8Container,
 {
@@ -243,27 +512,32 @@ where
 
         if !elem_block_header.is_correct() {
             error!("the file is not in the correct format");
-            return Err(error::V8Error::NotV8File { offset: pos });
+            return Err(bad_block_header(pos));
         }
 
         let elem_block_header_data = read_block_data(src, &elem_block_header)?;
+        let elem_name = elem_name(&elem_block_header_data, pos)?;
 
         let elem_block_data: Vec<u8> = if cur_elem.elem_data_addr != V8_MAGIC_NUMBER {
             src.seek(SeekFrom::Start(u64::from(cur_elem.elem_data_addr)))?;
-            let block_header_data = BlockHeader::from_raw_parts(src)?;
-
-            read_block_data(src, &block_header_data)?
+            BlockHeader::from_raw_parts(src)
+                .and_then(|block_header_data| read_block_data(src, &block_header_data))
+                .map_err(|e| e.in_elem(&elem_name))?
         } else {
             vec![]
         };
 
-        let out_data = try_inflate_bytes(elem_block_data);
+        let data_addr = u64::from(cur_elem.elem_data_addr);
+        let out_data = inflate_elem(Cow::Owned(elem_block_data), data_addr)
+            .map_err(|e| e.in_elem(&elem_name))?
+            .into_owned();
 
         let mut rdr = Cursor::new(out_data);
         let is_v8file = rdr.is_v8file();
 
         let unpacked_data = if is_v8file {
-            load_file(&mut rdr, bool_inflate)?
+            load_file(&mut rdr, _bool_inflate)
+                .map_err(|e| e.nested().in_elem(&elem_name))?
         } else {
             V8File::new()
         };
@@ -285,6 +559,29 @@ where
         .with_elems(elems))
 }
 
+/// Inflates the data of an element if it is deflated, returning it as is
+/// otherwise. Data which inflates into the beginning of a nested container
+/// and then breaks off is damaged and fails with `V8Error::InflateFailed` at
+/// the `offset` of its data block.
+pub(crate) fn inflate_elem(input: Cow<'_, [u8]>, offset: u64) -> Result<Cow<'_, [u8]>> {
+    let mut inflated = vec![];
+    match DeflateDecoderBuf::new(input.as_ref()).read_to_end(&mut inflated) {
+        Ok(_) => Ok(Cow::Owned(inflated)),
+        Err(e) if ContainerView::is_v8file(&inflated) => {
+            Err(inflate_failed(offset, e.to_string()))
+        }
+        Err(_) => Ok(input),
+    }
+}
+
+/// Inflates `input` if it is deflated, borrowing it as is otherwise.
+pub fn try_inflate_slice(input: &[u8]) -> Cow<'_, [u8]> {
+    match inflate::inflate_bytes(input) {
+        Ok(inf_bytes) => Cow::Owned(inf_bytes),
+        Err(_) => Cow::Borrowed(input),
+    }
+}
+
 pub fn try_inflate_bytes(input: Vec<u8>) -> Vec<u8> {
     match inflate::inflate_bytes(&input) {
         Ok(inf_bytes) => inf_bytes,
@@ -0,0 +1,60 @@
+//! Containers read from streams which can not be opened by path, like the
+//! standard input.
+
+use crate::container::Result;
+use crate::sink::UnpackReport;
+
+use super::multi::parse_to_folder_with_options;
+use super::options::UnpackOptions;
+
+use log::*;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use tempfile::NamedTempFile;
+
+/// Copy of a stream in a temporary file, so that the functions taking a
+/// file name can seek in it. The file is removed when dropped.
+#[derive(Debug)]
+pub struct SpooledInput {
+    file: NamedTempFile,
+    size: u64,
+}
+
+impl SpooledInput {
+    /// Copies `src` to the end into a temporary file.
+    pub fn from_reader<R: Read>(src: &mut R) -> Result<SpooledInput> {
+        let mut file = NamedTempFile::new()?;
+        let size = io::copy(src, &mut file)?;
+        file.flush()?;
+        debug!("spooled {} bytes to {:?}", size, file.path());
+
+        Ok(SpooledInput { file, size })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+
+    /// Path of the temporary file as expected by the functions taking a file
+    /// name.
+    pub fn file_name(&self) -> String {
+        self.path().to_string_lossy().into_owned()
+    }
+
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// Unpacks the container read from `src` into the directory `dir_name` the
+/// same way as `parse_to_folder_with_options` does. The stream is always
+/// spooled to a temporary file first, so it does not have to seek.
+pub fn unpack_from_reader<R: Read>(
+    src: &mut R,
+    dir_name: &str,
+    options: &UnpackOptions,
+) -> Result<UnpackReport> {
+    let input = SpooledInput::from_reader(src)?;
+
+    parse_to_folder_with_options(&input.file_name(), dir_name, options)
+}
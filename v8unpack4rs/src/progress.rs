@@ -0,0 +1,36 @@
+//! Reporting of the progress of unpacking and building.
+
+use std::fmt;
+
+/// Observer of the progress of unpacking and building containers.
+///
+/// All methods do nothing by default. They may be called from the threads
+/// of the multi-threaded pipeline, so implementations have to be `Sync`.
+pub trait Progress: fmt::Debug + Send + Sync {
+    /// The operation starts with `total_elems` elements of the root
+    /// container and `total_bytes` bytes of input, when they are known.
+    fn begin(&self, _total_elems: Option<usize>, _total_bytes: Option<u64>) {}
+
+    /// Processing of the element of the root container `name` starts.
+    fn elem_started(&self, _name: &str) {}
+
+    /// Processing of the element of the root container `name` is finished.
+    fn elem_finished(&self, _name: &str) {}
+
+    /// `bytes` more bytes of the input are read.
+    fn bytes_read(&self, _bytes: u64) {}
+
+    /// `bytes` more bytes are written to the output.
+    fn bytes_written(&self, _bytes: u64) {}
+
+    /// The operation is finished.
+    fn end(&self) {}
+}
+
+/// Progress observer which ignores everything.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoProgress;
+
+impl Progress for NoProgress {}
+
+pub(crate) static NO_PROGRESS: NoProgress = NoProgress;
@@ -0,0 +1,453 @@
+//! Writing unpacked elements to disk.
+
+use std::collections::HashSet;
+use std::io::prelude::*;
+use std::io::{self, BufReader, ErrorKind as ioErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{cmp, fmt, fs};
+
+use log::*;
+
+use crate::cancel::CancellationToken;
+use crate::container::Result;
+use crate::filter::ElemFilter;
+use crate::progress::Progress;
+
+/// Counts of files touched while unpacking into a directory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UnpackReport {
+    /// Files which did not exist before.
+    pub added: usize,
+    /// Files which existed with a different content.
+    pub changed: usize,
+    /// Files left untouched because their content is the same.
+    pub unchanged: usize,
+    /// Files and directories removed because they are no longer in the
+    /// container.
+    pub removed: usize,
+}
+
+impl fmt::Display for UnpackReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "added: {}, changed: {}, unchanged: {}, removed: {}",
+            self.added, self.changed, self.unchanged, self.removed
+        )
+    }
+}
+
+/// Readable and seekable data, like a temporary file, written to a sink by
+/// `OutputSink::write_file_from`.
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// Filter, progress observer and cancellation token of a sink.
+#[derive(Debug, Clone, Default)]
+pub struct SinkOptions {
+    root: PathBuf,
+    filter: ElemFilter,
+    progress: Option<Arc<dyn Progress>>,
+    cancellation: CancellationToken,
+}
+
+impl SinkOptions {
+    /// Creates a new instance of `SinkOptions` accepting all elements.
+    pub fn new() -> SinkOptions {
+        SinkOptions::default()
+    }
+
+    /// Skips the elements rejected by `filter`, the paths of the elements
+    /// are the paths given to the sink relative to `root`.
+    pub fn with_filter(mut self, root: &Path, filter: ElemFilter) -> Self {
+        self.root = root.to_path_buf();
+        self.filter = filter;
+
+        self
+    }
+
+    pub fn get_filter(&self) -> &ElemFilter {
+        &self.filter
+    }
+
+    /// Reports the bytes written to `progress`.
+    pub fn with_progress(mut self, progress: Option<Arc<dyn Progress>>) -> Self {
+        self.progress = progress;
+
+        self
+    }
+
+    /// Stops writing with `V8Error::Cancelled` once `token` is cancelled.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+
+        self
+    }
+
+    pub fn get_cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Checks whether the element written to `path` has to be read at all,
+    /// see `ElemFilter::visits`.
+    pub fn visits(&self, path: &Path) -> bool {
+        self.filter.is_empty() || self.filter.visits(&self.elem_path(path))
+    }
+
+    /// Checks whether the element written to `path` is selected by the
+    /// filter.
+    pub fn accepts(&self, path: &Path) -> bool {
+        self.filter.is_empty() || self.filter.accepts(&self.elem_path(path))
+    }
+
+    /// Returns the path of the element written to `path`, like `Form/form`.
+    pub fn elem_path(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        let names: Vec<_> = relative
+            .components()
+            .map(|name| name.as_os_str().to_string_lossy())
+            .collect();
+
+        names.join("/")
+    }
+
+    /// Reports `bytes` more bytes written to the progress observer.
+    pub fn report_written(&self, bytes: u64) {
+        if let Some(ref progress) = self.progress {
+            progress.bytes_written(bytes);
+        }
+    }
+}
+
+/// Destination of the unpacked elements, like a directory tree or an
+/// archive. A nested container becomes a directory of its elements.
+pub trait OutputSink {
+    fn get_options(&self) -> &SinkOptions;
+
+    fn get_options_mut(&mut self) -> &mut SinkOptions;
+
+    /// Creates a directory for a nested container.
+    fn create_dir(&mut self, path: &Path) -> Result<()>;
+
+    /// Writes data of an element to a file, unless the element is rejected
+    /// by the filter.
+    fn write_file(&mut self, path: &Path, data: &[u8]) -> Result<()>;
+
+    /// Writes `len` bytes of an element read from `src` to a file, without
+    /// holding the data in memory.
+    fn write_file_from(
+        &mut self,
+        path: &Path,
+        src: &mut dyn ReadSeek,
+        len: u64,
+    ) -> Result<()>;
+
+    fn get_report(&self) -> &UnpackReport;
+
+    /// Completes the output, like writing the end of an archive.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// See `SinkOptions::visits`.
+    fn visits(&self, path: &Path) -> bool {
+        self.get_options().visits(path)
+    }
+
+    /// See `SinkOptions::accepts`.
+    fn accepts(&self, path: &Path) -> bool {
+        self.get_options().accepts(path)
+    }
+
+    fn get_cancellation(&self) -> &CancellationToken {
+        self.get_options().get_cancellation()
+    }
+}
+
+/// Name of the file in the output directory which lists the files and
+/// directories written by the unpacks into it, one path per line.
+pub const MANIFEST_NAME: &str = ".v8unpack-manifest";
+
+/// Writes elements into a directory tree.
+///
+/// In incremental mode a file is rewritten only if its content differs from
+/// the file already on disk, so mtimes of unchanged files are preserved.
+/// The paths written are recorded in the manifest, see `MANIFEST_NAME`, so
+/// that `remove_stale` never touches files it did not write.
+#[derive(Debug, Default)]
+pub struct DirSink {
+    incremental: bool,
+    report: UnpackReport,
+    written: HashSet<PathBuf>,
+    options: SinkOptions,
+}
+
+impl DirSink {
+    /// Creates a new instance of `DirSink`.
+    pub fn new(incremental: bool) -> DirSink {
+        DirSink {
+            incremental,
+            ..DirSink::default()
+        }
+    }
+
+    pub fn with_options(mut self, options: SinkOptions) -> Self {
+        self.options = options;
+
+        self
+    }
+
+    /// Skips the elements rejected by `filter`, the paths of the elements
+    /// are the paths of the files relative to `root`. Files skipped this way
+    /// are not removed by `remove_stale`.
+    pub fn with_filter(mut self, root: &Path, filter: ElemFilter) -> Self {
+        self.options = self.options.with_filter(root, filter);
+
+        self
+    }
+
+    /// Reports the bytes written to `progress`.
+    pub fn with_progress(mut self, progress: Option<Arc<dyn Progress>>) -> Self {
+        self.options = self.options.with_progress(progress);
+
+        self
+    }
+
+    /// Stops writing with `V8Error::Cancelled` once `token` is cancelled.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.options = self.options.with_cancellation(token);
+
+        self
+    }
+
+    /// Records the files and directories written by this sink under `root`
+    /// in the manifest, along with those of the earlier unpacks which are
+    /// still on disk.
+    pub fn save_manifest(&mut self, root: &Path) -> Result<()> {
+        self.update_manifest(root, false)
+    }
+
+    /// Removes files and directories under `root` which an earlier unpack
+    /// listed in the manifest and this sink did not write, then updates the
+    /// manifest. Other files, like `.git` or files added by hand, are left
+    /// alone.
+    pub fn remove_stale(&mut self, root: &Path) -> Result<()> {
+        self.update_manifest(root, true)
+    }
+
+    fn update_manifest(&mut self, root: &Path, remove: bool) -> Result<()> {
+        let manifest = root.join(MANIFEST_NAME);
+        let mut previous: Vec<String> = match fs::read_to_string(&manifest) {
+            Ok(text) => text.lines().map(str::to_string).collect(),
+            Err(ref e) if e.kind() == ioErrorKind::NotFound => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        // Files go before their directories, which are removed only when
+        // empty.
+        previous.sort_by_key(|relative| cmp::Reverse(relative.matches('/').count()));
+
+        let mut kept = vec![];
+        for relative in previous {
+            // A damaged manifest must not lead outside of `root` or to the
+            // hidden entries, like `.git`.
+            if relative.is_empty()
+                || relative
+                    .split('/')
+                    .any(|name| name.is_empty() || name.starts_with('.'))
+            {
+                continue;
+            }
+            let path = relative
+                .split('/')
+                .fold(root.to_path_buf(), |path, name| path.join(name));
+            if self.written.contains(&path) {
+                continue;
+            }
+            let meta = match fs::symlink_metadata(&path) {
+                Ok(meta) => meta,
+                Err(ref e) if e.kind() == ioErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            if remove && self.visits(&path) {
+                if meta.is_dir() {
+                    if fs::read_dir(&path)?.next().is_none() {
+                        info!("remove directory {:?}", path);
+                        fs::remove_dir(&path)?;
+                        self.report.removed += 1;
+                        continue;
+                    }
+                } else if self.accepts(&path) {
+                    info!("remove file {:?}", path);
+                    fs::remove_file(&path)?;
+                    self.report.removed += 1;
+                    continue;
+                }
+            }
+            kept.push(relative);
+        }
+
+        let mut lines: Vec<String> = self
+            .written
+            .iter()
+            .filter_map(|path| relative_name(root, path))
+            .chain(kept)
+            .collect();
+        lines.sort();
+        lines.dedup();
+        let mut text = lines.join("\n");
+        text.push('\n');
+        fs::write(&manifest, text)?;
+
+        Ok(())
+    }
+
+    fn same_content_from<R>(path: &Path, src: &mut R, len: u64) -> Result<bool>
+    where
+        R: Read + Seek + ?Sized,
+    {
+        if fs::metadata(path)?.len() != len {
+            return Ok(false);
+        }
+
+        let mut old = BufReader::new(fs::File::open(path)?);
+        let mut new = src.take(len);
+        let mut old_buf = [0; 8192];
+        let mut new_buf = [0; 8192];
+        loop {
+            let read = new.read(&mut new_buf)?;
+            if read == 0 {
+                return Ok(true);
+            }
+            if old.read_exact(&mut old_buf[..read]).is_err()
+                || old_buf[..read] != new_buf[..read]
+            {
+                return Ok(false);
+            }
+        }
+    }
+
+    fn same_content(path: &Path, data: &[u8]) -> Result<bool> {
+        match fs::metadata(path) {
+            Ok(ref meta) if meta.len() != data.len() as u64 => return Ok(false),
+            Ok(_) => {}
+            Err(ref e) if e.kind() == ioErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+
+        Ok(fs::read(path)? == data)
+    }
+}
+
+impl OutputSink for DirSink {
+    fn get_options(&self) -> &SinkOptions {
+        &self.options
+    }
+
+    fn get_options_mut(&mut self) -> &mut SinkOptions {
+        &mut self.options
+    }
+
+    fn create_dir(&mut self, path: &Path) -> Result<()> {
+        self.options.get_cancellation().check()?;
+        if path.as_os_str().is_empty() {
+            return Ok(());
+        }
+        if path.is_file() {
+            fs::remove_file(path)?;
+        }
+        if !path.exists() {
+            fs::create_dir(path)?;
+        }
+        self.written.insert(path.to_path_buf());
+
+        Ok(())
+    }
+
+    fn write_file(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        self.options.get_cancellation().check()?;
+        if !self.accepts(path) {
+            debug!("skip filtered element {:?}", path);
+            return Ok(());
+        }
+        self.written.insert(path.to_path_buf());
+
+        if path.is_dir() {
+            fs::remove_dir_all(path)?;
+            self.report.changed += 1;
+        } else if !path.exists() {
+            self.report.added += 1;
+        } else if self.incremental && Self::same_content(path, data)? {
+            self.report.unchanged += 1;
+            return Ok(());
+        } else {
+            self.report.changed += 1;
+        }
+
+        debug!("write to file {:?}", path);
+        fs::write(path, data)?;
+        self.options.report_written(data.len() as u64);
+
+        Ok(())
+    }
+
+    fn write_file_from(
+        &mut self,
+        path: &Path,
+        src: &mut dyn ReadSeek,
+        len: u64,
+    ) -> Result<()> {
+        self.options.get_cancellation().check()?;
+        if !self.accepts(path) {
+            debug!("skip filtered element {:?}", path);
+            return Ok(());
+        }
+        self.written.insert(path.to_path_buf());
+
+        if path.is_dir() {
+            fs::remove_dir_all(path)?;
+            self.report.changed += 1;
+        } else if !path.exists() {
+            self.report.added += 1;
+        } else if self.incremental && Self::same_content_from(path, src, len)? {
+            self.report.unchanged += 1;
+            return Ok(());
+        } else {
+            self.report.changed += 1;
+        }
+
+        debug!("write to file {:?}", path);
+        src.seek(SeekFrom::Start(0))?;
+        let written = match io::copy(&mut src.take(len), &mut fs::File::create(path)?) {
+            Ok(written) => written,
+            Err(e) => {
+                // Do not leave a half-written file behind.
+                let _ = fs::remove_file(path);
+                return Err(e.into());
+            }
+        };
+        self.options.report_written(written);
+
+        Ok(())
+    }
+
+    fn get_report(&self) -> &UnpackReport {
+        &self.report
+    }
+}
+
+/// Returns the path of `path` relative to `root`, separated with `/`.
+fn relative_name(root: &Path, path: &Path) -> Option<String> {
+    let names: Option<Vec<_>> = path
+        .strip_prefix(root)
+        .ok()?
+        .components()
+        .map(|name| name.as_os_str().to_str())
+        .collect();
+
+    names
+        .filter(|names| !names.is_empty())
+        .map(|names| names.join("/"))
+}
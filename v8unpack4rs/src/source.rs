@@ -0,0 +1,88 @@
+//! Reading the source files of a container to build.
+
+use std::path::PathBuf;
+use std::{fmt, fs};
+
+use log::*;
+
+use crate::container::Result;
+use crate::sink::MANIFEST_NAME;
+
+/// A file or a directory of an input source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceEntry {
+    pub name: String,
+    /// The entry is a directory of the elements of a nested container.
+    pub is_dir: bool,
+}
+
+/// Source files of a container, like a directory tree or an archive.
+///
+/// Paths are separated with `/` and relative to the root of the source,
+/// which is the empty path.
+pub trait InputSource: fmt::Debug + Send + Sync {
+    /// Lists the entries of the directory at `path`.
+    fn read_dir(&self, path: &str) -> Result<Vec<SourceEntry>>;
+
+    /// Reads the content of the file at `path`.
+    fn read_file(&self, path: &str) -> Result<Vec<u8>>;
+
+    /// Returns the path of the entry on disk, which allows to use the block
+    /// cache for it. `None` by default.
+    fn local_path(&self, _path: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Source files in a directory tree on disk.
+#[derive(Debug, Clone)]
+pub struct DirSource {
+    root: PathBuf,
+}
+
+impl DirSource {
+    /// Creates a new instance of `DirSource` reading the directory `root`.
+    pub fn new<P: Into<PathBuf>>(root: P) -> DirSource {
+        DirSource { root: root.into() }
+    }
+
+    fn path_of(&self, path: &str) -> PathBuf {
+        path.split('/')
+            .filter(|name| !name.is_empty())
+            .fold(self.root.clone(), |result, name| result.join(name))
+    }
+}
+
+impl InputSource for DirSource {
+    fn read_dir(&self, path: &str) -> Result<Vec<SourceEntry>> {
+        let mut entries = vec![];
+        for entry in fs::read_dir(self.path_of(path))? {
+            let entry = entry?;
+            if let Ok(name) = entry.file_name().into_string() {
+                if name == MANIFEST_NAME {
+                    continue;
+                }
+                if let Ok(file_type) = entry.file_type() {
+                    entries.push(SourceEntry {
+                        name,
+                        is_dir: file_type.is_dir(),
+                    });
+                } else {
+                    error!("Couldn't get file type for {:?}", entry.path());
+                }
+            } else {
+                error!("Couldn't get file name for {:?}", entry.path());
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        Ok(fs::read(self.path_of(path))?)
+    }
+
+    fn local_path(&self, path: &str) -> Option<PathBuf> {
+        Some(self.path_of(path))
+    }
+}
@@ -0,0 +1,306 @@
+//! In-memory tree of the elements of a container.
+//!
+//! A tree holds the same structure as a directory made by unpacking: files
+//! with the inflated data of the elements and directories with the elements
+//! of nested containers. It allows to unpack, modify and build containers
+//! without touching the disk.
+
+use crate::container::{Result, V8File};
+use crate::filter::ElemFilter;
+use crate::sink::{OutputSink, ReadSeek, SinkOptions, UnpackReport};
+use crate::source::{InputSource, SourceEntry};
+
+use std::io::{self, ErrorKind as ioErrorKind, Read, SeekFrom};
+use std::path::Path;
+
+/// A file or a nested container of a tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemNode {
+    File(Vec<u8>),
+    Dir(MemTree),
+}
+
+/// Elements of a container by name, in the order of the container.
+///
+/// Paths of the elements are separated with `/`, like `Form/form`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemTree {
+    entries: Vec<(String, MemNode)>,
+}
+
+impl MemTree {
+    /// Creates a new empty instance of `MemTree`.
+    pub fn new() -> MemTree {
+        MemTree::default()
+    }
+
+    /// Converts a container loaded by `parser::load_file`.
+    pub fn from_v8file(file: &V8File) -> Result<MemTree> {
+        let mut sink = MemSink::new();
+        file.save_file_to_sink(Path::new(""), &mut sink)?;
+
+        Ok(sink.into_tree())
+    }
+
+    /// Converts the tree into a container the same way as
+    /// `V8File::load_file_from_folder` does.
+    pub fn to_v8file(&self) -> Result<V8File> {
+        let mut file = V8File::new();
+        file.load_from_source(self, "", &ElemFilter::new())?;
+
+        Ok(file)
+    }
+
+    /// Number of the elements of the root container.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterates over the elements of the root container.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &MemNode)> {
+        self.entries
+            .iter()
+            .map(|(name, node)| (name.as_str(), node))
+    }
+
+    /// Returns the paths and the data of all files, nested ones included.
+    pub fn files(&self) -> Vec<(String, &[u8])> {
+        let mut result = vec![];
+        self.collect_files("", &mut result);
+
+        result
+    }
+
+    pub fn get(&self, path: &str) -> Option<&MemNode> {
+        let (parent, name) = split_path(path);
+        let dir = if parent.is_empty() {
+            self
+        } else {
+            match self.get(parent)? {
+                MemNode::Dir(dir) => dir,
+                MemNode::File(_) => return None,
+            }
+        };
+
+        dir.entries
+            .iter()
+            .find(|(entry, _)| entry == name)
+            .map(|(_, node)| node)
+    }
+
+    pub fn get_mut(&mut self, path: &str) -> Option<&mut MemNode> {
+        let (parent, name) = split_path(path);
+        let dir = if parent.is_empty() {
+            self
+        } else {
+            match self.get_mut(parent)? {
+                MemNode::Dir(dir) => dir,
+                MemNode::File(_) => return None,
+            }
+        };
+
+        dir.entries
+            .iter_mut()
+            .find(|(entry, _)| entry == name)
+            .map(|(_, node)| node)
+    }
+
+    /// Returns the data of the file at `path`.
+    pub fn get_file(&self, path: &str) -> Option<&[u8]> {
+        match self.get(path)? {
+            MemNode::File(data) => Some(data),
+            MemNode::Dir(_) => None,
+        }
+    }
+
+    /// Stores `data` in the file at `path`, creating the nested containers
+    /// on the way. A file or a nested container at `path` is replaced.
+    pub fn insert_file(&mut self, path: &str, data: Vec<u8>) {
+        let (parent, name) = split_path(path);
+        self.dir_mut(parent).set(name, MemNode::File(data));
+    }
+
+    /// Creates the nested container at `path` with the enclosing ones, a
+    /// file in the way is replaced.
+    pub fn insert_dir(&mut self, path: &str) -> &mut MemTree {
+        self.dir_mut(path)
+    }
+
+    /// Removes the element at `path` and returns it.
+    pub fn remove(&mut self, path: &str) -> Option<MemNode> {
+        let (parent, name) = split_path(path);
+        let dir = if parent.is_empty() {
+            self
+        } else {
+            match self.get_mut(parent)? {
+                MemNode::Dir(dir) => dir,
+                MemNode::File(_) => return None,
+            }
+        };
+
+        let index = dir.entries.iter().position(|(entry, _)| entry == name)?;
+        Some(dir.entries.remove(index).1)
+    }
+
+    fn set(&mut self, name: &str, node: MemNode) {
+        match self.entries.iter_mut().find(|(entry, _)| entry == name) {
+            Some(entry) => entry.1 = node,
+            None => self.entries.push((name.to_string(), node)),
+        }
+    }
+
+    /// Returns the nested container at `path`, creating it if needed.
+    fn dir_mut(&mut self, path: &str) -> &mut MemTree {
+        let mut dir = self;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let index = match dir.entries.iter().position(|(entry, _)| entry == name) {
+                Some(index) => index,
+                None => {
+                    dir.entries
+                        .push((name.to_string(), MemNode::Dir(MemTree::new())));
+                    dir.entries.len() - 1
+                }
+            };
+            let node = &mut dir.entries[index].1;
+            if let MemNode::File(_) = node {
+                *node = MemNode::Dir(MemTree::new());
+            }
+            dir = match node {
+                MemNode::Dir(nested) => nested,
+                MemNode::File(_) => unreachable!(),
+            };
+        }
+
+        dir
+    }
+
+    fn collect_files<'a>(&'a self, prefix: &str, result: &mut Vec<(String, &'a [u8])>) {
+        for (name, node) in self.entries.iter() {
+            let path = if prefix.is_empty() {
+                name.clone()
+            } else {
+                format!("{}/{}", prefix, name)
+            };
+            match node {
+                MemNode::File(data) => result.push((path, data)),
+                MemNode::Dir(dir) => dir.collect_files(&path, result),
+            }
+        }
+    }
+}
+
+impl InputSource for MemTree {
+    fn read_dir(&self, path: &str) -> Result<Vec<SourceEntry>> {
+        let dir = if path.is_empty() {
+            self
+        } else {
+            match self.get(path) {
+                Some(MemNode::Dir(dir)) => dir,
+                _ => return Err(not_found(path)),
+            }
+        };
+
+        Ok(dir
+            .entries
+            .iter()
+            .map(|(name, node)| SourceEntry {
+                name: name.clone(),
+                is_dir: matches!(node, MemNode::Dir(_)),
+            })
+            .collect())
+    }
+
+    fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        self.get_file(path)
+            .map(|data| data.to_vec())
+            .ok_or_else(|| not_found(path))
+    }
+}
+
+/// Writes elements into a `MemTree`.
+#[derive(Debug, Default)]
+pub struct MemSink {
+    tree: MemTree,
+    options: SinkOptions,
+    report: UnpackReport,
+}
+
+impl MemSink {
+    /// Creates a new instance of `MemSink` with an empty tree.
+    pub fn new() -> MemSink {
+        MemSink::default()
+    }
+
+    pub fn into_tree(self) -> MemTree {
+        self.tree
+    }
+}
+
+impl OutputSink for MemSink {
+    fn get_options(&self) -> &SinkOptions {
+        &self.options
+    }
+
+    fn get_options_mut(&mut self) -> &mut SinkOptions {
+        &mut self.options
+    }
+
+    fn create_dir(&mut self, path: &Path) -> Result<()> {
+        self.options.get_cancellation().check()?;
+        self.tree.insert_dir(&self.options.elem_path(path));
+
+        Ok(())
+    }
+
+    fn write_file(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        self.options.get_cancellation().check()?;
+        if !self.accepts(path) {
+            return Ok(());
+        }
+
+        self.tree
+            .insert_file(&self.options.elem_path(path), data.to_vec());
+        self.report.added += 1;
+        self.options.report_written(data.len() as u64);
+
+        Ok(())
+    }
+
+    fn write_file_from(
+        &mut self,
+        path: &Path,
+        src: &mut dyn ReadSeek,
+        len: u64,
+    ) -> Result<()> {
+        let mut data = vec![];
+        src.seek(SeekFrom::Start(0))?;
+        src.take(len).read_to_end(&mut data)?;
+
+        self.write_file(path, &data)
+    }
+
+    fn get_report(&self) -> &UnpackReport {
+        &self.report
+    }
+}
+
+/// Splits `path` into the path of the parent and the name.
+fn split_path(path: &str) -> (&str, &str) {
+    let path = path.trim_matches('/');
+    match path.rfind('/') {
+        Some(pos) => (&path[..pos], &path[pos + 1..]),
+        None => ("", path),
+    }
+}
+
+fn not_found(path: &str) -> crate::error::V8Error {
+    io::Error::new(
+        ioErrorKind::NotFound,
+        format!("{} is not in the tree", path),
+    )
+    .into()
+}
@@ -0,0 +1,260 @@
+//! Borrowed parsing layer over a container that is already in memory.
+//!
+//! Unlike the `parser` module, which copies every header and page into
+//! fresh buffers, `ContainerView` hands out slices of the source bytes and
+//! allocates only when a block is split into several pages.
+
+use std::borrow::Cow;
+use std::cmp;
+use std::collections::HashSet;
+use std::io::{Cursor, Error as ioError, ErrorKind as ioErrorKind};
+
+use crate::container::*;
+use crate::error;
+
+/// Read-only view of a container stored in a byte slice.
+#[derive(Debug)]
+pub struct ContainerView<'a> {
+    data: &'a [u8],
+    file_header: FileHeader,
+    elems_addrs: Vec<ElemAddr>,
+}
+
+impl<'a> ContainerView<'a> {
+    /// Creates a view of the container, reading its table of contents.
+    pub fn new(data: &'a [u8]) -> Result<ContainerView<'a>> {
+        if !Self::is_v8file(data) {
+            return Err(error::V8Error::NotV8File { offset: 0 });
+        }
+
+        let file_header = FileHeader::from_bytes(data, 0)?;
+        let mut view = ContainerView {
+            data,
+            file_header,
+            elems_addrs: vec![],
+        };
+
+        let toc = view.read_block(FileHeader::SIZE)?;
+        let data_size = toc.len() as u64;
+        let mut rdr = Cursor::new(toc.as_ref());
+        while rdr.position() + u64::from(ElemAddr::SIZE) <= data_size {
+            let elem_addr = ElemAddr::from_raw_parts(&mut rdr)?;
+            if elem_addr.fffffff != V8_MAGIC_NUMBER {
+                break;
+            }
+
+            if elem_addr.elem_header_addr as usize >= data.len()
+                || (elem_addr.elem_data_addr != V8_MAGIC_NUMBER
+                    && elem_addr.elem_data_addr as usize >= data.len())
+            {
+                return Err(error::V8Error::BadTocEntry {
+                    offset: u64::from(FileHeader::SIZE),
+                    path: String::new(),
+                    container: None,
+                    index: view.elems_addrs.len(),
+                });
+            }
+            view.elems_addrs.push(elem_addr);
+        }
+
+        Ok(view)
+    }
+
+    /// Creates a view of a container checked by `new` before, reading its
+    /// blocks by offsets without the table of contents.
+    pub(crate) fn without_toc(data: &'a [u8]) -> Result<ContainerView<'a>> {
+        Ok(ContainerView {
+            data,
+            file_header: FileHeader::from_bytes(data, 0)?,
+            elems_addrs: vec![],
+        })
+    }
+
+    /// Checks that the slice starts with a correct container.
+    pub fn is_v8file(data: &[u8]) -> bool {
+        if FileHeader::from_bytes(data, 0).is_err() {
+            return false;
+        }
+
+        match data.get(FileHeader::SIZE as usize..) {
+            Some(rest) => {
+                match BlockHeader::from_bytes(rest, u64::from(FileHeader::SIZE)) {
+                    Ok(block_header) => block_header.is_correct(),
+                    Err(_) => false,
+                }
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the source bytes of the container.
+    pub fn get_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn get_file_header(&self) -> &FileHeader {
+        &self.file_header
+    }
+
+    pub fn get_elems_addrs(&self) -> &[ElemAddr] {
+        &self.elems_addrs
+    }
+
+    /// Returns the number of elements in the container.
+    pub fn len(&self) -> usize {
+        self.elems_addrs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elems_addrs.is_empty()
+    }
+
+    /// Reads the element with the given index in the table of contents.
+    pub fn elem(&self, index: usize) -> Result<ElemView<'a>> {
+        let elem_addr = match self.elems_addrs.get(index) {
+            Some(elem_addr) => elem_addr,
+            None => {
+                return Err(error::V8Error::IoError(ioError::new(
+                    ioErrorKind::InvalidInput,
+                    "Element index out of range",
+                )))
+            }
+        };
+
+        let header = self.read_block(elem_addr.elem_header_addr)?;
+        let data = if elem_addr.elem_data_addr != V8_MAGIC_NUMBER {
+            Some(self.read_block(elem_addr.elem_data_addr)?)
+        } else {
+            None
+        };
+
+        Ok(ElemView {
+            header,
+            data,
+            header_addr: elem_addr.elem_header_addr,
+            data_addr: elem_addr.elem_data_addr,
+        })
+    }
+
+    /// Iterates over the elements in the order of the table of contents.
+    pub fn iter<'s>(&'s self) -> impl Iterator<Item = Result<ElemView<'a>>> + 's {
+        (0..self.len()).map(move |index| self.elem(index))
+    }
+
+    /// Reads the data of the block which header starts at `offset`.
+    ///
+    /// The data of a single-page block is borrowed from the source slice.
+    /// A chain of pages which ends before the data does, loops or holds more
+    /// data than the container is an error.
+    pub fn read_block(&self, offset: u32) -> Result<Cow<'a, [u8]>> {
+        let mut block_header = self.block_header_at(offset)?;
+        let data_size = block_header.get_data_size()? as usize;
+        let mut page_addr = offset;
+        let mut page_size = block_header.get_page_size()? as usize;
+
+        if data_size <= page_size {
+            return Ok(Cow::Borrowed(self.page(page_addr, data_size)?));
+        }
+
+        let mut result = Vec::with_capacity(cmp::min(data_size, self.data.len()));
+        let mut visited = HashSet::new();
+        loop {
+            visited.insert(page_addr);
+            let len = cmp::min(page_size, data_size - result.len());
+            result.extend_from_slice(self.page(page_addr, len)?);
+            if result.len() >= data_size {
+                break;
+            }
+
+            let next_page_addr = block_header.get_next_page_addr()?;
+            if next_page_addr == V8_MAGIC_NUMBER {
+                return Err(truncated_block(u64::from(page_addr)));
+            }
+            if visited.contains(&next_page_addr) || result.len() > self.data.len() {
+                return Err(bad_block_header(u64::from(page_addr)));
+            }
+
+            block_header = self.block_header_at(next_page_addr)?;
+            page_addr = next_page_addr;
+            page_size = block_header.get_page_size()? as usize;
+        }
+
+        Ok(Cow::Owned(result))
+    }
+
+    fn block_header_at(&self, offset: u32) -> Result<BlockHeader> {
+        let header = self.slice(offset, offset as usize, BlockHeader::SIZE as usize)?;
+        let block_header = BlockHeader::from_bytes(header, u64::from(offset))?;
+
+        if !block_header.is_correct() {
+            return Err(bad_block_header(u64::from(offset)));
+        }
+
+        Ok(block_header)
+    }
+
+    /// Returns `len` bytes of the page which header starts at `page_addr`.
+    fn page(&self, page_addr: u32, len: usize) -> Result<&'a [u8]> {
+        let start = page_addr as usize + BlockHeader::SIZE as usize;
+        self.slice(page_addr, start, len)
+    }
+
+    fn slice(&self, page_addr: u32, start: usize, len: usize) -> Result<&'a [u8]> {
+        start
+            .checked_add(len)
+            .and_then(|end| self.data.get(start..end))
+            .ok_or_else(|| truncated_block(u64::from(page_addr)))
+    }
+}
+
+/// Element of a container borrowed from a `ContainerView`.
+#[derive(Debug, Clone)]
+pub struct ElemView<'a> {
+    header: Cow<'a, [u8]>,
+    data: Option<Cow<'a, [u8]>>,
+    header_addr: u32,
+    data_addr: u32,
+}
+
+impl<'a> ElemView<'a> {
+    /// Gets the name of the file in the container.
+    pub fn get_name(&self) -> Result<String> {
+        elem_name(&self.header, u64::from(self.header_addr))
+    }
+
+    pub fn get_header(&self) -> &[u8] {
+        &self.header
+    }
+
+    /// Returns the raw (possibly deflated) data of the element.
+    pub fn get_data(&self) -> Option<&[u8]> {
+        self.data.as_ref().map(|data| data.as_ref())
+    }
+
+    pub fn into_data(self) -> Option<Cow<'a, [u8]>> {
+        self.data
+    }
+
+    /// The offset into the container where is the header block.
+    pub fn get_header_addr(&self) -> u32 {
+        self.header_addr
+    }
+
+    /// The offset into the container where located data block.
+    pub fn get_data_addr(&self) -> u32 {
+        self.data_addr
+    }
+}
+
+/// Maps a container file into memory to be used with `ContainerView`.
+#[cfg(feature = "mmap")]
+pub fn map_file<P>(file_name: P) -> Result<memmap2::Mmap>
+where
+    P: AsRef<std::path::Path>,
+{
+    let file = std::fs::File::open(file_name)?;
+
+    // The map is read-only; as with any mmap the file must not be truncated
+    // while the view is alive.
+    Ok(unsafe { memmap2::Mmap::map(&file)? })
+}
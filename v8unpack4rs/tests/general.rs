@@ -3,12 +3,14 @@ extern crate tempdir;
 extern crate v8unpack4rs;
 
 use tempdir::TempDir;
+use v8unpack4rs::batch::{self, BatchCommand, BatchOptions};
+use v8unpack4rs::filter::ElemFilter;
 use v8unpack4rs::{builder, parser};
 
 use std::fs::File;
 use std::io::prelude::*;
 
-pub static TEST_FILE1: &'static [u8] = include_bytes!("test1.cf");
+pub static TEST_FILE1: &[u8] = include_bytes!("test1.cf");
 
 #[test]
 fn test_parse_and_build() {
@@ -33,7 +35,7 @@ fn test_parse_and_build() {
 
     assert!(parse_ok);
 
-    const BUILD_FILE: &'static str = "build.cf";
+    const BUILD_FILE: &str = "build.cf";
     let build_file = dir.path().join(BUILD_FILE);
     let build_file = build_file.as_path().to_str().unwrap();
 
@@ -58,3 +60,1612 @@ fn test_parse_and_build() {
 
     dir.close().unwrap();
 }
+
+#[test]
+fn test_build_parallel_is_deterministic() {
+    let dir = TempDir::new("test_build_parallel").unwrap();
+    let unpack = dir.path().join("unpack");
+    let unpack = unpack.to_str().unwrap();
+    let test1 = dir.path().join("test1.cf");
+    File::create(test1.clone())
+        .unwrap()
+        .write_all(TEST_FILE1)
+        .unwrap();
+
+    assert!(parser::unpack_to_directory_no_load(
+        test1.to_str().unwrap(),
+        unpack,
+        true,
+        true
+    )
+    .unwrap());
+
+    let single = dir.path().join("single.cf");
+    let single = single.to_str().unwrap();
+    let options = builder::BuildOptions::new().with_threads(1);
+    assert!(builder::build_cf_file_with_options(unpack, single, &options).unwrap());
+
+    let multi = dir.path().join("multi.cf");
+    let multi = multi.to_str().unwrap();
+    let options = builder::BuildOptions::new().with_threads(4);
+    assert!(builder::build_cf_file_with_options(unpack, multi, &options).unwrap());
+
+    assert_eq!(
+        std::fs::read(single).unwrap(),
+        std::fs::read(multi).unwrap()
+    );
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_build_large_elements_in_chunks() {
+    let dir = TempDir::new("test_build_chunks").unwrap();
+    let src = dir.path().join("src");
+    std::fs::create_dir_all(src.join("big").join("sub")).unwrap();
+    let mut seed = 1u32;
+    // The text starts with a byte order mark like the files of 1C do, so the
+    // parser, which tells raw data from deflated by trying to inflate it,
+    // takes the elements of the nested container as is.
+    let mut text = |size: usize| -> Vec<u8> {
+        let mut data = b"\xef\xbb\xbf".to_vec();
+        data.extend((3..size).map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            b"abcdefgh"[(seed >> 16) as usize % 8]
+        }));
+        data
+    };
+    let files = vec![
+        (std::path::Path::new("big/a"), text(5 << 18)),
+        (std::path::Path::new("big/sub/b"), text(1 << 16)),
+        (std::path::Path::new("top"), text(5 << 18)),
+    ];
+    for &(name, ref data) in &files {
+        File::create(src.join(name))
+            .unwrap()
+            .write_all(data)
+            .unwrap();
+    }
+    let src = src.to_str().unwrap();
+
+    let build = |name: &str, threads: usize| {
+        let out = dir.path().join(name);
+        let options = builder::BuildOptions::new().with_threads(threads);
+        assert!(builder::build_cf_file_with_options(
+            src,
+            out.to_str().unwrap(),
+            &options
+        )
+        .unwrap());
+        out
+    };
+    let single = build("single.cf", 1);
+    let multi = build("multi.cf", 4);
+    assert_eq!(
+        std::fs::read(&single).unwrap(),
+        std::fs::read(&multi).unwrap()
+    );
+
+    let parsed = dir.path().join("parsed");
+    assert!(parser::parse_to_folder(
+        multi.to_str().unwrap(),
+        parsed.to_str().unwrap(),
+        true
+    )
+    .unwrap());
+    for &(name, ref data) in &files {
+        assert!(
+            std::fs::read(parsed.join(name)).unwrap() == *data,
+            "{:?}",
+            name
+        );
+    }
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_view_matches_parser() {
+    use std::borrow::Cow;
+    use std::io::{Cursor, Seek, SeekFrom};
+    use v8unpack4rs::container::{BlockHeader, V8Container};
+    use v8unpack4rs::view::ContainerView;
+
+    let view = ContainerView::new(TEST_FILE1).unwrap();
+
+    let mut rdr = Cursor::new(TEST_FILE1);
+    let first_block_header = rdr.get_first_block_header().unwrap();
+    let elems_addrs = parser::read_elems_addrs(&mut rdr, &first_block_header).unwrap();
+
+    assert_eq!(view.len(), elems_addrs.len());
+    for (elem, elem_addr) in view.iter().zip(elems_addrs.iter()) {
+        let elem = elem.unwrap();
+
+        rdr.seek(SeekFrom::Start(u64::from(elem_addr.elem_data_addr)))
+            .unwrap();
+        let block_header = BlockHeader::from_raw_parts(&mut rdr).unwrap();
+        let expected = parser::read_block_data(&mut rdr, &block_header).unwrap();
+
+        let data = elem.into_data().unwrap();
+        assert!(matches!(data, Cow::Borrowed(_)));
+        assert_eq!(data.as_ref(), expected.as_slice());
+    }
+}
+
+#[test]
+fn test_broken_page_chains() {
+    use v8unpack4rs::container::{BlockHeader, FileHeader, V8_MAGIC_NUMBER};
+    use v8unpack4rs::error::V8Error;
+    use v8unpack4rs::view::ContainerView;
+
+    let toc_addr = FileHeader::SIZE;
+    // The table of contents of 48 bytes in pages of 12 bytes, the next page
+    // of the first one is given by `next_page_addr`.
+    let container = |next_page_addr: u32| {
+        let mut data = FileHeader::new(V8_MAGIC_NUMBER, 512, 0)
+            .into_bytes()
+            .unwrap();
+        data.extend(
+            BlockHeader::new(48, 12, next_page_addr)
+                .into_bytes()
+                .unwrap(),
+        );
+        data.extend_from_slice(&[0xff; 12]);
+        data
+    };
+
+    let dir = TempDir::new("test_page_chains").unwrap();
+    let file_name = dir.path().join("broken.cf");
+    let file_name = file_name.to_str().unwrap();
+    let cases = [
+        // The chain ends after the first page.
+        (container(V8_MAGIC_NUMBER), "truncated"),
+        // The first page is followed by itself.
+        (container(toc_addr), "cyclic"),
+    ];
+    for (data, kind) in cases.iter() {
+        let view_err = ContainerView::new(data).unwrap_err();
+        std::fs::write(file_name, data).unwrap();
+        let file_err = parser::file_info(file_name).unwrap_err();
+
+        for err in [view_err, file_err].iter() {
+            match (err, *kind) {
+                (V8Error::TruncatedBlock { offset, .. }, "truncated")
+                | (V8Error::BadBlockHeader { offset, .. }, "cyclic") => {
+                    assert_eq!(*offset, u64::from(toc_addr))
+                }
+                _ => panic!("unexpected error {:?} of a {} chain", err, kind),
+            }
+        }
+    }
+
+    // Two pages pointing at each other.
+    let mut data = container(0);
+    let second_addr = data.len() as u32;
+    let toc_header = BlockHeader::new(48, 12, second_addr).into_bytes().unwrap();
+    data[toc_addr as usize..second_addr as usize - 12].copy_from_slice(&toc_header);
+    data.extend(BlockHeader::new(0, 12, toc_addr).into_bytes().unwrap());
+    data.extend_from_slice(&[0xff; 12]);
+    assert!(matches!(
+        ContainerView::new(&data),
+        Err(V8Error::BadBlockHeader { offset, .. }) if offset == u64::from(second_addr)
+    ));
+    std::fs::write(file_name, &data).unwrap();
+    assert!(matches!(
+        parser::file_info(file_name),
+        Err(V8Error::BadBlockHeader { offset, .. }) if offset == u64::from(second_addr)
+    ));
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_incremental_unpack() {
+    let dir = TempDir::new("test_incremental").unwrap();
+    let unpack = dir.path().join("unpack");
+    let test1 = dir.path().join("test1.cf");
+    File::create(test1.clone())
+        .unwrap()
+        .write_all(TEST_FILE1)
+        .unwrap();
+    let test1 = test1.to_str().unwrap();
+
+    // The first container has an element which the second one lacks.
+    let source = dir.path().join("source");
+    assert!(parser::parse_to_folder(test1, source.to_str().unwrap(), true).unwrap());
+    File::create(source.join("stale"))
+        .unwrap()
+        .write_all(b"stale")
+        .unwrap();
+    let with_stale = dir.path().join("with_stale.cf");
+    let with_stale = with_stale.to_str().unwrap();
+    assert!(
+        builder::build_cf_file(source.to_str().unwrap(), with_stale, false).unwrap()
+    );
+
+    let options = parser::UnpackOptions::new()
+        .with_incremental(true)
+        .with_remove_stale(true);
+
+    let first = parser::unpack_to_directory_with_options(
+        with_stale,
+        unpack.to_str().unwrap(),
+        &options,
+    )
+    .unwrap();
+    assert!(first.added > 0);
+    assert_eq!(first.changed + first.unchanged + first.removed, 0);
+
+    let stale = unpack.join("stale");
+    assert!(stale.exists());
+    let version = unpack.join("version");
+    File::create(&version).unwrap().write_all(b"old").unwrap();
+    // Files the unpack did not write are left alone.
+    std::fs::create_dir_all(unpack.join(".git").join("refs")).unwrap();
+    File::create(unpack.join(".git").join("HEAD"))
+        .unwrap()
+        .write_all(b"ref: refs/heads/master")
+        .unwrap();
+    let untracked = unpack.join("untracked.txt");
+    File::create(&untracked)
+        .unwrap()
+        .write_all(b"notes")
+        .unwrap();
+
+    let second =
+        parser::parse_to_folder_with_options(test1, unpack.to_str().unwrap(), &options)
+            .unwrap();
+    assert_eq!(second.added, 0);
+    assert_eq!(second.changed, 1);
+    assert_eq!(second.unchanged, first.added - 2);
+    assert_eq!(second.removed, 1);
+    assert!(!stale.exists());
+    assert!(untracked.exists());
+    assert!(unpack.join(".git").join("HEAD").exists());
+    assert!(unpack.join(".git").join("refs").is_dir());
+    assert_ne!(std::fs::read(&version).unwrap(), b"old");
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_build_with_cache() {
+    use v8unpack4rs::builder::cache::BlockCache;
+
+    let dir = TempDir::new("test_build_cache").unwrap();
+    let unpack = dir.path().join("unpack");
+    let test1 = dir.path().join("test1.cf");
+    File::create(test1.clone())
+        .unwrap()
+        .write_all(TEST_FILE1)
+        .unwrap();
+    assert!(parser::unpack_to_directory_no_load(
+        test1.to_str().unwrap(),
+        unpack.to_str().unwrap(),
+        true,
+        true
+    )
+    .unwrap());
+    let unpack = unpack.to_str().unwrap();
+    let cache_dir = dir.path().join("cache");
+
+    let clean = dir.path().join("clean.cf");
+    assert!(builder::build_cf_file(unpack, clean.to_str().unwrap(), false).unwrap());
+
+    let build = |name: &str| {
+        let cache = BlockCache::open(&cache_dir).unwrap();
+        let options = builder::BuildOptions::new().with_cache(cache);
+        let out = dir.path().join(name);
+        assert!(builder::build_cf_file_with_options(
+            unpack,
+            out.to_str().unwrap(),
+            &options
+        )
+        .unwrap());
+        let cache = options.get_cache().unwrap();
+
+        (
+            std::fs::read(out).unwrap(),
+            cache.get_hits(),
+            cache.get_misses(),
+        )
+    };
+
+    let (cold, hits, misses) = build("cold.cf");
+    assert_eq!(hits, 0);
+    assert!(misses > 1);
+    assert_eq!(cold, std::fs::read(&clean).unwrap());
+
+    let (warm, hits, _) = build("warm.cf");
+    assert_eq!(hits, misses);
+    assert_eq!(warm, cold);
+
+    File::create(dir.path().join("unpack").join("version"))
+        .unwrap()
+        .write_all(b"changed")
+        .unwrap();
+    let (_, hits_after_change, misses_after_change) = build("changed.cf");
+    assert_eq!(misses_after_change, 1);
+    assert_eq!(hits_after_change, misses - 1);
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_build_with_cache_and_duplicate_files() {
+    use v8unpack4rs::builder::cache::BlockCache;
+
+    let dir = TempDir::new("test_build_cache_duplicates").unwrap();
+    let src = dir.path().join("src");
+    std::fs::create_dir(&src).unwrap();
+    let data = vec![b'x'; 256 * 1024];
+    for i in 0..64 {
+        File::create(src.join(format!("f{}", i)))
+            .unwrap()
+            .write_all(&data)
+            .unwrap();
+    }
+    let src = src.to_str().unwrap();
+
+    // The threads build blocks with the same key and store them at once.
+    for run in 0..4 {
+        let cache = BlockCache::open(dir.path().join(format!("cache{}", run))).unwrap();
+        let options = builder::BuildOptions::new()
+            .with_threads(16)
+            .with_cache(cache);
+        let out = dir.path().join(format!("out{}.cf", run));
+        assert!(builder::build_cf_file_with_options(
+            src,
+            out.to_str().unwrap(),
+            &options
+        )
+        .unwrap());
+    }
+
+    dir.close().unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn test_cache_key_of_symlinks() {
+    use std::os::unix::fs::symlink;
+    use v8unpack4rs::builder::cache::BlockCache;
+
+    let dir = TempDir::new("test_cache_symlinks").unwrap();
+    let sub = dir.path().join("sub");
+    std::fs::create_dir(&sub).unwrap();
+    File::create(sub.join("a"))
+        .unwrap()
+        .write_all(b"a")
+        .unwrap();
+    symlink(sub.join("a"), dir.path().join("link_file")).unwrap();
+    symlink(&sub, dir.path().join("link_dir")).unwrap();
+
+    // Links are read as files, the same as the builder reads them.
+    assert_eq!(
+        BlockCache::key(&dir.path().join("link_file"), false).unwrap(),
+        BlockCache::key(&sub.join("a"), false).unwrap()
+    );
+    assert!(BlockCache::key(&dir.path().join("link_dir"), false).is_err());
+    assert!(BlockCache::key(&sub, false).is_ok());
+
+    dir.close().unwrap();
+}
+
+fn read_tree(root: &std::path::Path) -> Vec<(std::path::PathBuf, Vec<u8>)> {
+    let mut result = vec![];
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if !path.ends_with(v8unpack4rs::sink::MANIFEST_NAME) {
+                let data = std::fs::read(&path).unwrap();
+                result.push((path.strip_prefix(root).unwrap().to_path_buf(), data));
+            }
+        }
+    }
+    result.sort();
+
+    result
+}
+
+#[test]
+fn test_parse_with_memory_budget() {
+    let dir = TempDir::new("test_memory_budget").unwrap();
+    let test1 = dir.path().join("test1.cf");
+    File::create(test1.clone())
+        .unwrap()
+        .write_all(TEST_FILE1)
+        .unwrap();
+    let test1 = test1.to_str().unwrap();
+
+    let unbounded = dir.path().join("unbounded");
+    assert!(parser::parse_to_folder(test1, unbounded.to_str().unwrap(), true).unwrap());
+
+    // Every element is larger than the budget and goes through a temporary file.
+    let bounded = dir.path().join("bounded");
+    let options = parser::UnpackOptions::new().with_max_memory(Some(16));
+    parser::parse_to_folder_with_options(test1, bounded.to_str().unwrap(), &options)
+        .unwrap();
+
+    assert_eq!(read_tree(&unbounded), read_tree(&bounded));
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_parse_with_compressed_data_filling_budget() {
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    let dir = TempDir::new("test_compressed_budget").unwrap();
+    let src = dir.path().join("src");
+    std::fs::create_dir(&src).unwrap();
+    let mut seed = 1u32;
+    for i in 0..6 {
+        let data: Vec<u8> = (0..900)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                if seed & 0x10000 == 0 {
+                    b'a'
+                } else {
+                    b'b'
+                }
+            })
+            .collect();
+        File::create(src.join(format!("f{}", i)))
+            .unwrap()
+            .write_all(&data)
+            .unwrap();
+    }
+    let packed = dir.path().join("packed.cf");
+    let packed = packed.to_str().unwrap().to_string();
+    assert!(builder::build_cf_file(src.to_str().unwrap(), &packed, false).unwrap());
+
+    // Each element fits after inflating, but the compressed elements queued
+    // before the inflate stage take the rest of the budget.
+    let out = dir.path().join("out");
+    let out = out.to_str().unwrap().to_string();
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || {
+        let options = parser::UnpackOptions::new().with_max_memory(Some(1000));
+        let result = parser::parse_to_folder_with_options(&packed, &out, &options);
+        sender.send(result.map(|report| report.added)).unwrap();
+    });
+    let added = receiver.recv_timeout(Duration::from_secs(30)).unwrap();
+    assert_eq!(added.unwrap(), 6);
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_errors_carry_offset_and_path() {
+    use std::error::Error;
+    use v8unpack4rs::error::V8Error;
+    use v8unpack4rs::view::ContainerView;
+
+    let dir = TempDir::new("test_errors").unwrap();
+    let view = ContainerView::new(TEST_FILE1).unwrap();
+    let header_addr = view.get_elems_addrs()[0].elem_header_addr as usize;
+
+    let mut damaged = TEST_FILE1.to_vec();
+    damaged[header_addr + 2] = b'Z';
+    let damaged_view = ContainerView::new(&damaged).unwrap();
+    match damaged_view.elem(0) {
+        Err(V8Error::BadBlockHeader { offset, .. }) => {
+            assert_eq!(offset, header_addr as u64)
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    // The data block of the first element claims more bytes than the file has.
+    let truncated = dir.path().join("truncated.cf");
+    let data_addr = view.get_elems_addrs()[0].elem_data_addr as usize;
+    let mut damaged = TEST_FILE1.to_vec();
+    damaged[data_addr + 2..data_addr + 10].copy_from_slice(b"00100000");
+    damaged[data_addr + 11..data_addr + 19].copy_from_slice(b"00100000");
+    File::create(&truncated)
+        .unwrap()
+        .write_all(&damaged)
+        .unwrap();
+
+    let err = parser::unpack_to_directory_with_options(
+        truncated.to_str().unwrap(),
+        dir.path().join("unpack").to_str().unwrap(),
+        &parser::UnpackOptions::new(),
+    )
+    .unwrap_err();
+
+    let name = view.elem(0).unwrap().get_name().unwrap();
+    match err {
+        V8Error::TruncatedBlock {
+            offset,
+            ref path,
+            container: None,
+        } => {
+            assert_eq!(offset, data_addr as u64);
+            assert_eq!(path, &name);
+        }
+        ref other => panic!("unexpected error {:?}", other),
+    }
+    assert_eq!(err.offset(), Some(data_addr as u64));
+    assert!(err.to_string().contains(&name));
+    assert!(err.source().is_none());
+
+    let not_v8file = dir.path().join("not_v8file.cf");
+    File::create(&not_v8file)
+        .unwrap()
+        .write_all(&[b'x'; 64])
+        .unwrap();
+    let err = parser::parse_to_folder_with_options(
+        not_v8file.to_str().unwrap(),
+        dir.path().join("parse").to_str().unwrap(),
+        &parser::UnpackOptions::new(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, V8Error::NotV8File { offset: 0 }));
+}
+
+#[test]
+fn test_nested_container_errors() {
+    use v8unpack4rs::error::V8Error;
+    use v8unpack4rs::view::ContainerView;
+
+    let dir = TempDir::new("test_nested_errors").unwrap();
+    let src = dir.path().join("src");
+    std::fs::create_dir_all(src.join("sub")).unwrap();
+    File::create(src.join("sub").join("inner"))
+        .unwrap()
+        .write_all(&[b'a'; 40000])
+        .unwrap();
+    File::create(src.join("top"))
+        .unwrap()
+        .write_all(b"top")
+        .unwrap();
+
+    let unpack = |data: &[u8], name: &str| {
+        let file_name = dir.path().join(name);
+        File::create(&file_name).unwrap().write_all(data).unwrap();
+        let file_name = file_name.to_str().unwrap();
+        let out = dir.path().join(format!("{}.unpack", name));
+        let single = parser::unpack_to_directory_with_options(
+            file_name,
+            out.to_str().unwrap(),
+            &parser::UnpackOptions::new(),
+        )
+        .unwrap_err();
+        let out = dir.path().join(format!("{}.parse", name));
+        let multi = parser::parse_to_folder_with_options(
+            file_name,
+            out.to_str().unwrap(),
+            &parser::UnpackOptions::new(),
+        )
+        .unwrap_err();
+        vec![single, multi]
+    };
+
+    // The header of the element of the nested container is damaged, its
+    // offset is counted from the beginning of the nested container.
+    let plain = dir.path().join("plain.cf");
+    builder::build_cf_file(src.to_str().unwrap(), plain.to_str().unwrap(), true)
+        .unwrap();
+    let plain = std::fs::read(&plain).unwrap();
+    let view = ContainerView::new(&plain).unwrap();
+    let sub = view
+        .iter()
+        .map(Result::unwrap)
+        .find(|elem| elem.get_name().unwrap() == "sub")
+        .unwrap();
+    let sub_data = sub.get_data().unwrap();
+    let sub_start = sub_data.as_ptr() as usize - plain.as_ptr() as usize;
+    let inner_addr = ContainerView::new(sub_data).unwrap().get_elems_addrs()[0]
+        .elem_header_addr as usize;
+    let mut damaged = plain.clone();
+    damaged[sub_start + inner_addr + 2] = b'Z';
+    for err in unpack(&damaged, "damaged_header.cf") {
+        match err {
+            V8Error::BadBlockHeader {
+                offset,
+                ref path,
+                container: Some(ref container),
+            } => {
+                assert_eq!(offset, inner_addr as u64);
+                assert_eq!(path, "sub");
+                assert_eq!(container, "sub");
+            }
+            ref other => panic!("unexpected error {:?}", other),
+        }
+        assert_eq!(err.container(), Some("sub"));
+        assert!(err.to_string().contains("nested container \"sub\""));
+    }
+
+    // The deflated data of an element breaks off after the beginning of a
+    // nested container: a stored block with the container, then a block of
+    // the reserved type. The container is larger than the decoder gives out
+    // at once, so its beginning is inflated before the error.
+    let mut stream = vec![0];
+    stream.extend_from_slice(&(sub_data.len() as u16).to_le_bytes());
+    stream.extend_from_slice(&(!(sub_data.len() as u16)).to_le_bytes());
+    stream.extend_from_slice(sub_data);
+    stream.push(0x07);
+    File::create(src.join("broken"))
+        .unwrap()
+        .write_all(&stream)
+        .unwrap();
+    let packed = dir.path().join("broken.cf");
+    builder::build_cf_file(src.to_str().unwrap(), packed.to_str().unwrap(), true)
+        .unwrap();
+    let packed = std::fs::read(&packed).unwrap();
+    let view = ContainerView::new(&packed).unwrap();
+    let index = (0..view.get_elems_addrs().len())
+        .find(|&index| view.elem(index).unwrap().get_name().unwrap() == "broken")
+        .unwrap();
+    let data_addr = u64::from(view.get_elems_addrs()[index].elem_data_addr);
+    for err in unpack(&packed, "broken_stream.cf") {
+        match err {
+            V8Error::InflateFailed {
+                offset,
+                ref path,
+                container: None,
+                ..
+            } => {
+                assert_eq!(offset, data_addr);
+                assert_eq!(path, "broken");
+            }
+            ref other => panic!("unexpected error {:?}", other),
+        }
+    }
+    let listed = parser::list_elems(&packed, None).unwrap_err();
+    assert!(matches!(listed, V8Error::InflateFailed { .. }));
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_damaged_input_does_not_panic() {
+    use v8unpack4rs::view::ContainerView;
+
+    let dir = TempDir::new("test_damaged").unwrap();
+    let damaged = dir.path().join("damaged.cf");
+
+    for len in (0..TEST_FILE1.len()).step_by(97) {
+        let mut data = TEST_FILE1[..len].to_vec();
+        if len > 64 {
+            data[len / 2] ^= 0xff;
+        }
+
+        if let Ok(view) = ContainerView::new(&data) {
+            for elem in view.iter() {
+                let _ = elem.and_then(|elem| elem.get_name());
+            }
+        }
+
+        File::create(&damaged).unwrap().write_all(&data).unwrap();
+        let damaged = damaged.to_str().unwrap();
+        let out = dir.path().join(format!("out{}", len));
+        let out = out.to_str().unwrap();
+        let _ = parser::unpack_to_directory_no_load(damaged, out, true, true);
+        let _ = parser::parse_to_folder(damaged, out, true);
+        let _ = parser::unpack_to_folder(damaged, out);
+        let _ = parser::unpack_pipeline(damaged, out);
+    }
+}
+
+#[test]
+fn test_pack_over_4gib_fails() {
+    use v8unpack4rs::error::V8Error;
+
+    let dir = TempDir::new("test_pack_limit").unwrap();
+    let src = dir.path().join("src");
+    std::fs::create_dir(&src).unwrap();
+    File::create(src.join("FileHeader"))
+        .unwrap()
+        .write_all(&TEST_FILE1[..16])
+        .unwrap();
+    File::create(src.join("big.header"))
+        .unwrap()
+        .write_all(&[0; 24])
+        .unwrap();
+    // A sparse file, nothing is read before the layout is computed.
+    File::create(src.join("big.data"))
+        .unwrap()
+        .set_len(1 << 32)
+        .unwrap();
+
+    let out = dir.path().join("big.cf");
+    let err = builder::pack_from_folder(src.to_str().unwrap(), out.to_str().unwrap())
+        .unwrap_err();
+    match err {
+        V8Error::LimitExceeded {
+            ref path, value, ..
+        } => {
+            assert_eq!(path, "big");
+            assert!(value >= 1 << 32);
+        }
+        ref other => panic!("unexpected error {:?}", other),
+    }
+    assert!(err.to_string().contains("64-bit"));
+}
+
+#[test]
+fn test_extract_elem() {
+    use v8unpack4rs::view::ContainerView;
+
+    let dir = TempDir::new("test_extract").unwrap();
+    let test1 = dir.path().join("test1.cf");
+    File::create(&test1).unwrap().write_all(TEST_FILE1).unwrap();
+    let unpack = dir.path().join("unpack");
+    parser::unpack_to_directory_no_load(
+        test1.to_str().unwrap(),
+        unpack.to_str().unwrap(),
+        true,
+        true,
+    )
+    .unwrap();
+
+    let view = ContainerView::new(TEST_FILE1).unwrap();
+    for elem in view.iter() {
+        let name = elem.unwrap().get_name().unwrap();
+        let on_disk = unpack.join(&name);
+        if on_disk.is_dir() {
+            let nested = parser::find_elem(TEST_FILE1, &name).unwrap().unwrap();
+            assert!(ContainerView::is_v8file(&nested));
+
+            for entry in std::fs::read_dir(&on_disk).unwrap() {
+                let entry = entry.unwrap();
+                let elem_path =
+                    format!("{}/{}", name, entry.file_name().to_str().unwrap());
+                let data = parser::find_elem(TEST_FILE1, &elem_path).unwrap();
+                assert_eq!(data.unwrap(), std::fs::read(entry.path()).unwrap());
+            }
+        } else {
+            let data = parser::find_elem(TEST_FILE1, &name).unwrap();
+            assert_eq!(data.unwrap(), std::fs::read(&on_disk).unwrap());
+        }
+    }
+
+    assert!(parser::find_elem(TEST_FILE1, "no/such/element")
+        .unwrap()
+        .is_none());
+
+    let out = dir.path().join("version");
+    assert!(parser::extract_to_path(
+        test1.to_str().unwrap(),
+        "version",
+        out.to_str().unwrap()
+    )
+    .unwrap());
+    assert_eq!(
+        std::fs::read(out).unwrap(),
+        std::fs::read(unpack.join("version")).unwrap()
+    );
+
+    for elem in parser::list_elems(TEST_FILE1, None).unwrap() {
+        let mut data = vec![];
+        assert!(parser::extract_to_writer(
+            test1.to_str().unwrap(),
+            &elem.path,
+            &mut data
+        )
+        .unwrap());
+        assert_eq!(
+            Some(data),
+            parser::find_elem(TEST_FILE1, &elem.path).unwrap(),
+            "{}",
+            elem.path
+        );
+    }
+    assert!(!parser::extract_to_writer(
+        test1.to_str().unwrap(),
+        "no/such/element",
+        &mut vec![]
+    )
+    .unwrap());
+}
+
+#[test]
+fn test_list_elems() {
+    let dir = TempDir::new("test_list").unwrap();
+    let test1 = dir.path().join("test1.cf");
+    File::create(&test1).unwrap().write_all(TEST_FILE1).unwrap();
+    let unpack = dir.path().join("unpack");
+    parser::unpack_to_directory_no_load(
+        test1.to_str().unwrap(),
+        unpack.to_str().unwrap(),
+        true,
+        true,
+    )
+    .unwrap();
+
+    let root = parser::list_elems(TEST_FILE1, Some(0)).unwrap();
+    assert!(!root.is_empty());
+    assert!(root.iter().all(|elem| elem.depth == 0));
+
+    let all = parser::list_file(test1.to_str().unwrap(), None).unwrap();
+    assert!(all.len() > root.len());
+    assert_eq!(all, parser::list_elems(TEST_FILE1, None).unwrap());
+    assert_eq!(
+        parser::list_file(test1.to_str().unwrap(), Some(0)).unwrap(),
+        root
+    );
+    for elem in all.iter() {
+        let on_disk = unpack.join(&elem.path);
+        assert_eq!(elem.v8file, on_disk.is_dir(), "{}", elem.path);
+        if !elem.v8file {
+            let len = std::fs::metadata(&on_disk).unwrap().len();
+            assert_eq!(elem.inflated_size, len, "{}", elem.path);
+        }
+        assert_eq!(elem.depth, elem.path.matches('/').count());
+    }
+}
+
+#[test]
+fn test_container_info() {
+    let info = parser::container_info(TEST_FILE1).unwrap();
+    let root = parser::list_elems(TEST_FILE1, Some(0)).unwrap();
+
+    assert_eq!(info.format, parser::ContainerFormat::Format32);
+    assert_eq!(info.file_size, TEST_FILE1.len() as u64);
+    assert_eq!(info.elem_count, Some(root.len()));
+    assert_eq!(info.toc_size % 12, 0);
+    assert!(info.toc_size >= 12 * root.len() as u64);
+    assert_eq!(info.platform_version.as_deref(), Some("216.0"));
+
+    assert!(parser::container_info(b"not a container").is_err());
+
+    let dir = TempDir::new("test_container_info").unwrap();
+    let test1 = dir.path().join("test1.cf");
+    std::fs::write(&test1, TEST_FILE1).unwrap();
+    assert_eq!(parser::file_info(test1.to_str().unwrap()).unwrap(), info);
+
+    let mut data_64 = vec![0u8; 20];
+    data_64[8..12].copy_from_slice(&512u32.to_le_bytes());
+    data_64.extend_from_slice(
+        b"\r\n0000000000000018 0000000000000200 000000007fffffff \r\n",
+    );
+    data_64.resize(1024, 0);
+    let file_64 = dir.path().join("test64.cf");
+    std::fs::write(&file_64, &data_64).unwrap();
+    let info_64 = parser::file_info(file_64.to_str().unwrap()).unwrap();
+    assert_eq!(info_64, parser::container_info(&data_64).unwrap());
+    assert_eq!(info_64.format, parser::ContainerFormat::Format64);
+    assert_eq!((info_64.toc_size, info_64.page_size), (0x18, 512));
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_batch_parse_and_build() {
+    let dir = TempDir::new("test_batch").unwrap();
+    let input = dir.path().join("in");
+    std::fs::create_dir_all(input.join("reports")).unwrap();
+    std::fs::write(input.join("reports").join("a.epf"), TEST_FILE1).unwrap();
+    std::fs::write(input.join("b.cf"), TEST_FILE1).unwrap();
+    std::fs::write(input.join("broken.erf"), b"not a container").unwrap();
+    std::fs::write(input.join("readme.txt"), b"skipped").unwrap();
+
+    let inputs =
+        batch::collect_inputs(input.to_str().unwrap(), BatchCommand::Parse).unwrap();
+    assert_eq!(inputs.len(), 3);
+
+    let parsed = dir.path().join("parsed");
+    let options = BatchOptions::new(BatchCommand::Parse).with_jobs(2);
+    let report = batch::run_batch(&inputs, parsed.to_str().unwrap(), &options);
+    assert_eq!(report.succeeded(), 2);
+    assert_eq!(report.failed(), 1);
+    assert!(report.items[1].error.is_some());
+    assert!(parsed.join("reports").join("a.epf").is_dir());
+    assert!(!parsed.join("broken.erf").exists());
+
+    let pattern = format!("{}/**/*.epf", parsed.to_str().unwrap());
+    let inputs = batch::collect_inputs(&pattern, BatchCommand::Build).unwrap();
+    assert_eq!(inputs.len(), 1);
+    assert_eq!(inputs[0].relative, std::path::Path::new("reports/a.epf"));
+
+    let built = dir.path().join("built");
+    let options = BatchOptions::new(BatchCommand::Build);
+    let report = batch::run_batch(&inputs, built.to_str().unwrap(), &options);
+    assert_eq!(report.failed(), 0);
+    let data = std::fs::read(built.join("reports").join("a.epf")).unwrap();
+    assert_eq!(parser::list_elems(&data, None).unwrap().len(), 24);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_batch_skips_non_utf8_paths() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = TempDir::new("test_batch_non_utf8").unwrap();
+    let input = dir.path().join("in");
+    std::fs::create_dir(&input).unwrap();
+    std::fs::write(input.join("a.cf"), TEST_FILE1).unwrap();
+    let bad_name = OsStr::from_bytes(b"b\xff.cf");
+    std::fs::write(input.join(bad_name), TEST_FILE1).unwrap();
+
+    let inputs =
+        batch::collect_inputs(input.to_str().unwrap(), BatchCommand::Parse).unwrap();
+    assert_eq!(inputs.len(), 2);
+
+    let parsed = dir.path().join("parsed");
+    let options = BatchOptions::new(BatchCommand::Parse);
+    let report = batch::run_batch(&inputs, parsed.to_str().unwrap(), &options);
+    assert_eq!(report.succeeded(), 1);
+    assert_eq!(report.failed(), 1);
+    let failed = report
+        .items
+        .iter()
+        .find(|item| item.error.is_some())
+        .unwrap();
+    assert_eq!(failed.input, input.join(bad_name));
+    let message = failed.error.as_ref().unwrap().to_string();
+    assert!(message.contains("not valid UTF-8"), "{}", message);
+    assert!(!parsed.join(bad_name).exists());
+    assert!(!parsed.join("b\u{fffd}.cf").exists());
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_filters() {
+    let dir = TempDir::new("test_filters").unwrap();
+    let test1 = dir.path().join("test1.cf");
+    std::fs::write(&test1, TEST_FILE1).unwrap();
+    let all = parser::list_elems(TEST_FILE1, None).unwrap();
+    let texts: Vec<_> = all
+        .iter()
+        .filter(|elem| elem.path.ends_with("/text"))
+        .map(|elem| elem.path.clone())
+        .collect();
+    assert!(!texts.is_empty());
+
+    let only_texts = parser::UnpackOptions::new()
+        .with_filter(ElemFilter::new().with_include("*/text"));
+    for (name, single_threaded) in [("single", true), ("multi", false)] {
+        let unpack = dir.path().join(name);
+        let (test1, unpack_str) = (test1.to_str().unwrap(), unpack.to_str().unwrap());
+        let report = if single_threaded {
+            parser::unpack_to_directory_with_options(test1, unpack_str, &only_texts)
+        } else {
+            parser::parse_to_folder_with_options(test1, unpack_str, &only_texts)
+        }
+        .unwrap();
+        assert_eq!(report.added, texts.len());
+        for path in texts.iter() {
+            assert!(unpack.join(path).is_file(), "{}", path);
+        }
+    }
+
+    let source = dir.path().join("source");
+    parser::parse_to_folder(test1.to_str().unwrap(), source.to_str().unwrap(), true)
+        .unwrap();
+    let built = dir.path().join("built.cf");
+    let options = builder::BuildOptions::new().with_filter(
+        ElemFilter::new()
+            .with_exclude("*/text")
+            .with_exclude("version"),
+    );
+    builder::build_cf_file_with_options(
+        source.to_str().unwrap(),
+        built.to_str().unwrap(),
+        &options,
+    )
+    .unwrap();
+    let rebuilt = parser::list_elems(&std::fs::read(&built).unwrap(), None).unwrap();
+    assert_eq!(rebuilt.len(), all.len() - texts.len() - 1);
+    assert!(rebuilt
+        .iter()
+        .all(|elem| elem.path != "version" && !elem.path.ends_with("/text")));
+}
+
+#[derive(Debug, Default)]
+struct CountingProgress {
+    total: std::sync::Mutex<Option<usize>>,
+    finished: std::sync::atomic::AtomicUsize,
+    written: std::sync::atomic::AtomicU64,
+}
+
+impl v8unpack4rs::progress::Progress for CountingProgress {
+    fn begin(&self, total_elems: Option<usize>, _total_bytes: Option<u64>) {
+        *self.total.lock().unwrap() = total_elems;
+    }
+
+    fn elem_finished(&self, _name: &str) {
+        self.finished
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    fn bytes_written(&self, bytes: u64) {
+        self.written
+            .fetch_add(bytes, std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+extern "C" fn count_elems(
+    user_data: *mut std::os::raw::c_void,
+    done: usize,
+    total: usize,
+    _written: u64,
+) {
+    let calls = unsafe { &mut *(user_data as *mut Vec<(usize, usize)>) };
+    calls.push((done, total));
+}
+
+#[test]
+fn test_progress() {
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    let dir = TempDir::new("test_progress").unwrap();
+    let test1 = dir.path().join("test1.cf");
+    std::fs::write(&test1, TEST_FILE1).unwrap();
+    let root = parser::list_elems(TEST_FILE1, Some(0)).unwrap();
+
+    let progress = Arc::new(CountingProgress::default());
+    let options = parser::UnpackOptions::new().with_progress(progress.clone());
+    let unpack = dir.path().join("unpack");
+    parser::parse_to_folder_with_options(
+        test1.to_str().unwrap(),
+        unpack.to_str().unwrap(),
+        &options,
+    )
+    .unwrap();
+    assert_eq!(*progress.total.lock().unwrap(), Some(root.len()));
+    assert_eq!(progress.finished.load(Ordering::SeqCst), root.len());
+    assert!(progress.written.load(Ordering::SeqCst) > 0);
+
+    let progress = Arc::new(CountingProgress::default());
+    let options = builder::BuildOptions::new().with_progress(progress.clone());
+    let built = dir.path().join("built.cf");
+    builder::build_cf_file_with_options(
+        unpack.to_str().unwrap(),
+        built.to_str().unwrap(),
+        &options,
+    )
+    .unwrap();
+    assert_eq!(progress.finished.load(Ordering::SeqCst), root.len());
+    assert!(progress.written.load(Ordering::SeqCst) > 0);
+
+    let file_name = std::ffi::CString::new(test1.to_str().unwrap()).unwrap();
+    let dir_name =
+        std::ffi::CString::new(dir.path().join("ffi").to_str().unwrap()).unwrap();
+    let mut calls: Vec<(usize, usize)> = vec![];
+    let parsed = unsafe {
+        v8unpack4rs::parse_cf_with_progress(
+            file_name.as_ptr(),
+            dir_name.as_ptr(),
+            Some(count_elems),
+            &mut calls as *mut Vec<(usize, usize)> as *mut std::os::raw::c_void,
+        )
+    };
+    assert!(parsed);
+    assert_eq!(calls.first(), Some(&(0, root.len())));
+    assert_eq!(calls.last(), Some(&(root.len(), root.len())));
+}
+
+/// Cancels the operation once the first element is finished.
+#[derive(Debug)]
+struct CancelAfterFirst(v8unpack4rs::cancel::CancellationToken);
+
+impl v8unpack4rs::progress::Progress for CancelAfterFirst {
+    fn elem_finished(&self, _name: &str) {
+        self.0.cancel();
+    }
+}
+
+#[test]
+fn test_cancellation() {
+    use std::sync::Arc;
+    use v8unpack4rs::cancel::CancellationToken;
+    use v8unpack4rs::error::V8Error;
+
+    let dir = TempDir::new("test_cancellation").unwrap();
+    let test1 = dir.path().join("test1.cf");
+    std::fs::write(&test1, TEST_FILE1).unwrap();
+    let root = parser::list_elems(TEST_FILE1, Some(0)).unwrap();
+
+    let token = CancellationToken::new();
+    let options = parser::UnpackOptions::new()
+        .with_progress(Arc::new(CancelAfterFirst(token.clone())))
+        .with_cancellation(token);
+    let unpack = dir.path().join("unpack");
+    let result = parser::unpack_to_directory_with_options(
+        test1.to_str().unwrap(),
+        unpack.to_str().unwrap(),
+        &options,
+    );
+    assert!(matches!(result, Err(V8Error::Cancelled)));
+    assert_eq!(std::fs::read_dir(&unpack).unwrap().count(), 1);
+
+    let token = CancellationToken::new();
+    token.cancel();
+    let options = parser::UnpackOptions::new().with_cancellation(token);
+    let result = parser::parse_to_folder_with_options(
+        test1.to_str().unwrap(),
+        dir.path().join("multi").to_str().unwrap(),
+        &options,
+    );
+    assert!(matches!(result, Err(V8Error::Cancelled)));
+
+    let full = dir.path().join("full");
+    parser::parse_to_folder(test1.to_str().unwrap(), full.to_str().unwrap(), true)
+        .unwrap();
+    assert_eq!(std::fs::read_dir(&full).unwrap().count(), root.len());
+
+    let token = CancellationToken::new();
+    let options = builder::BuildOptions::new()
+        .with_threads(1)
+        .with_progress(Arc::new(CancelAfterFirst(token.clone())))
+        .with_cancellation(token);
+    let built = dir.path().join("built.cf");
+    let result = builder::build_cf_file_with_options(
+        full.to_str().unwrap(),
+        built.to_str().unwrap(),
+        &options,
+    );
+    assert!(matches!(result, Err(V8Error::Cancelled)));
+    assert!(!built.exists());
+}
+
+#[test]
+fn test_streams() {
+    let dir = TempDir::new("test_streams").unwrap();
+    let root = parser::list_elems(TEST_FILE1, Some(0)).unwrap();
+
+    let unpack = dir.path().join("unpack");
+    let mut src = std::io::Cursor::new(TEST_FILE1);
+    parser::unpack_from_reader(
+        &mut src,
+        unpack.to_str().unwrap(),
+        &parser::UnpackOptions::new(),
+    )
+    .unwrap();
+    // The elements and the manifest.
+    assert_eq!(std::fs::read_dir(&unpack).unwrap().count(), root.len() + 1);
+
+    let mut built = vec![];
+    builder::build_cf_to_writer(
+        unpack.to_str().unwrap(),
+        &mut built,
+        &builder::BuildOptions::new(),
+    )
+    .unwrap();
+    assert_eq!(parser::list_elems(&built, None).unwrap().len(), 24);
+
+    let spooled = parser::SpooledInput::from_reader(&mut built.as_slice()).unwrap();
+    assert_eq!(spooled.get_size(), built.len() as u64);
+    let mut version = vec![];
+    assert!(
+        parser::extract_to_writer(&spooled.file_name(), "version", &mut version)
+            .unwrap()
+    );
+    assert_eq!(
+        Some(version),
+        parser::find_elem(TEST_FILE1, "version").unwrap()
+    );
+}
+
+#[test]
+fn test_memory_tree() {
+    use std::sync::Arc;
+    use v8unpack4rs::tree::{MemNode, MemTree};
+
+    let elems = parser::list_elems(TEST_FILE1, None).unwrap();
+    let files = elems.iter().filter(|elem| !elem.v8file).count();
+    let nested = elems.iter().find(|elem| elem.v8file).unwrap();
+
+    let mut tree =
+        parser::unpack_to_tree(TEST_FILE1, &parser::UnpackOptions::new()).unwrap();
+    assert_eq!(
+        tree.len(),
+        parser::list_elems(TEST_FILE1, Some(0)).unwrap().len()
+    );
+    assert_eq!(tree.files().len(), files);
+    assert!(matches!(tree.get(&nested.path), Some(MemNode::Dir(_))));
+    assert_eq!(
+        tree.get_file("version").map(|data| data.to_vec()),
+        parser::find_elem(TEST_FILE1, "version").unwrap()
+    );
+
+    let mut src = std::io::Cursor::new(TEST_FILE1);
+    let file = parser::load_file(&mut src, true).unwrap();
+    assert_eq!(MemTree::from_v8file(&file).unwrap(), tree);
+
+    tree.insert_file("version", b"changed".to_vec());
+    tree.insert_file("new/elem", b"{1,\"added\"}".to_vec());
+    assert!(tree.remove("root").is_some());
+    let built =
+        builder::build_to_vec(Arc::new(tree.clone()), &builder::BuildOptions::new())
+            .unwrap();
+    assert_eq!(
+        parser::find_elem(&built, "version").unwrap(),
+        Some(b"changed".to_vec())
+    );
+    assert_eq!(parser::find_elem(&built, "root").unwrap(), None);
+
+    let rebuilt = parser::unpack_to_tree(&built, &parser::UnpackOptions::new()).unwrap();
+    assert_eq!(rebuilt, tree);
+    assert_eq!(rebuilt.get_file("new/elem"), Some(&b"{1,\"added\"}"[..]));
+
+    let data = tree.to_v8file().unwrap().get_data().unwrap();
+    assert_eq!(
+        parser::list_elems(&data, None).unwrap().len(),
+        parser::list_elems(&built, None).unwrap().len()
+    );
+}
+
+#[test]
+fn test_c_interface() {
+    use std::ffi::{CStr, CString};
+    use std::os::raw::c_void;
+    use std::ptr;
+    use v8unpack4rs::*;
+
+    extern "C" fn count(user_data: *mut c_void, info: *const V8ElemInfo) {
+        let count = unsafe { &mut *(user_data as *mut usize) };
+        assert!(!unsafe { (*info).path }.is_null());
+        *count += 1;
+    }
+
+    let dir = TempDir::new("test_c_interface").unwrap();
+    let c_path =
+        |name: &str| CString::new(dir.path().join(name).to_str().unwrap()).unwrap();
+    let test1 = c_path("test1.cf");
+    std::fs::write(dir.path().join("test1.cf"), TEST_FILE1).unwrap();
+    let (unpack, build) = (c_path("unpack"), c_path("build.cf"));
+
+    unsafe {
+        assert_eq!(v8_parse(test1.as_ptr(), unpack.as_ptr()), V8_OK);
+        assert!(v8_last_error_message().is_null());
+        assert_eq!(v8_build(unpack.as_ptr(), build.as_ptr(), false), V8_OK);
+
+        let mut elems = 0usize;
+        let user_data = &mut elems as *mut usize as *mut c_void;
+        assert_eq!(v8_list(build.as_ptr(), -1, Some(count), user_data), V8_OK);
+        assert_eq!(elems, 24);
+
+        let version = c_path("version");
+        let name = CString::new("version").unwrap();
+        assert_eq!(
+            v8_extract(test1.as_ptr(), name.as_ptr(), version.as_ptr()),
+            V8_OK
+        );
+        let missing = CString::new("missing").unwrap();
+        assert_eq!(
+            v8_extract(test1.as_ptr(), missing.as_ptr(), version.as_ptr()),
+            V8_ERR_NOT_FOUND
+        );
+        let message = CStr::from_ptr(v8_last_error_message()).to_str().unwrap();
+        assert!(message.contains("missing"));
+
+        assert_eq!(
+            v8_parse(ptr::null(), unpack.as_ptr()),
+            V8_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            v8_list(test1.as_ptr(), 0, None, ptr::null_mut()),
+            V8_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            v8_parse(version.as_ptr(), unpack.as_ptr()),
+            V8_ERR_NOT_V8FILE
+        );
+        assert_eq!(
+            v8_parse(c_path("missing.cf").as_ptr(), unpack.as_ptr()),
+            V8_ERR_IO
+        );
+
+        let (packed, binary) = (c_path("packed"), c_path("packed.cf"));
+        assert_eq!(
+            v8_unpack(version.as_ptr(), packed.as_ptr()),
+            V8_ERR_NOT_V8FILE
+        );
+        assert_eq!(v8_unpack(test1.as_ptr(), packed.as_ptr()), V8_OK);
+        assert_eq!(v8_pack(packed.as_ptr(), binary.as_ptr()), V8_OK);
+        let packed = std::fs::read(dir.path().join("packed.cf")).unwrap();
+        assert_eq!(parser::list_elems(&packed, None).unwrap().len(), 24);
+    }
+}
+
+#[test]
+fn test_parse_cf_status() {
+    use std::ffi::{CStr, CString};
+    use std::ptr;
+    use v8unpack4rs::view::ContainerView;
+    use v8unpack4rs::*;
+
+    let dir = TempDir::new("test_parse_cf_status").unwrap();
+    let c_path = |name: &str| {
+        let path = dir.path().join(name);
+        CString::new(path.to_str().unwrap()).unwrap()
+    };
+    let unpack = c_path("unpack");
+    let test1 = c_path("test1.cf");
+    std::fs::write(dir.path().join("test1.cf"), TEST_FILE1).unwrap();
+    std::fs::write(dir.path().join("text.cf"), b"not a container").unwrap();
+
+    let view = ContainerView::new(TEST_FILE1).unwrap();
+    let header_addr = view.get_elems_addrs()[0].elem_header_addr as usize;
+    let mut damaged = TEST_FILE1.to_vec();
+    damaged[header_addr + 2] = b'Z';
+    std::fs::write(dir.path().join("damaged.cf"), damaged).unwrap();
+
+    let last_error = || unsafe {
+        let message = v8_last_error_message();
+        assert!(!message.is_null());
+        CStr::from_ptr(message).to_string_lossy().into_owned()
+    };
+
+    let parse = |file_name: *const std::os::raw::c_char,
+                 dir_name: *const std::os::raw::c_char| unsafe {
+        v8_parse_with_progress(file_name, dir_name, None, ptr::null_mut())
+    };
+
+    assert_eq!(parse(test1.as_ptr(), unpack.as_ptr()), V8_OK);
+    assert!(v8_last_error_message().is_null());
+
+    assert_eq!(parse(ptr::null(), unpack.as_ptr()), V8_ERR_INVALID_ARGUMENT);
+    assert!(last_error().contains("file_name"));
+    assert_eq!(parse(test1.as_ptr(), ptr::null()), V8_ERR_INVALID_ARGUMENT);
+    let not_utf8 = [0xffu8, 0xfe, 0];
+    assert_eq!(
+        parse(not_utf8.as_ptr() as *const _, unpack.as_ptr()),
+        V8_ERR_INVALID_ARGUMENT
+    );
+    assert!(last_error().contains("UTF-8"));
+
+    assert_eq!(
+        parse(c_path("missing.cf").as_ptr(), unpack.as_ptr()),
+        V8_ERR_IO
+    );
+    assert_eq!(
+        parse(c_path("text.cf").as_ptr(), unpack.as_ptr()),
+        V8_ERR_NOT_V8FILE
+    );
+    assert_eq!(
+        parse(c_path("damaged.cf").as_ptr(), unpack.as_ptr()),
+        V8_ERR_BAD_FORMAT
+    );
+    assert!(!last_error().is_empty());
+
+    // `parse_cf` keeps returning `bool`, the error is still recorded.
+    unsafe {
+        assert!(parse_cf(test1.as_ptr(), unpack.as_ptr()));
+        assert!(v8_last_error_message().is_null());
+
+        assert!(!parse_cf(ptr::null(), unpack.as_ptr()));
+        assert!(last_error().contains("pfile_name"));
+        assert!(!parse_cf(not_utf8.as_ptr() as *const _, unpack.as_ptr()));
+        assert!(!parse_cf(c_path("text.cf").as_ptr(), unpack.as_ptr()));
+        assert!(!last_error().is_empty());
+
+        assert!(!parse_cf_with_progress(
+            c_path("damaged.cf").as_ptr(),
+            unpack.as_ptr(),
+            None,
+            ptr::null_mut()
+        ));
+        assert!(!last_error().is_empty());
+    }
+}
+
+#[test]
+fn test_c_handles() {
+    use std::ffi::{CStr, CString};
+    use std::ptr;
+    use v8unpack4rs::*;
+
+    unsafe fn read(handle: *const V8Handle, index: usize) -> Vec<u8> {
+        let mut buffer = V8Buffer {
+            data: ptr::null_mut(),
+            len: 0,
+        };
+        assert_eq!(v8_elem_read(handle, index, &mut buffer), V8_OK);
+        let data = std::slice::from_raw_parts(buffer.data, buffer.len).to_vec();
+        v8_buffer_free(buffer);
+        data
+    }
+
+    let dir = TempDir::new("test_c_handles").unwrap();
+    let test1 = dir.path().join("test1.cf");
+    std::fs::write(&test1, TEST_FILE1).unwrap();
+    let test1 = CString::new(test1.to_str().unwrap()).unwrap();
+    let elems = parser::list_elems(TEST_FILE1, Some(1)).unwrap();
+    let root: Vec<_> = elems.iter().filter(|elem| elem.depth == 0).collect();
+
+    unsafe {
+        let mut handle = ptr::null_mut();
+        assert_eq!(v8_open(test1.as_ptr(), &mut handle), V8_OK);
+        let mut memory = ptr::null_mut();
+        assert_eq!(
+            v8_open_memory(TEST_FILE1.as_ptr(), TEST_FILE1.len(), &mut memory),
+            V8_OK
+        );
+        assert_eq!(v8_elem_count(handle), root.len());
+        assert_eq!(v8_elem_count(memory), root.len());
+
+        for (index, elem) in root.iter().enumerate() {
+            let name = CStr::from_ptr(v8_elem_name(handle, index));
+            assert_eq!(name.to_str().unwrap(), elem.path);
+            let data = read(memory, index);
+            if !elem.v8file {
+                assert_eq!(
+                    Some(data),
+                    parser::find_elem(TEST_FILE1, &elem.path).unwrap()
+                );
+                let mut nested = ptr::null_mut();
+                assert_eq!(v8_elem_open(handle, index, &mut nested), V8_ERR_NOT_V8FILE);
+                assert!(nested.is_null());
+                continue;
+            }
+
+            let mut nested = ptr::null_mut();
+            assert_eq!(v8_elem_open(handle, index, &mut nested), V8_OK);
+            let prefix = format!("{}/", elem.path);
+            let children: Vec<_> = elems
+                .iter()
+                .filter(|child| child.depth == 1 && child.path.starts_with(&prefix))
+                .collect();
+            assert_eq!(v8_elem_count(nested), children.len());
+            assert_eq!(
+                Some(read(nested, 0)),
+                parser::find_elem(TEST_FILE1, &children[0].path).unwrap()
+            );
+            v8_close(nested);
+        }
+
+        assert!(v8_elem_name(handle, root.len()).is_null());
+        let mut buffer = V8Buffer {
+            data: ptr::null_mut(),
+            len: 0,
+        };
+        assert_eq!(
+            v8_elem_read(handle, root.len(), &mut buffer),
+            V8_ERR_INVALID_ARGUMENT
+        );
+        assert!(buffer.data.is_null());
+        assert_eq!(
+            v8_elem_read(ptr::null(), 0, &mut buffer),
+            V8_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(v8_elem_count(ptr::null()), 0);
+        v8_close(handle);
+        v8_close(memory);
+        v8_close(ptr::null_mut());
+
+        let mut handle = ptr::null_mut();
+        assert_eq!(
+            v8_open_memory(b"garbage".as_ptr(), 7, &mut handle),
+            V8_ERR_NOT_V8FILE
+        );
+        assert!(handle.is_null());
+        assert_eq!(
+            v8_open_memory(ptr::null(), 0, &mut handle),
+            V8_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            v8_open(test1.as_ptr(), ptr::null_mut()),
+            V8_ERR_INVALID_ARGUMENT
+        );
+    }
+}
+
+#[test]
+fn test_c_memory() {
+    use std::ffi::CString;
+    use std::ptr;
+    use v8unpack4rs::*;
+
+    let dir = TempDir::new("test_c_memory").unwrap();
+    let unpack = CString::new(dir.path().join("unpack").to_str().unwrap()).unwrap();
+    let version = CString::new("version").unwrap();
+    let empty = || V8Buffer {
+        data: ptr::null_mut(),
+        len: 0,
+    };
+
+    unsafe {
+        let (data, len) = (TEST_FILE1.as_ptr(), TEST_FILE1.len());
+        let mut buffer = empty();
+        assert_eq!(
+            v8_extract_memory(data, len, version.as_ptr(), &mut buffer),
+            V8_OK
+        );
+        assert_eq!(
+            Some(std::slice::from_raw_parts(buffer.data, buffer.len).to_vec()),
+            parser::find_elem(TEST_FILE1, "version").unwrap()
+        );
+        v8_buffer_free(buffer);
+
+        let missing = CString::new("missing").unwrap();
+        let mut buffer = empty();
+        assert_eq!(
+            v8_extract_memory(data, len, missing.as_ptr(), &mut buffer),
+            V8_ERR_NOT_FOUND
+        );
+        assert!(buffer.data.is_null());
+
+        assert_eq!(v8_parse_memory(data, len, unpack.as_ptr()), V8_OK);
+        let mut buffer = empty();
+        assert_eq!(v8_build_memory(unpack.as_ptr(), false, &mut buffer), V8_OK);
+        let built = std::slice::from_raw_parts(buffer.data, buffer.len).to_vec();
+        v8_buffer_free(buffer);
+        assert_eq!(parser::list_elems(&built, None).unwrap().len(), 24);
+
+        assert_eq!(
+            v8_parse_memory(b"garbage".as_ptr(), 7, unpack.as_ptr()),
+            V8_ERR_NOT_V8FILE
+        );
+        assert_eq!(
+            v8_extract_memory(ptr::null(), 0, version.as_ptr(), &mut empty()),
+            V8_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            v8_build_memory(unpack.as_ptr(), false, ptr::null_mut()),
+            V8_ERR_INVALID_ARGUMENT
+        );
+    }
+}
+
+#[cfg(feature = "archive")]
+#[test]
+fn test_archives() {
+    use std::io::Cursor;
+    use std::sync::Arc;
+    use v8unpack4rs::archive::{ArchiveFormat, ArchiveSource, TarSink, ZipSink};
+
+    let dir = TempDir::new("test_archives").unwrap();
+    let test1 = dir.path().join("test1.cf");
+    std::fs::write(&test1, TEST_FILE1).unwrap();
+    let test1 = test1.to_str().unwrap();
+    let elems = parser::list_elems(TEST_FILE1, None).unwrap();
+    let options = parser::UnpackOptions::new();
+
+    assert_eq!(
+        ArchiveFormat::from_path("out/test1.ZIP"),
+        Some(ArchiveFormat::Zip)
+    );
+    assert_eq!(ArchiveFormat::from_path("out/test1"), None);
+
+    let mut tar = TarSink::new(vec![]);
+    parser::unpack_to_sink(test1, &mut tar, &options).unwrap();
+    let tar = ArchiveSource::from_tar(tar.into_inner().unwrap().as_slice()).unwrap();
+
+    let mut zip = ZipSink::new(Cursor::new(vec![]));
+    let report = parser::parse_to_sink(test1, &mut zip, &options).unwrap();
+    assert_eq!(
+        report.added,
+        elems.iter().filter(|elem| !elem.v8file).count()
+    );
+    let zip = ArchiveSource::from_zip(zip.into_inner().unwrap()).unwrap();
+
+    let mut from_tar = vec![];
+    builder::build_cf_to_writer_from_source(
+        Arc::new(tar),
+        &mut from_tar,
+        &builder::BuildOptions::new(),
+    )
+    .unwrap();
+    let mut from_zip = vec![];
+    builder::build_cf_to_writer_from_source(
+        Arc::new(zip),
+        &mut from_zip,
+        &builder::BuildOptions::new(),
+    )
+    .unwrap();
+    assert_eq!(from_tar, from_zip);
+
+    let paths = |data: &[u8]| {
+        let mut paths: Vec<_> = parser::list_elems(data, None)
+            .unwrap()
+            .into_iter()
+            .filter(|elem| !elem.v8file)
+            .map(|elem| (elem.path, elem.inflated_size))
+            .collect();
+        paths.sort();
+        paths
+    };
+    assert_eq!(paths(&from_tar), paths(TEST_FILE1));
+}
@@ -0,0 +1,101 @@
+//! Checks the memory used by the multi-threaded parser with a byte budget.
+//! The allocations are counted for the whole process, so the test has a
+//! binary of its own.
+
+extern crate tempdir;
+extern crate v8unpack4rs;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs::File;
+use std::io::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tempdir::TempDir;
+use v8unpack4rs::{builder, parser};
+
+struct CountingAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+fn allocated(bytes: usize) {
+    let current = CURRENT.fetch_add(bytes, Ordering::SeqCst) + bytes;
+    PEAK.fetch_max(current, Ordering::SeqCst);
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            if new_size > layout.size() {
+                allocated(new_size - layout.size());
+            } else {
+                CURRENT.fetch_sub(layout.size() - new_size, Ordering::SeqCst);
+            }
+        }
+        new_ptr
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+#[test]
+fn test_parse_stays_within_memory_budget() {
+    const LIMIT: usize = 1024 * 1024;
+    // Buffers of the stages, like the readers and the inflate window.
+    const OVERHEAD: usize = 256 * 1024;
+
+    let dir = TempDir::new("test_memory_peak").unwrap();
+    let src = dir.path().join("src");
+    std::fs::create_dir(&src).unwrap();
+    let mut seed = 1u32;
+    for i in 0..6 {
+        // Compressed to about a seventh, several elements fit into the
+        // budget before they are inflated.
+        let data: Vec<u8> = (0..3 * LIMIT / 4)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                if seed & 0x10000 == 0 {
+                    b'a'
+                } else {
+                    b'b'
+                }
+            })
+            .collect();
+        File::create(src.join(format!("f{}", i)))
+            .unwrap()
+            .write_all(&data)
+            .unwrap();
+    }
+    let packed = dir.path().join("packed.cf");
+    let packed = packed.to_str().unwrap();
+    assert!(builder::build_cf_file(src.to_str().unwrap(), packed, false).unwrap());
+
+    let out = dir.path().join("out");
+    let options = parser::UnpackOptions::new().with_max_memory(Some(LIMIT as u64));
+    let before = CURRENT.load(Ordering::SeqCst);
+    PEAK.store(before, Ordering::SeqCst);
+    let report =
+        parser::parse_to_folder_with_options(packed, out.to_str().unwrap(), &options)
+            .unwrap();
+    let peak = PEAK.load(Ordering::SeqCst) - before;
+
+    assert_eq!(report.added, 6);
+    assert!(peak < LIMIT + OVERHEAD, "peak of {} bytes", peak);
+
+    dir.close().unwrap();
+}